@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 /// Attestation certificates (DER encoded X.509)
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Certificate(pub Vec<u8>);
 
 #[allow(clippy::len_without_is_empty)]
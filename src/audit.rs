@@ -1,11 +1,16 @@
 //! Auditing options (for use with the `get_option` and `put_option` command)
 
+mod collector;
 pub(crate) mod commands;
 mod error;
+pub mod export;
+mod verify;
 
 pub use self::{
-    commands::{LogDigest, LogEntries, LogEntry},
+    collector::{read_log_file, Collected, Collector, DEFAULT_POLL_INTERVAL},
+    commands::{AuditResponseCode, LogDigest, LogEntries, LogEntry},
     error::{Error, ErrorKind},
+    verify::verify,
 };
 
 use crate::command;
@@ -0,0 +1,315 @@
+//! Audit log collection: drain the HSM's audit log into an append-only file,
+//! verifying the digest chain as entries are collected.
+//!
+//! Each line of the file is a hex-encoded log entry in the HSM's wire format
+//! (including its digest), so the file can be verified independently of the
+//! collector. Entries are acknowledged with `SetLogIndex` only once they've
+//! been synced to disk, so a crash can't lose entries (although entries
+//! which were stored but not acknowledged are fetched again, and skipped).
+
+use super::{verify::check_chain, Error, ErrorKind, LogEntries, LogEntry};
+use crate::{serialization, Client};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// Default interval between polls of the audit log
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Size of a serialized log entry
+const ENTRY_SIZE: usize = 32;
+
+/// Collects audit log entries from an HSM into an append-only file
+pub struct Collector {
+    /// Client for the HSM whose log is collected
+    client: Client,
+
+    /// Path to the log file
+    path: PathBuf,
+
+    /// Log file, opened for appending
+    file: File,
+
+    /// Most recent entry stored in the log file, which the next entry
+    /// collected has to chain from
+    last_entry: Option<LogEntry>,
+
+    /// Interval between polls when running continuously
+    poll_interval: Duration,
+}
+
+impl Collector {
+    /// Open (or create) the log file at the given path, verifying the
+    /// entries already stored in it and resuming the chain from the last one.
+    ///
+    /// If the file ends with a partially written entry (e.g. after a crash),
+    /// it's discarded: the entry wasn't acknowledged, so it's collected again.
+    pub fn open(client: Client, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| storage_error(&path, e))?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .map_err(|e| storage_error(&path, e))?;
+
+        // Discard a partially written final line
+        let complete_len = contents.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        if complete_len < contents.len() {
+            warn!(
+                "discarding partially written entry at the end of {}",
+                path.display()
+            );
+
+            file.set_len(complete_len as u64)
+                .map_err(|e| storage_error(&path, e))?;
+        }
+
+        let mut last_entry: Option<LogEntry> = None;
+
+        for (i, line) in contents[..complete_len].lines().enumerate() {
+            let entry = decode_entry(line).ok_or_else(|| {
+                format_err!(
+                    ErrorKind::StorageError,
+                    "{}:{}: malformed log entry",
+                    path.display(),
+                    i + 1
+                )
+            })?;
+
+            if let Some(previous) = &last_entry {
+                check_chain(previous, &entry)?;
+            }
+
+            last_entry = Some(entry);
+        }
+
+        Ok(Self {
+            client,
+            path,
+            file,
+            last_entry,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        })
+    }
+
+    /// Set the interval between polls when running continuously
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Path to the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Most recent entry stored in the log file
+    pub fn last_entry(&self) -> Option<&LogEntry> {
+        self.last_entry.as_ref()
+    }
+
+    /// Fetch new log entries from the HSM, verify they chain from the
+    /// entries already collected, append them to the log file and then
+    /// acknowledge them.
+    ///
+    /// If any entry is missing or has an invalid digest, nothing is stored
+    /// or acknowledged and an error is returned. On the first collection
+    /// into an empty file, the first entry is trusted as is.
+    pub fn collect(&mut self) -> Result<Collected, Error> {
+        let log = self
+            .client
+            .get_log_entries()
+            .map_err(|e| format_err!(ErrorKind::DeviceError, "error getting log entries: {}", e))?;
+
+        if log.unlogged_boot_events > 0 || log.unlogged_auth_events > 0 {
+            warn!(
+                "audit log overflowed: {} boot event(s) and {} authentication event(s) weren't logged",
+                log.unlogged_boot_events, log.unlogged_auth_events
+            );
+        }
+
+        let entries = self.new_entries(&log)?;
+
+        if !entries.is_empty() {
+            let mut lines = String::new();
+
+            for entry in &entries {
+                lines.push_str(&encode_entry(entry)?);
+                lines.push('\n');
+            }
+
+            self.file
+                .write_all(lines.as_bytes())
+                .and_then(|()| self.file.sync_data())
+                .map_err(|e| storage_error(&self.path, e))?;
+
+            self.last_entry = entries.last().cloned();
+        }
+
+        // Acknowledge everything up to the last stored entry, including
+        // entries stored previously but never acknowledged
+        if !log.entries.is_empty() {
+            if let Some(last) = &self.last_entry {
+                self.client.set_log_index(last.item).map_err(|e| {
+                    format_err!(
+                        ErrorKind::DeviceError,
+                        "error acknowledging log entries up to {}: {}",
+                        last.item,
+                        e
+                    )
+                })?;
+            }
+        }
+
+        Ok(Collected {
+            entries,
+            unlogged_boot_events: log.unlogged_boot_events,
+            unlogged_auth_events: log.unlogged_auth_events,
+        })
+    }
+
+    /// Collect log entries continuously, polling at the configured interval.
+    ///
+    /// Only returns if collecting fails, e.g. because the chain is broken.
+    pub fn run(&mut self) -> Result<(), Error> {
+        loop {
+            let collected = self.collect()?;
+
+            if !collected.entries.is_empty() {
+                debug!(
+                    "collected {} audit log entries into {}",
+                    collected.entries.len(),
+                    self.path.display()
+                );
+            }
+
+            thread::sleep(self.poll_interval);
+        }
+    }
+
+    /// Select the entries in the log which haven't been stored yet, checking
+    /// they chain from the last stored entry
+    fn new_entries(&self, log: &LogEntries) -> Result<Vec<LogEntry>, Error> {
+        let mut previous = self.last_entry.clone();
+        let mut entries = vec![];
+
+        for entry in &log.entries {
+            if let Some(prev) = &previous {
+                // Entries up to the last stored entry were already stored
+                // (but not acknowledged)
+                if entries.is_empty() && entry.item == prev.item {
+                    ensure!(
+                        entry.digest == prev.digest,
+                        ErrorKind::ChainInvalid,
+                        "log entry {} doesn't match the stored entry",
+                        entry.item
+                    );
+
+                    continue;
+                }
+
+                if entries.is_empty() && is_before(entry.item, prev.item) {
+                    continue;
+                }
+
+                check_chain(prev, entry)?;
+            }
+
+            entries.push(entry.clone());
+            previous = Some(entry.clone());
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Result of a collection from the HSM's log
+#[derive(Clone, Debug)]
+pub struct Collected {
+    /// Entries which were stored
+    pub entries: Vec<LogEntry>,
+
+    /// Number of boot events which weren't logged because the log was full
+    pub unlogged_boot_events: u16,
+
+    /// Number of authentication events which weren't logged because the log
+    /// was full
+    pub unlogged_auth_events: u16,
+}
+
+impl Collected {
+    /// Were any events not logged because the log was full?
+    pub fn has_gaps(&self) -> bool {
+        self.unlogged_boot_events > 0 || self.unlogged_auth_events > 0
+    }
+}
+
+/// Read the entries stored in a log file written by a [`Collector`].
+///
+/// The entries aren't verified: use [`verify`](super::verify()) to check them.
+pub fn read_log_file(path: impl AsRef<Path>) -> Result<Vec<LogEntry>, Error> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|e| storage_error(path, e))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            decode_entry(line).ok_or_else(|| {
+                format_err!(
+                    ErrorKind::StorageError,
+                    "{}:{}: malformed log entry",
+                    path.display(),
+                    i + 1
+                )
+                .into()
+            })
+        })
+        .collect()
+}
+
+/// Is item number `a` before `b` (allowing for wraparound)?
+fn is_before(a: u16, b: u16) -> bool {
+    a != b && b.wrapping_sub(a) < 0x8000
+}
+
+/// Encode a log entry as a line of the log file
+fn encode_entry(entry: &LogEntry) -> Result<String, Error> {
+    let bytes = serialization::serialize(entry)
+        .map_err(|e| format_err!(ErrorKind::StorageError, "error serializing entry: {}", e))?;
+
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Decode a line of the log file
+fn decode_entry(line: &str) -> Option<LogEntry> {
+    let line = line.trim();
+
+    if line.len() != ENTRY_SIZE * 2 || !line.is_ascii() {
+        return None;
+    }
+
+    let bytes = (0..line.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&line[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .ok()?;
+
+    serialization::deserialize(&bytes).ok()
+}
+
+/// Error reading or writing the log file
+fn storage_error(path: &Path, e: std::io::Error) -> Error {
+    format_err!(ErrorKind::StorageError, "{}: {}", path.display(), e).into()
+}
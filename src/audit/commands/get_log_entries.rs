@@ -9,6 +9,7 @@ use crate::{
     serialization::{self, serialize},
 };
 use serde::{ser, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt::{self, Debug};
 
 /// Request parameters for `command::get_log_entries`
@@ -40,7 +41,7 @@ impl Response for LogEntries {
 }
 
 /// Entry in the log response
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct LogEntry {
     /// Entry number
     pub item: u16,
@@ -79,13 +80,33 @@ impl LogEntry {
         out.resize(out.len() - LOG_DIGEST_SIZE, 0);
         Ok(out.into_boxed_slice())
     }
+
+    /// Compute the digest of this log entry, chained to the digest of the
+    /// previous entry.
+    pub fn chained_digest(&self, previous: &LogDigest) -> Result<LogDigest, serialization::Error> {
+        let digest = Sha256::new()
+            .chain_update(self.digest_payload()?)
+            .chain_update(previous)
+            .finalize();
+
+        let mut bytes = [0u8; LOG_DIGEST_SIZE];
+        bytes.copy_from_slice(&digest[..LOG_DIGEST_SIZE]);
+        Ok(LogDigest(bytes))
+    }
+
+    /// Does the digest of this log entry chain to the digest of the previous
+    /// entry?
+    pub fn verify_digest(&self, previous: &LogDigest) -> bool {
+        self.chained_digest(previous)
+            .is_ok_and(|digest| digest == self.digest)
+    }
 }
 
 /// Size of a truncated digest in the log
 pub const LOG_DIGEST_SIZE: usize = 16;
 
 /// Truncated SHA-256 digest of a log entry and the previous log digest
-#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct LogDigest(pub [u8; LOG_DIGEST_SIZE]);
 
 impl AsRef<[u8]> for LogDigest {
@@ -105,6 +126,7 @@ impl Debug for LogDigest {
     }
 }
 
+/// Result of a logged command, as recorded in the audit log
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
 pub struct AuditResponseCode(pub response::Code);
 
@@ -9,10 +9,22 @@ pub type Error = crate::Error<ErrorKind>;
 /// Kinds of audit-related errors
 #[derive(Copy, Clone, Debug, Eq, Error, PartialEq)]
 pub enum ErrorKind {
+    /// Log entries are missing or their digests don't chain together
+    #[error("log chain invalid")]
+    ChainInvalid,
+
+    /// Error getting or acknowledging log entries from the HSM
+    #[error("device error")]
+    DeviceError,
+
     /// Invalid option
     #[error("invalid option")]
     OptionInvalid,
 
+    /// Error reading or writing stored log entries
+    #[error("storage error")]
+    StorageError,
+
     /// Invalid tag
     #[error("invalid tag")]
     TagInvalid,
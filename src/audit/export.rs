@@ -0,0 +1,310 @@
+//! Human-readable export of audit log entries as JSON Lines, CSV or RFC 5424
+//! syslog messages.
+//!
+//! Entries are first decoded into [`Record`]s, which name the command and
+//! result of each entry and resolve key IDs to labels using a snapshot of
+//! the HSM's objects.
+
+use super::{Error, ErrorKind, LogEntry};
+use crate::{device, object, response};
+use serde::Serialize;
+use std::io::Write;
+
+/// Key ID recorded for fields of log entries which don't apply
+const NO_KEY_ID: object::Id = 0xffff;
+
+/// Header line for CSV exports
+pub const CSV_HEADER: &str = "item,tick,command,length,result,session_key_id,session_key_label,\
+target_key_id,target_key_label,second_key_id,second_key_label,digest";
+
+/// Log entry decoded into human-readable form
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Record {
+    /// Entry number
+    pub item: u16,
+
+    /// Tick count of the HSM's internal clock
+    pub tick: u32,
+
+    /// Name of the command (e.g. `sign-ecdsa`)
+    pub command: String,
+
+    /// Length of the command
+    pub length: u16,
+
+    /// Did the command succeed?
+    pub success: bool,
+
+    /// Result of the command (`success`, or a description of the error)
+    pub result: String,
+
+    /// Authentication key of the session the command was sent in
+    pub session_key: Option<KeyRef>,
+
+    /// Key the command operated on
+    pub target_key: Option<KeyRef>,
+
+    /// Second key affected by the command
+    pub second_key: Option<KeyRef>,
+
+    /// Hex-encoded digest of the entry
+    pub digest: String,
+}
+
+impl Record {
+    /// Decode a log entry, resolving key IDs to labels using the given
+    /// objects (e.g. a snapshot of the objects in the HSM).
+    ///
+    /// Objects of different types can share an ID: session keys are resolved
+    /// to authentication keys, and other keys to the first object listed with
+    /// their ID.
+    pub fn decode(entry: &LogEntry, objects: &[object::Info]) -> Self {
+        let result = match entry.result.0 {
+            response::Code::Success(_) => "success".to_owned(),
+            code => device::ErrorKind::from_response_code(code)
+                .map(|kind| kind.to_string())
+                .unwrap_or_else(|| format!("{code:?}")),
+        };
+
+        Self {
+            item: entry.item,
+            tick: entry.tick,
+            command: entry.cmd.to_string(),
+            length: entry.length,
+            success: entry.result.0.is_success(),
+            result,
+            session_key: KeyRef::resolve(
+                entry.session_key,
+                Some(object::Type::AuthenticationKey),
+                objects,
+            ),
+            target_key: KeyRef::resolve(entry.target_key, None, objects),
+            second_key: KeyRef::resolve(entry.second_key, None, objects),
+            digest: entry
+                .digest
+                .0
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        }
+    }
+
+    /// Serialize this record as JSON
+    #[cfg(feature = "serde_json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Serialize this record as a CSV row (see [`CSV_HEADER`])
+    pub fn to_csv(&self) -> String {
+        let mut fields = vec![
+            self.item.to_string(),
+            self.tick.to_string(),
+            self.command.clone(),
+            self.length.to_string(),
+            csv_escape(&self.result),
+        ];
+
+        for key in [&self.session_key, &self.target_key, &self.second_key] {
+            match key {
+                Some(key) => {
+                    fields.push(format!("0x{:04x}", key.id));
+                    fields.push(csv_escape(key.label.as_deref().unwrap_or_default()));
+                }
+                None => fields.extend([String::new(), String::new()]),
+            }
+        }
+
+        fields.push(self.digest.clone());
+        fields.join(",")
+    }
+
+    /// Serialize this record as an RFC 5424 syslog message.
+    ///
+    /// The HSM's clock only counts ticks since it booted, so messages have
+    /// no timestamp: the tick count is included in the structured data.
+    pub fn to_syslog(&self, options: &SyslogOptions) -> String {
+        // Failed commands are warnings, others informational
+        let severity = if self.success { 6 } else { 4 };
+
+        let mut params = vec![
+            ("item", self.item.to_string()),
+            ("tick", self.tick.to_string()),
+            ("command", self.command.clone()),
+            ("result", self.result.clone()),
+        ];
+
+        for (name, key) in [
+            ("session_key", &self.session_key),
+            ("target_key", &self.target_key),
+            ("second_key", &self.second_key),
+        ] {
+            if let Some(key) = key {
+                params.push((name, format!("0x{:04x}", key.id)));
+
+                if let Some(label) = &key.label {
+                    params.push((label_param(name), label.clone()));
+                }
+            }
+        }
+
+        params.push(("digest", self.digest.clone()));
+
+        let structured_data: String = params
+            .iter()
+            .map(|(name, value)| format!(" {name}=\"{}\"", sd_escape(value)))
+            .collect();
+
+        format!(
+            "<{}>1 - {} {} - {} [{}{}] {}: {}",
+            u16::from(options.facility) * 8 + severity,
+            options.hostname.as_deref().unwrap_or("-"),
+            options.app_name,
+            self.command,
+            options.sd_id,
+            structured_data,
+            self.command,
+            self.result
+        )
+    }
+}
+
+/// Key referenced by a log entry
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct KeyRef {
+    /// ID of the key
+    pub id: object::Id,
+
+    /// Label of the key (if it was found among the objects)
+    pub label: Option<String>,
+}
+
+impl KeyRef {
+    /// Resolve a key ID from a log entry, preferring objects of the given type
+    fn resolve(
+        id: object::Id,
+        object_type: Option<object::Type>,
+        objects: &[object::Info],
+    ) -> Option<Self> {
+        if id == NO_KEY_ID {
+            return None;
+        }
+
+        let label = objects
+            .iter()
+            .find(|info| info.object_id == id && Some(info.object_type) == object_type)
+            .or_else(|| objects.iter().find(|info| info.object_id == id))
+            .map(|info| info.label.to_string());
+
+        Some(Self { id, label })
+    }
+}
+
+/// Options for syslog exports
+#[derive(Clone, Debug)]
+pub struct SyslogOptions {
+    /// Syslog facility (defaults to 13, "log audit")
+    pub facility: u8,
+
+    /// Hostname to include in messages (if any)
+    pub hostname: Option<String>,
+
+    /// Application name to include in messages
+    pub app_name: String,
+
+    /// ID of the structured data element each entry is recorded in. Must be
+    /// of the form `name@<private enterprise number>`.
+    pub sd_id: String,
+}
+
+impl Default for SyslogOptions {
+    fn default() -> Self {
+        Self {
+            facility: 13,
+            hostname: None,
+            app_name: "yubihsm".to_owned(),
+            // 32473 is the private enterprise number reserved for examples
+            sd_id: "yubihsm@32473".to_owned(),
+        }
+    }
+}
+
+/// Formats log entries can be exported in
+#[derive(Clone, Debug)]
+pub enum Format {
+    /// One JSON object per line
+    #[cfg(feature = "serde_json")]
+    JsonLines,
+
+    /// Comma-separated values, with a header line
+    Csv,
+
+    /// RFC 5424 syslog messages, one per line
+    Syslog(SyslogOptions),
+}
+
+/// Export log entries in the given format, resolving key IDs to labels using
+/// the given objects
+pub fn export(
+    entries: &[LogEntry],
+    objects: &[object::Info],
+    format: &Format,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let mut output = String::new();
+
+    if let Format::Csv = format {
+        output.push_str(CSV_HEADER);
+        output.push('\n');
+    }
+
+    for entry in entries {
+        let record = Record::decode(entry, objects);
+
+        output.push_str(&match format {
+            #[cfg(feature = "serde_json")]
+            Format::JsonLines => record.to_json(),
+            Format::Csv => record.to_csv(),
+            Format::Syslog(options) => record.to_syslog(options),
+        });
+
+        output.push('\n');
+    }
+
+    writer
+        .write_all(output.as_bytes())
+        .map_err(|e| format_err!(ErrorKind::StorageError, "error writing export: {}", e).into())
+}
+
+/// Name of the structured data parameter for the label of a key
+fn label_param(name: &str) -> &'static str {
+    match name {
+        "session_key" => "session_key_label",
+        "target_key" => "target_key_label",
+        _ => "second_key_label",
+    }
+}
+
+/// Quote a CSV field if needed
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Escape a syslog structured data parameter value
+fn sd_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
@@ -0,0 +1,48 @@
+//! Offline verification of audit log entries, e.g. entries exported from an
+//! HSM by a [`Collector`](super::Collector)
+
+use super::{Error, ErrorKind, LogDigest, LogEntry};
+
+/// Verify that a sequence of log entries chains from a trusted digest (e.g.
+/// the digest of the last entry checked previously, or one recorded when the
+/// HSM was provisioned), without any entries missing.
+pub fn verify(trusted_digest: &LogDigest, entries: &[LogEntry]) -> Result<(), Error> {
+    let first = match entries.first() {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+
+    ensure!(
+        first.verify_digest(trusted_digest),
+        ErrorKind::ChainInvalid,
+        "digest of log entry {} doesn't chain from the trusted digest",
+        first.item
+    );
+
+    for pair in entries.windows(2) {
+        check_chain(&pair[0], &pair[1])?;
+    }
+
+    Ok(())
+}
+
+/// Check that `entry` directly follows `previous` in the chain
+pub(super) fn check_chain(previous: &LogEntry, entry: &LogEntry) -> Result<(), Error> {
+    ensure!(
+        entry.item == previous.item.wrapping_add(1),
+        ErrorKind::ChainInvalid,
+        "log entries missing between {} and {}",
+        previous.item,
+        entry.item
+    );
+
+    ensure!(
+        entry.verify_digest(&previous.digest),
+        ErrorKind::ChainInvalid,
+        "digest of log entry {} doesn't chain from entry {}",
+        entry.item,
+        previous.item
+    );
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Change the authentication key of the current session
+//!
+//! <https://developers.yubico.com/YubiHSM2/Commands/Change_Authentication_Key.html>
+
+use crate::{
+    authentication,
+    command::{self, Command},
+    object,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+/// Request parameters for `command::change_authentication_key`
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ChangeAuthenticationKeyCommand {
+    /// ID of the key to change (the current session's authentication key)
+    pub key_id: object::Id,
+
+    /// Algorithm of the new key
+    pub algorithm: authentication::Algorithm,
+
+    /// New authentication key
+    pub authentication_key: authentication::Key,
+}
+
+impl Command for ChangeAuthenticationKeyCommand {
+    type ResponseType = ChangeAuthenticationKeyResponse;
+}
+
+/// Response from `command::change_authentication_key`
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ChangeAuthenticationKeyResponse {
+    /// ID of the key
+    pub key_id: object::Id,
+}
+
+impl Response for ChangeAuthenticationKeyResponse {
+    const COMMAND_CODE: command::Code = command::Code::ChangeAuthenticationKey;
+}
@@ -0,0 +1,79 @@
+//! Full-device backups: export every object marked `EXPORTABLE_UNDER_WRAP`
+//! under a wrap key into a single archive, which can be restored into any
+//! HSM holding the same wrap key.
+
+mod archive;
+mod error;
+pub mod restore;
+
+pub use self::{
+    archive::{Archive, Entry, Manifest, VERSION},
+    error::{Error, ErrorKind},
+    restore::restore,
+};
+
+use crate::{object, Capability, Client};
+
+/// Back up every exportable object in the HSM under the given wrap key.
+///
+/// Objects without the `EXPORTABLE_UNDER_WRAP` capability are skipped, as
+/// is the wrap key itself.
+pub fn create(client: &Client, wrap_key_id: object::Id) -> Result<Archive, Error> {
+    let device_info = client
+        .device_info()
+        .map_err(|e| format_err!(ErrorKind::ExportFailed, "error getting device info: {}", e))?;
+
+    let objects = client
+        .list_objects(&[])
+        .map_err(|e| format_err!(ErrorKind::ExportFailed, "error listing objects: {}", e))?;
+
+    let mut entries = vec![];
+
+    for object in objects {
+        if object.object_type == object::Type::WrapKey && object.object_id == wrap_key_id {
+            continue;
+        }
+
+        let info = client
+            .get_object_info(object.object_id, object.object_type)
+            .map_err(|e| {
+                format_err!(
+                    ErrorKind::ExportFailed,
+                    "error getting info for {:?} 0x{:04x}: {}",
+                    object.object_type,
+                    object.object_id,
+                    e
+                )
+            })?;
+
+        if !info
+            .capabilities
+            .contains(Capability::EXPORTABLE_UNDER_WRAP)
+        {
+            debug!(
+                "skipping non-exportable {:?} 0x{:04x}",
+                info.object_type, info.object_id
+            );
+            continue;
+        }
+
+        let message = client
+            .export_wrapped(wrap_key_id, info.object_type, info.object_id)
+            .map_err(|e| {
+                format_err!(
+                    ErrorKind::ExportFailed,
+                    "error exporting {:?} 0x{:04x}: {}",
+                    info.object_type,
+                    info.object_id,
+                    e
+                )
+            })?;
+
+        entries.push(Entry::new(info, message));
+    }
+
+    Ok(Archive::new(
+        Manifest::new(device_info.serial_number, wrap_key_id),
+        entries,
+    ))
+}
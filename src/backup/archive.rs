@@ -0,0 +1,218 @@
+//! Backup archives: objects wrapped under a wrap key, along with a manifest
+//! describing where and when they were exported from.
+
+use super::{Error, ErrorKind};
+use crate::{
+    device::SerialNumber,
+    object,
+    uuid::{self, Uuid},
+    wrap, Algorithm, Capability, Domain,
+};
+use base64ct::{Base64, Encoding};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, str::FromStr};
+use time::OffsetDateTime as DateTime;
+
+/// Current version of the archive format
+pub const VERSION: u32 = 1;
+
+/// Backup archive
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Archive {
+    /// Version of the archive format
+    pub version: u32,
+
+    /// Manifest describing the backup
+    pub manifest: Manifest,
+
+    /// Wrapped objects
+    pub objects: Vec<Entry>,
+}
+
+impl Archive {
+    /// Create a new archive containing the given objects
+    pub(super) fn new(manifest: Manifest, objects: Vec<Entry>) -> Self {
+        Self {
+            version: VERSION,
+            manifest,
+            objects,
+        }
+    }
+
+    /// Check the version of the archive and the digests of all of the
+    /// objects it contains
+    pub fn verify(&self) -> Result<(), Error> {
+        ensure!(
+            self.version == VERSION,
+            ErrorKind::ArchiveInvalid,
+            "unsupported archive version: {} (expected {})",
+            self.version,
+            VERSION
+        );
+
+        for entry in &self.objects {
+            entry.message()?;
+        }
+
+        Ok(())
+    }
+
+    /// Serialize this archive as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl FromStr for Archive {
+    type Err = Error;
+
+    /// Parse a `yubihsm::backup::Archive` from its JSON serialization
+    fn from_str(s: &str) -> Result<Self, Error> {
+        serde_json::from_str(s).map_err(|e| {
+            format_err!(
+                ErrorKind::ArchiveInvalid,
+                "error parsing yubihsm::backup::Archive JSON: {}",
+                e
+            )
+            .into()
+        })
+    }
+}
+
+/// Manifest describing a backup
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    /// UUID which uniquely identifies this backup
+    pub uuid: Uuid,
+
+    /// Serial number of the HSM the objects were exported from
+    pub device_serial_number: String,
+
+    /// Date the backup was made
+    pub date: DateTime,
+
+    /// Software that made the backup
+    pub software: String,
+
+    /// ID of the wrap key the objects were exported under
+    pub wrap_key_id: object::Id,
+}
+
+impl Manifest {
+    /// Create a new manifest for a backup of the given device
+    pub(super) fn new(serial_number: SerialNumber, wrap_key_id: object::Id) -> Self {
+        Self {
+            uuid: uuid::new_v4(),
+            device_serial_number: serial_number.to_string(),
+            date: DateTime::now_utc(),
+            software: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            wrap_key_id,
+        }
+    }
+}
+
+/// Object in a backup archive
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+    /// Object identifier
+    pub object_id: object::Id,
+
+    /// Object type
+    pub object_type: object::Type,
+
+    /// Algorithm of the object
+    pub algorithm: Algorithm,
+
+    /// Label of the object
+    pub label: String,
+
+    /// Capabilities of the object
+    pub capabilities: Capability,
+
+    /// Delegated capabilities of the object
+    pub delegated_capabilities: Capability,
+
+    /// Domains of the object
+    pub domains: Domain,
+
+    /// Sequence number of the object
+    pub sequence: object::SequenceId,
+
+    /// How the object originated
+    pub origin: object::Origin,
+
+    /// Hex-encoded SHA-256 digest of the wrapped object
+    pub digest: String,
+
+    /// Base64-encoded wrapped object (i.e. nonce followed by ciphertext)
+    pub wrapped: String,
+}
+
+impl Entry {
+    /// Create an entry for an exported object
+    pub(super) fn new(info: object::Info, message: wrap::Message) -> Self {
+        let wrapped = message.into_vec();
+
+        Self {
+            object_id: info.object_id,
+            object_type: info.object_type,
+            algorithm: info.algorithm,
+            label: info.label.to_string(),
+            capabilities: info.capabilities,
+            delegated_capabilities: info.delegated_capabilities,
+            domains: info.domains,
+            sequence: info.sequence,
+            origin: info.origin,
+            digest: hex_digest(&wrapped),
+            wrapped: Base64::encode_string(&wrapped),
+        }
+    }
+
+    /// Get the handle of this object
+    pub fn handle(&self) -> object::Handle {
+        object::Handle::new(self.object_id, self.object_type)
+    }
+
+    /// Decode the wrapped object, checking it against its digest
+    pub fn message(&self) -> Result<wrap::Message, Error> {
+        let wrapped = Base64::decode_vec(&self.wrapped).map_err(|e| {
+            format_err!(
+                ErrorKind::ArchiveInvalid,
+                "malformed {:?} 0x{:04x}: {}",
+                self.object_type,
+                self.object_id,
+                e
+            )
+        })?;
+
+        ensure!(
+            hex_digest(&wrapped) == self.digest.to_ascii_lowercase(),
+            ErrorKind::DigestMismatch,
+            "{:?} 0x{:04x} doesn't match its digest",
+            self.object_type,
+            self.object_id
+        );
+
+        wrap::Message::from_vec(wrapped).map_err(|e| {
+            format_err!(
+                ErrorKind::ArchiveInvalid,
+                "malformed {:?} 0x{:04x}: {}",
+                self.object_type,
+                self.object_id,
+                e
+            )
+            .into()
+        })
+    }
+}
+
+/// Compute the hex-encoded SHA-256 digest of the given data
+fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            write!(hex, "{byte:02x}").unwrap();
+            hex
+        })
+}
@@ -0,0 +1,38 @@
+//! Backup errors
+
+use crate::error::{BoxError, Context};
+use thiserror::Error;
+
+/// Backup-related errors
+pub type Error = crate::Error<ErrorKind>;
+
+/// Kinds of backup-related errors
+#[derive(Copy, Clone, Debug, Eq, Error, PartialEq)]
+pub enum ErrorKind {
+    /// Malformed or unsupported archive
+    #[error("invalid archive")]
+    ArchiveInvalid,
+
+    /// Objects in the archive already exist in the target HSM
+    #[error("object conflict")]
+    Conflict,
+
+    /// Wrapped object doesn't match its digest in the manifest
+    #[error("digest mismatch")]
+    DigestMismatch,
+
+    /// Error exporting objects from the HSM
+    #[error("export failed")]
+    ExportFailed,
+
+    /// Error importing objects into the HSM
+    #[error("import failed")]
+    ImportFailed,
+}
+
+impl ErrorKind {
+    /// Create an error context from this error
+    pub fn context(self, source: impl Into<BoxError>) -> Context<ErrorKind> {
+        Context::new(self, Some(source.into()))
+    }
+}
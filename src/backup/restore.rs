@@ -0,0 +1,152 @@
+//! Restoring backup archives into an HSM
+
+use super::{Archive, Error, ErrorKind};
+use crate::{device, object, Client};
+
+/// Options for restoring a backup archive
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Check the archive against the target HSM without importing anything
+    pub dry_run: bool,
+
+    /// Skip objects which already exist in the target HSM rather than
+    /// aborting the restore
+    pub skip_existing: bool,
+
+    /// ID of the wrap key in the target HSM to import objects under
+    /// (defaults to the ID of the key the archive was made with)
+    pub wrap_key_id: Option<object::Id>,
+}
+
+impl Options {
+    /// Check the archive against the target HSM without importing anything
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Skip objects which already exist in the target HSM rather than
+    /// aborting the restore
+    pub fn skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
+    }
+
+    /// Import objects under the given wrap key
+    pub fn wrap_key_id(mut self, wrap_key_id: object::Id) -> Self {
+        self.wrap_key_id = Some(wrap_key_id);
+        self
+    }
+}
+
+/// Object in the archive which already exists in the target HSM
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    /// Handle of the object
+    pub handle: object::Handle,
+
+    /// Information about the existing object
+    pub existing: object::Info,
+}
+
+/// Outcome of restoring a backup archive
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Was this a dry run?
+    pub dry_run: bool,
+
+    /// Objects which were restored (or would be, for a dry run)
+    pub restored: Vec<object::Handle>,
+
+    /// Objects which already existed in the target HSM
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Restore a backup archive into the HSM.
+///
+/// The archive is verified and every object in it is checked against the
+/// target HSM before anything is imported. Unless `skip_existing` is set,
+/// the restore is aborted if any objects already exist. A dry run reports
+/// what would be restored (and any conflicts) without importing anything.
+pub fn restore(client: &Client, archive: &Archive, options: &Options) -> Result<Report, Error> {
+    archive.verify()?;
+
+    let wrap_key_id = options.wrap_key_id.unwrap_or(archive.manifest.wrap_key_id);
+
+    client
+        .get_object_info(wrap_key_id, object::Type::WrapKey)
+        .map_err(|e| {
+            format_err!(
+                ErrorKind::ImportFailed,
+                "error getting wrap key 0x{:04x}: {}",
+                wrap_key_id,
+                e
+            )
+        })?;
+
+    let mut report = Report {
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    let mut pending = vec![];
+
+    for entry in &archive.objects {
+        let handle = entry.handle();
+
+        match client.get_object_info(handle.object_id, handle.object_type) {
+            Ok(existing) => report.conflicts.push(Conflict { handle, existing }),
+            Err(e) if e.device_error() == Some(device::ErrorKind::ObjectNotFound) => {
+                pending.push(entry)
+            }
+            Err(e) => fail!(
+                ErrorKind::ImportFailed,
+                "error checking for existing {:?} 0x{:04x}: {}",
+                handle.object_type,
+                handle.object_id,
+                e
+            ),
+        }
+    }
+
+    if !report.conflicts.is_empty() && !options.skip_existing && !options.dry_run {
+        fail!(
+            ErrorKind::Conflict,
+            "{} object(s) already exist: {:?}",
+            report.conflicts.len(),
+            report
+                .conflicts
+                .iter()
+                .map(|conflict| &conflict.handle)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    for entry in pending {
+        let handle = entry.handle();
+
+        if !options.dry_run {
+            info!(
+                "restoring {:?} 0x{:04x} ({})",
+                handle.object_type, handle.object_id, entry.label
+            );
+
+            client
+                .import_wrapped(wrap_key_id, entry.message()?)
+                .map_err(|e| {
+                    format_err!(
+                        ErrorKind::ImportFailed,
+                        "error importing {:?} 0x{:04x} ({} object(s) restored): {}",
+                        handle.object_type,
+                        handle.object_id,
+                        report.restored.len(),
+                        e
+                    )
+                })?;
+        }
+
+        report.restored.push(handle);
+    }
+
+    Ok(report)
+}
@@ -24,7 +24,9 @@ use crate::{
     domain::Domain,
     ecdsa::commands::*,
     ed25519::{self, commands::*},
+    error::BoxError,
     hmac::{self, commands::*},
+    inventory::{self, Inventory},
     object::{self, commands::*, generate},
     opaque::{self, commands::*},
     otp::{self, commands::*},
@@ -41,6 +43,9 @@ use std::{
     time::{Duration, Instant},
 };
 
+/// Callback which stores audit log entries drained from a full log
+type LogSink = dyn Fn(&LogEntries) -> Result<(), BoxError> + Send + Sync;
+
 #[cfg(feature = "passwords")]
 use std::{thread, time::SystemTime};
 
@@ -66,6 +71,9 @@ pub struct Client {
 
     /// Cached `Credentials` for reconnecting closed sessions
     credentials: Option<Credentials>,
+
+    /// Callback for storing audit log entries when the log is full
+    log_sink: Option<Arc<LogSink>>,
 }
 
 impl Client {
@@ -98,11 +106,30 @@ impl Client {
             connector,
             session: Arc::new(Mutex::new(None)),
             credentials: Some(credentials),
+            log_sink: None,
         };
 
         Ok(client)
     }
 
+    /// Register a callback for storing audit log entries.
+    ///
+    /// When forced auditing is enabled (see [`Client::set_force_audit_option`])
+    /// and the audit log is full, the HSM refuses to perform audited commands.
+    /// With a log sink registered, a command which fails because the log is
+    /// full causes the log entries to be fetched and passed to the sink, then
+    /// acknowledged with `SetLogIndex`, after which the command is retried.
+    ///
+    /// Entries are only acknowledged if the sink succeeds, so it should only
+    /// return once they've been stored durably. The sink is shared with
+    /// clones of this client made after it's registered.
+    pub fn set_log_sink<F>(&mut self, sink: F)
+    where
+        F: Fn(&LogEntries) -> Result<(), BoxError> + Send + Sync + 'static,
+    {
+        self.log_sink = Some(Arc::new(sink));
+    }
+
     /// Borrow this client's YubiHSM connector (which is `Clone`able)
     pub fn connector(&self) -> &Connector {
         &self.connector
@@ -161,11 +188,103 @@ impl Client {
         Ok(Instant::now().duration_since(t))
     }
 
+    /// Take a snapshot of the objects in the HSM, along with its serial
+    /// number, firmware version and storage usage.
+    ///
+    /// If `public_keys` is true, the public keys of asymmetric keys are
+    /// included in the inventory.
+    pub fn inventory(&self, public_keys: bool) -> Result<Inventory, Error> {
+        let device_info = self.device_info()?;
+        let storage_info = self.get_storage_info()?;
+        let mut objects = vec![];
+
+        for entry in self.list_objects(&[])? {
+            let info = self.get_object_info(entry.object_id, entry.object_type)?;
+
+            let public_key = if public_keys && info.object_type == object::Type::AsymmetricKey {
+                Some(self.get_public_key(info.object_id)?)
+            } else {
+                None
+            };
+
+            objects.push(inventory::Object::new(info, public_key));
+        }
+
+        Ok(Inventory::new(&device_info, storage_info, objects))
+    }
+
+    /// Find the object with the given label and type.
+    ///
+    /// Fails with a device error of kind `ObjectNotFound` if there's no such
+    /// object, and with a response error if several objects match.
+    pub fn find_object(
+        &self,
+        label: &str,
+        object_type: object::Type,
+    ) -> Result<object::Info, Error> {
+        let mut objects =
+            self.find_objects(&object::Query::new().label(label).object_type(object_type))?;
+
+        match objects.len() {
+            0 => {
+                debug!("no {:?} labeled {:?}", object_type, label);
+                Err(session::Error::from(device::ErrorKind::ObjectNotFound).into())
+            }
+            1 => Ok(objects.remove(0)),
+            n => fail!(
+                ErrorKind::ResponseError,
+                "{} objects of type {:?} labeled {:?}",
+                n,
+                object_type,
+                label
+            ),
+        }
+    }
+
+    /// Find the objects selected by the given query, getting information
+    /// about each of them.
+    pub fn find_objects(&self, query: &object::Query) -> Result<Vec<object::Info>, Error> {
+        let mut objects = vec![];
+
+        for entry in self.list_objects(&query.filters())? {
+            if !query.matches_type(entry.object_type) {
+                continue;
+            }
+
+            let info = self.get_object_info(entry.object_id, entry.object_type)?;
+
+            if query.matches(&info) {
+                objects.push(info);
+            }
+        }
+
+        Ok(objects)
+    }
+
     /// Encrypt a command, send it to the HSM, then read and decrypt the response.
+    ///
+    /// If the audit log is full and a log sink is registered, the log is
+    /// drained into the sink and the command retried.
     fn send_command<T: Command>(&self, command: T) -> Result<T::ResponseType, Error> {
+        match self.send_session_command(&command) {
+            Err(err)
+                if self.log_sink.is_some()
+                    && err.device_error() == Some(device::ErrorKind::LogFull) =>
+            {
+                // The command wasn't performed, so it's safe to retry
+                self.drain_log()?;
+                self.send_session_command(&command)
+            }
+            result => result,
+        }
+    }
+
+    /// Send a command in the current session, opening a new session if the
+    /// current one has reached its command limit
+    fn send_session_command<T: Command>(&self, command: &T) -> Result<T::ResponseType, Error> {
         let mut session = self.session()?;
 
-        match session.send_command(&command) {
+        match session.send_command(command) {
             Ok(response) => Ok(response),
             Err(err) if *err.kind() == session::ErrorKind::CommandLimitExceeded => {
                 // If we encounter this, we've exceeded the maximum number of
@@ -177,12 +296,34 @@ impl Client {
 
                 // Attempt to initiate a new session and retry the command.
                 // (the original command was never sent in this case)
-                Ok(self.session()?.send_command(&command)?)
+                Ok(self.session()?.send_command(command)?)
             }
             Err(err) => Err(err.into()),
         }
     }
 
+    /// Pass the entries in the audit log to the log sink, then acknowledge
+    /// them so the HSM can log further commands
+    fn drain_log(&self) -> Result<(), Error> {
+        let sink = match &self.log_sink {
+            Some(sink) => sink,
+            None => return Ok(()),
+        };
+
+        let log = self.send_session_command(&GetLogEntriesCommand {})?;
+
+        sink(&log).map_err(|e| ErrorKind::LogSinkError.context(e))?;
+
+        if let Some(last) = log.entries.last() {
+            debug!("drained audit log entries up to {}", last.item);
+            self.send_session_command(&SetLogIndexCommand {
+                log_index: last.item,
+            })?;
+        }
+
+        Ok(())
+    }
+
     //
     // HSM Commands
     // <https://developers.yubico.com/YubiHSM2/Commands/>
@@ -196,6 +337,31 @@ impl Client {
         Ok(())
     }
 
+    /// Change the authentication key of the current session, keeping its
+    /// other attributes. The key must have the `CHANGE_AUTHENTICATION_KEY`
+    /// capability.
+    ///
+    /// The current session stays open, but the credentials this client
+    /// reconnects with aren't updated.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Change_Authentication_Key.html>
+    pub fn change_authentication_key<K>(
+        &self,
+        key_id: object::Id,
+        authentication_key: K,
+    ) -> Result<object::Id, Error>
+    where
+        K: Into<authentication::Key>,
+    {
+        Ok(self
+            .send_command(ChangeAuthenticationKeyCommand {
+                key_id,
+                algorithm: authentication::Algorithm::YubicoAes,
+                authentication_key: authentication_key.into(),
+            })?
+            .key_id)
+    }
+
     /// Decrypt data encrypted with RSA-OAEP
     ///
     /// <https://developers.yubico.com/YubiHSM2/Commands/Decrypt_Oaep.html>
@@ -1083,6 +1249,10 @@ impl Client {
 
     /// Sign an SSH certificate using the given template.
     ///
+    /// The `signature` is an RSA-2048 PKCS#1v1.5 SHA-256 signature over the
+    /// big endian `timestamp` followed by the `request`, made with the
+    /// timestamp key of the template.
+    ///
     /// **WARNING**: This functionality has not been tested and has not yet been
     /// confirmed to actually work! USE AT YOUR OWN RISK!
     ///
@@ -1096,7 +1266,7 @@ impl Client {
         template_id: object::Id,
         algorithm: A,
         timestamp: u32,
-        signature: [u8; 32],
+        signature: [u8; TIMESTAMP_SIGNATURE_SIZE],
         request: Vec<u8>,
     ) -> Result<ssh::Certificate, Error>
     where
@@ -1108,7 +1278,7 @@ impl Client {
                 template_id,
                 algorithm: algorithm.into(),
                 timestamp,
-                signature,
+                signature: signature.to_vec(),
                 request,
             })?
             .into())
@@ -14,6 +14,10 @@ pub type Error = crate::Error<ErrorKind>;
 /// Client error kinds
 #[derive(Copy, Clone, Debug, Eq, Error, PartialEq)]
 pub enum ErrorKind {
+    /// Object has a different algorithm than expected
+    #[error("algorithm mismatch")]
+    AlgorithmMismatch,
+
     /// Couldn't authenticate session
     #[error("authentication failed")]
     AuthenticationError,
@@ -34,6 +38,10 @@ pub enum ErrorKind {
     #[error("HSM error")]
     DeviceError,
 
+    /// Log sink couldn't store audit log entries
+    #[error("audit log sink error")]
+    LogSinkError,
+
     /// Protocol error occurred
     #[error("protocol error")]
     ProtocolError,
@@ -2,6 +2,10 @@
 
 use super::{Error, ErrorKind};
 use serde::{de, ser, Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
 
 /// Command IDs for `YubiHSM 2` operations
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
@@ -136,6 +140,33 @@ impl Code {
     }
 }
 
+impl Display for Code {
+    /// Name of the command in kebab case (e.g. `sign-ecdsa`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, c) in format!("{self:?}").chars().enumerate() {
+            if c.is_ascii_uppercase() && i > 0 {
+                write!(f, "-")?;
+            }
+
+            write!(f, "{}", c.to_ascii_lowercase())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Code {
+    type Err = Error;
+
+    /// Parse a command from its kebab-case name (e.g. `sign-ecdsa`)
+    fn from_str(s: &str) -> Result<Self, Error> {
+        (0..=u8::MAX)
+            .filter_map(|byte| Code::from_u8(byte).ok())
+            .find(|code| *code != Code::Unknown && code.to_string() == s)
+            .ok_or_else(|| format_err!(ErrorKind::CodeInvalid, "unknown command: {:?}", s).into())
+    }
+}
+
 impl Serialize for Code {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -75,8 +75,7 @@ impl Connector {
     /// Create a mock HSM connector (useful for testing)
     #[cfg(feature = "mockhsm")]
     pub fn mockhsm() -> Self {
-        let mockhsm: Box<dyn Connectable> = MockHsm::new().into();
-        Self::from(mockhsm)
+        Self::from(MockHsm::new())
     }
 
     /// Send a command message to the HSM, then read and return the response
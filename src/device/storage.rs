@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 /// Response from the [Get Storage Info] command.
 ///
 /// [Get Storage Info]: https://developers.yubico.com/YubiHSM2/Commands/Get_Storage_Info.html
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Info {
     /// Total number of storage records
     pub total_records: u16,
@@ -69,6 +69,13 @@ where
         })
     }
 
+    /// Create a new YubiHSM-backed ECDSA signer for the asymmetric key with
+    /// the given label
+    pub fn create_by_label(client: Client, label: &str) -> Result<Self, Error> {
+        let info = client.find_object(label, object::Type::AsymmetricKey)?;
+        Self::create(client, info.object_id)
+    }
+
     /// Get the public key for the YubiHSM-backed private key.
     pub fn public_key(&self) -> &sec1::EncodedPoint<C> {
         &self.public_key
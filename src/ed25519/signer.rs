@@ -33,6 +33,13 @@ impl Signer {
         })
     }
 
+    /// Create a new YubiHSM-backed Ed25519 signer for the asymmetric key with
+    /// the given label
+    pub fn create_by_label(client: Client, label: &str) -> Result<Self, Error> {
+        let info = client.find_object(label, object::Type::AsymmetricKey)?;
+        Self::create(client, info.object_id)
+    }
+
     /// Get the public key for the YubiHSM-backed Ed25519 private key
     pub fn public_key(&self) -> &PublicKey {
         &self.public_key
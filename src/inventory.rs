@@ -0,0 +1,200 @@
+//! Object inventories: snapshots of the objects in an HSM along with
+//! information about the device, which can be compared to find the objects
+//! which were added, removed or changed between two snapshots.
+//!
+//! Use [`Client::inventory`](crate::Client::inventory) to take a snapshot.
+
+use crate::{
+    asymmetric::PublicKey,
+    device::{self, SerialNumber},
+    object, Algorithm, Capability, Domain,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use time::OffsetDateTime as DateTime;
+
+/// Snapshot of the objects in an HSM
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Inventory {
+    /// Serial number of the HSM
+    pub device_serial_number: SerialNumber,
+
+    /// Firmware version of the HSM (e.g. `2.4.0`)
+    pub firmware_version: String,
+
+    /// Date the snapshot was taken
+    pub date: DateTime,
+
+    /// Storage usage of the HSM
+    pub storage: device::StorageInfo,
+
+    /// Objects in the HSM, ordered by type and ID
+    pub objects: Vec<Object>,
+}
+
+impl Inventory {
+    /// Create an inventory of the given objects
+    pub(crate) fn new(
+        device_info: &device::Info,
+        storage: device::StorageInfo,
+        mut objects: Vec<Object>,
+    ) -> Self {
+        objects.sort_by_key(|object| (object.object_type, object.object_id));
+
+        Self {
+            device_serial_number: device_info.serial_number,
+            firmware_version: format!(
+                "{}.{}.{}",
+                device_info.major_version, device_info.minor_version, device_info.build_version
+            ),
+            date: DateTime::now_utc(),
+            storage,
+            objects,
+        }
+    }
+
+    /// Find an object by its ID and type
+    pub fn get(&self, object_id: object::Id, object_type: object::Type) -> Option<&Object> {
+        self.objects
+            .iter()
+            .find(|object| object.object_id == object_id && object.object_type == object_type)
+    }
+
+    /// Compare this inventory to a later one, finding the objects which were
+    /// added, removed or changed.
+    ///
+    /// Objects are matched by type and ID: an object which was deleted and
+    /// replaced by one with the same ID shows up as changed (with a new
+    /// sequence number).
+    pub fn diff(&self, later: &Inventory) -> Diff {
+        let before = self.by_handle();
+        let after = later.by_handle();
+        let mut diff = Diff::default();
+
+        for (handle, object) in &before {
+            match after.get(handle) {
+                Some(&later_object) if later_object != *object => diff.changed.push(Change {
+                    before: (*object).clone(),
+                    after: later_object.clone(),
+                }),
+                Some(_) => (),
+                None => diff.removed.push((*object).clone()),
+            }
+        }
+
+        for (handle, object) in &after {
+            if !before.contains_key(handle) {
+                diff.added.push((*object).clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Serialize this inventory as JSON
+    #[cfg(feature = "serde_json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Index the objects in this inventory by type and ID
+    fn by_handle(&self) -> BTreeMap<(object::Type, object::Id), &Object> {
+        self.objects
+            .iter()
+            .map(|object| ((object.object_type, object.object_id), object))
+            .collect()
+    }
+}
+
+/// Object in an inventory
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Object {
+    /// Object identifier
+    pub object_id: object::Id,
+
+    /// Object type
+    pub object_type: object::Type,
+
+    /// Algorithm of the object
+    pub algorithm: Algorithm,
+
+    /// Label of the object
+    pub label: String,
+
+    /// Length of the object in bytes
+    pub length: u16,
+
+    /// Capabilities of the object
+    pub capabilities: Capability,
+
+    /// Delegated capabilities of the object
+    pub delegated_capabilities: Capability,
+
+    /// Domains of the object
+    pub domains: Domain,
+
+    /// Sequence number of the object
+    pub sequence: object::SequenceId,
+
+    /// How the object originated
+    pub origin: object::Origin,
+
+    /// Public key of the object (if it's an asymmetric key and public keys
+    /// were requested)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<PublicKey>,
+}
+
+impl Object {
+    /// Create an inventory entry for an object
+    pub(crate) fn new(info: object::Info, public_key: Option<PublicKey>) -> Self {
+        Self {
+            object_id: info.object_id,
+            object_type: info.object_type,
+            algorithm: info.algorithm,
+            label: info.label.to_string(),
+            length: info.length,
+            capabilities: info.capabilities,
+            delegated_capabilities: info.delegated_capabilities,
+            domains: info.domains,
+            sequence: info.sequence,
+            origin: info.origin,
+            public_key,
+        }
+    }
+
+    /// Get the handle of this object
+    pub fn handle(&self) -> object::Handle {
+        object::Handle::new(self.object_id, self.object_type)
+    }
+}
+
+/// Differences between two inventories
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Diff {
+    /// Objects which are only in the later inventory
+    pub added: Vec<Object>,
+
+    /// Objects which are only in the earlier inventory
+    pub removed: Vec<Object>,
+
+    /// Objects which are in both inventories but differ
+    pub changed: Vec<Change>,
+}
+
+impl Diff {
+    /// Are the inventories the same?
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Object which differs between two inventories
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Change {
+    /// Object in the earlier inventory
+    pub before: Object,
+
+    /// Object in the later inventory
+    pub after: Object,
+}
@@ -60,6 +60,8 @@ pub mod asymmetric;
 pub mod attestation;
 pub mod audit;
 pub mod authentication;
+#[cfg(feature = "backup")]
+pub mod backup;
 pub mod capability;
 pub mod client;
 pub mod command;
@@ -70,12 +72,14 @@ pub mod ecdh;
 pub mod ecdsa;
 pub mod ed25519;
 pub mod hmac;
+pub mod inventory;
 #[cfg(feature = "mockhsm")]
-pub(crate) mod mockhsm;
+pub mod mockhsm;
 pub mod object;
 pub mod opaque;
 pub mod otp;
 pub mod response;
+pub mod rotation;
 pub mod rsa;
 pub mod session;
 #[cfg(feature = "setup")]
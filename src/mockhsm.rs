@@ -4,25 +4,39 @@
 #[cfg(not(debug_assertions))]
 compile_error!("MockHsm is not intended for use in release builds");
 
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+mod attestation;
 mod audit;
+mod builder;
+mod clock;
 mod command;
 mod connection;
 mod digest;
 mod error;
+mod firmware;
 mod object;
+mod rng;
 mod session;
+mod ssh;
 mod state;
 
 use self::state::State;
 pub use self::{
+    builder::Builder,
     connection::MockConnection,
     error::{Error, ErrorKind},
+    session::SessionInfo,
+};
+use crate::{
+    audit::{AuditOption, LogEntry},
+    connector::{self, Connectable, Connection, Connector},
 };
-use crate::connector::{self, Connectable, Connection};
 
-/// Mock serial number for the MockHsm
+/// Serial number of the MockHsm unless configured otherwise via [`Builder`]
 pub const MOCK_SERIAL_NUMBER: &str = "0123456789";
 
 /// Software simulation of a `YubiHSM 2` intended for testing
@@ -31,6 +45,15 @@ pub const MOCK_SERIAL_NUMBER: &str = "0123456789";
 /// This only implements a subset of the YubiHSM's functionality, and does
 /// *NOT* properly enforce access control / capabilities!
 ///
+/// Like the real device, it supports at most 16 concurrent sessions, closes
+/// sessions after 30 seconds of inactivity, and has a limited amount of
+/// object storage.
+///
+/// Use [`MockHsm::builder`] to emulate a device with a particular serial
+/// number, firmware version, or set of supported algorithms, or to preload
+/// objects into it. The device's objects, sessions, options, and audit log
+/// can be inspected directly for use in test assertions.
+///
 /// It is *STRONGLY* recommended to also test live against a real device.
 ///
 /// To enable, make sure to build yubihsm.rs with the `mockhsm` cargo feature
@@ -40,7 +63,71 @@ pub struct MockHsm(Arc<Mutex<State>>);
 impl MockHsm {
     /// Create a new MockHsm
     pub fn new() -> Self {
-        MockHsm(Arc::new(Mutex::new(State::new())))
+        Builder::new().build()
+    }
+
+    /// Create a builder for configuring the device the MockHsm emulates
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Move the MockHsm's clock forward by the given duration, e.g. to
+    /// simulate session inactivity timeouts without waiting for them
+    pub fn advance_clock(&self, duration: Duration) {
+        self.0.lock().unwrap().clock.advance(duration);
+    }
+
+    /// Get information about all objects presently stored in the MockHsm
+    pub fn objects(&self) -> Vec<crate::object::Info> {
+        let state = self.0.lock().unwrap();
+        state
+            .objects
+            .iter()
+            .map(|(_, obj)| obj.info().clone())
+            .collect()
+    }
+
+    /// Get information about an object stored in the MockHsm, if it exists
+    pub fn object(
+        &self,
+        object_id: crate::object::Id,
+        object_type: crate::object::Type,
+    ) -> Option<crate::object::Info> {
+        let state = self.0.lock().unwrap();
+        state
+            .objects
+            .get(object_id, object_type)
+            .map(|obj| obj.info().clone())
+    }
+
+    /// Get information about the sessions presently open with the MockHsm
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        self.0.lock().unwrap().sessions()
+    }
+
+    /// Get the auditing setting for a particular command
+    pub fn command_audit_option(&self, command_type: crate::command::Code) -> AuditOption {
+        self.0
+            .lock()
+            .unwrap()
+            .command_audit_options
+            .get(command_type)
+    }
+
+    /// Get the forced auditing setting
+    pub fn force_audit_option(&self) -> AuditOption {
+        self.0.lock().unwrap().force_audit
+    }
+
+    /// Get the FIPS mode setting
+    pub fn fips_option(&self) -> AuditOption {
+        self.0.lock().unwrap().fips
+    }
+
+    /// Get the entries presently in the audit log
+    pub fn log_entries(&self) -> Vec<LogEntry> {
+        let state = self.0.lock().unwrap();
+        state.audit_log.entries().cloned().collect()
     }
 }
 
@@ -62,6 +149,13 @@ impl Default for MockHsm {
     }
 }
 
+impl From<MockHsm> for Connector {
+    fn from(mockhsm: MockHsm) -> Connector {
+        let driver: Box<dyn Connectable> = mockhsm.into();
+        Connector::from(driver)
+    }
+}
+
 impl Into<Box<dyn Connectable>> for MockHsm {
     fn into(self) -> Box<dyn Connectable> {
         Box::new(self)
@@ -0,0 +1,205 @@
+//! Attestation certificates issued by the `MockHsm`
+//!
+//! Like a real `YubiHSM 2`, the `MockHsm` ships with a device attestation key
+//! (NIST P-256) and a corresponding X.509 certificate, both stored under
+//! object ID 0. Unlike a real device, the certificate is self-signed.
+//!
+//! Issued certificates bind the attested public key to a subject naming its
+//! object ID, but omit the Yubico-specific extensions (firmware version,
+//! serial number, origin, domains, capabilities, label).
+//!
+//! Certificates are valid from the time they're issued, except when the
+//! `MockHsm` RNG is seeded: their validity period then starts at a fixed
+//! time so the certificates are reproducible.
+
+use super::{object::Payload, rng::Rng, Error, ErrorKind};
+use crate::{device, object};
+use ::rsa::pkcs1v15;
+use rand_core::RngCore;
+use sha2::Sha256;
+use spki::{
+    AlgorithmIdentifierOwned, ObjectIdentifier, SubjectPublicKeyInfoOwned, SubjectPublicKeyInfoRef,
+};
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+use x509_cert::{
+    builder::{
+        self,
+        profile::{cabf, BuilderProfile},
+        Builder, CertificateBuilder,
+    },
+    der::{self, asn1::BitString, Decode, Encode},
+    ext::Extension,
+    name::Name,
+    serial_number::SerialNumber,
+    time::{Time, Validity},
+    Certificate, TbsCertificate,
+};
+
+/// Object ID of the device attestation key and its certificate
+pub(crate) const ATTESTATION_KEY_ID: object::Id = 0;
+
+/// Validity period of certificates issued by the `MockHsm` (10 years)
+const CERTIFICATE_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// Start of the validity period of certificates issued with a seeded RNG
+/// (2020-01-01T00:00:00Z)
+const SEEDED_NOT_BEFORE: Duration = Duration::from_secs(1_577_836_800);
+
+/// Ed25519 algorithm identifier (RFC 8410)
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+/// Generate the self-signed certificate for the device attestation key
+pub(crate) fn device_certificate(
+    attestation_key: &p256::SecretKey,
+    serial_number: device::SerialNumber,
+    rng: &mut Rng,
+) -> Vec<u8> {
+    let subject = Name::from_str(&format!("CN=YubiHSM Attestation ({serial_number})")).unwrap();
+
+    let public_key = SubjectPublicKeyInfoOwned::from_key(&attestation_key.public_key()).unwrap();
+    let profile = cabf::Root::new(false, subject).unwrap();
+    let validity = validity(rng).unwrap();
+
+    CertificateBuilder::new(profile, random_serial_number(rng), validity, public_key)
+        .unwrap()
+        .build::<_, p256::ecdsa::DerSignature>(&p256::ecdsa::SigningKey::from(attestation_key))
+        .unwrap()
+        .to_der()
+        .unwrap()
+}
+
+/// Issue an attestation certificate for `subject_key`, signed by
+/// `attestation_key` and naming the subject of `attestation_certificate`
+/// as the issuer.
+pub(crate) fn issue_certificate(
+    key_id: object::Id,
+    subject_key: &Payload,
+    attestation_key: &Payload,
+    attestation_certificate: &[u8],
+    rng: &mut Rng,
+) -> Result<Vec<u8>, Error> {
+    let issuer = Certificate::from_der(attestation_certificate)
+        .map_err(|e| format_err!(ErrorKind::CryptoError, "bad attestation certificate: {}", e))?
+        .tbs_certificate
+        .subject;
+
+    let subject = Name::from_str(&format!("CN=YubiHSM Attestation id:0x{key_id:04x}"))
+        .map_err(|e| format_err!(ErrorKind::CryptoError, "bad subject name: {}", e))?;
+
+    let validity = validity(rng)
+        .map_err(|e| format_err!(ErrorKind::CryptoError, "bad validity period: {}", e))?;
+
+    let builder = CertificateBuilder::new(
+        AttestationProfile { issuer, subject },
+        random_serial_number(rng),
+        validity,
+        subject_public_key_info(subject_key)?,
+    )
+    .map_err(|e| format_err!(ErrorKind::CryptoError, "error building certificate: {}", e))?;
+
+    let certificate = match attestation_key {
+        Payload::EcdsaNistP256(secret_key) => builder
+            .build::<_, p256::ecdsa::DerSignature>(&p256::ecdsa::SigningKey::from(secret_key)),
+        Payload::EcdsaNistP384(secret_key) => builder
+            .build::<_, p384::ecdsa::DerSignature>(&p384::ecdsa::SigningKey::from(secret_key)),
+        Payload::RsaKey(private_key) => builder.build::<_, pkcs1v15::Signature>(
+            &pkcs1v15::SigningKey::<Sha256>::new(private_key.clone()),
+        ),
+        other => fail!(
+            ErrorKind::CryptoError,
+            "unsupported attestation key algorithm: {:?}",
+            other.algorithm()
+        ),
+    }
+    .map_err(|e| format_err!(ErrorKind::CryptoError, "error signing certificate: {}", e))?;
+
+    certificate.to_der().map_err(|e| {
+        format_err!(ErrorKind::CryptoError, "error encoding certificate: {}", e).into()
+    })
+}
+
+/// Certificate profile for attestation certificates: an explicit issuer
+/// and subject, and no extensions
+struct AttestationProfile {
+    issuer: Name,
+    subject: Name,
+}
+
+impl BuilderProfile for AttestationProfile {
+    fn get_issuer(&self, _subject: &Name) -> Name {
+        self.issuer.clone()
+    }
+
+    fn get_subject(&self) -> Name {
+        self.subject.clone()
+    }
+
+    fn build_extensions(
+        &self,
+        _spk: SubjectPublicKeyInfoRef<'_>,
+        _issuer_spk: SubjectPublicKeyInfoRef<'_>,
+        _tbs: &TbsCertificate,
+    ) -> builder::Result<Vec<Extension>> {
+        Ok(vec![])
+    }
+}
+
+/// Encode the public key of an asymmetric key payload as an X.509
+/// `SubjectPublicKeyInfo`
+fn subject_public_key_info(payload: &Payload) -> Result<SubjectPublicKeyInfoOwned, Error> {
+    match payload {
+        Payload::EcdsaNistP256(secret_key) => {
+            SubjectPublicKeyInfoOwned::from_key(&secret_key.public_key())
+        }
+        Payload::EcdsaSecp256k1(secret_key) => {
+            SubjectPublicKeyInfoOwned::from_key(&secret_key.public_key())
+        }
+        Payload::EcdsaNistP384(secret_key) => {
+            SubjectPublicKeyInfoOwned::from_key(&secret_key.public_key())
+        }
+        Payload::EcdsaNistP521(secret_key) => {
+            SubjectPublicKeyInfoOwned::from_key(&secret_key.public_key())
+        }
+        Payload::Ed25519Key(signing_key) => {
+            BitString::from_bytes(signing_key.verifying_key().as_bytes())
+                .map(|subject_public_key| SubjectPublicKeyInfoOwned {
+                    algorithm: AlgorithmIdentifierOwned {
+                        oid: ED25519_OID,
+                        parameters: None,
+                    },
+                    subject_public_key,
+                })
+                .map_err(Into::into)
+        }
+        Payload::RsaKey(private_key) => {
+            SubjectPublicKeyInfoOwned::from_key(&private_key.to_public_key())
+        }
+        other => fail!(
+            ErrorKind::CryptoError,
+            "not an asymmetric key: {:?}",
+            other.algorithm()
+        ),
+    }
+    .map_err(|e| format_err!(ErrorKind::CryptoError, "error encoding public key: {}", e).into())
+}
+
+/// Validity period of a certificate issued now
+fn validity(rng: &Rng) -> Result<Validity, der::Error> {
+    let not_before = match rng {
+        Rng::Os => SystemTime::now(),
+        Rng::Seeded { .. } => SystemTime::UNIX_EPOCH + SEEDED_NOT_BEFORE,
+    };
+
+    Ok(Validity {
+        not_before: Time::try_from(not_before)?,
+        not_after: Time::try_from(not_before + CERTIFICATE_VALIDITY)?,
+    })
+}
+
+/// Generate a random certificate serial number
+fn random_serial_number(rng: &mut Rng) -> SerialNumber {
+    SerialNumber::from(rng.next_u32())
+}
@@ -1,9 +1,25 @@
 //! (Partial) support for audit logging within the MockHsm
 //!
-//! No logging is performed and these settings are not yet enforced
+//! Commands with auditing enabled are recorded in a hash-chained log like the
+//! one kept by a real device, however the target and second key IDs of log
+//! entries aren't recorded. When forced auditing is enabled, audited commands
+//! are refused with a log-full error while the log is full.
 
-use crate::{audit::*, command, serialization::serialize};
-use std::collections::BTreeMap;
+use crate::{
+    audit::{commands::AuditResponseCode, *},
+    command, object, response,
+    serialization::serialize,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Instant,
+};
+
+/// Number of entries the audit log can hold
+pub const LOG_CAPACITY: u8 = 62;
+
+/// Placeholder used for fields of log entries which don't apply
+const NO_KEY_ID: object::Id = 0xffff;
 
 /// Default per-command auditing options
 pub const DEFAULT_COMMAND_AUDIT_OPTIONS: &[AuditCommand] = &[
@@ -77,6 +93,14 @@ impl CommandAuditOptions {
         serialize(&audit_command).unwrap()
     }
 
+    /// Get the setting for a particular command
+    pub fn get(&self, command_type: command::Code) -> AuditOption {
+        self.0
+            .get(&command_type)
+            .copied()
+            .unwrap_or(AuditOption::Off)
+    }
+
     /// Change a setting for a particular command
     pub fn put(&mut self, command_type: command::Code, audit_option: AuditOption) {
         self.0.insert(command_type, audit_option);
@@ -94,3 +118,132 @@ impl Default for CommandAuditOptions {
         CommandAuditOptions(result)
     }
 }
+
+/// Hash-chained log of audited commands
+#[derive(Debug)]
+pub struct AuditLog {
+    /// Entries which haven't been overwritten or acknowledged
+    entries: VecDeque<LogEntry>,
+
+    /// Item number of the most recent entry
+    last_item: u16,
+
+    /// Digest of the most recent entry
+    last_digest: LogDigest,
+
+    /// Time the log was started, used to compute entry ticks
+    boot_time: Instant,
+}
+
+impl AuditLog {
+    /// Create a new log, beginning with an initialization entry
+    pub fn new(now: Instant) -> Self {
+        let mut log = Self {
+            entries: VecDeque::new(),
+            last_item: 0,
+            last_digest: LogDigest([0u8; LOG_DIGEST_SIZE]),
+            boot_time: now,
+        };
+
+        log.push(
+            command::Code::HsmInitialization,
+            0xffff,
+            NO_KEY_ID,
+            AuditResponseCode(response::Code::Success(command::Code::Error)),
+            u32::MAX,
+        );
+
+        log
+    }
+
+    /// Record the result of a command performed in a session authenticated
+    /// with the given key, overwriting the oldest entry if the log is full
+    pub fn record(
+        &mut self,
+        now: Instant,
+        command_type: command::Code,
+        length: usize,
+        session_key: object::Id,
+        response: &response::Message,
+    ) {
+        let tick = now.saturating_duration_since(self.boot_time).as_millis() as u32;
+
+        self.push(
+            command_type,
+            length as u16,
+            session_key,
+            audit_result(response),
+            tick,
+        );
+    }
+
+    /// Get the entries presently in the log
+    pub fn entries(&self) -> impl Iterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    /// Number of entries presently in the log
+    pub fn used(&self) -> u8 {
+        self.entries.len() as u8
+    }
+
+    /// Is the log full, i.e. would recording another entry overwrite one?
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= usize::from(LOG_CAPACITY)
+    }
+
+    /// Mark entries up to and including the given index as consumed
+    pub fn set_index(&mut self, log_index: u16) {
+        self.entries.retain(|entry| entry.item > log_index);
+    }
+
+    /// Append a new entry, chaining its digest to the previous entry
+    fn push(
+        &mut self,
+        cmd: command::Code,
+        length: u16,
+        session_key: object::Id,
+        result: AuditResponseCode,
+        tick: u32,
+    ) {
+        let mut entry = LogEntry {
+            item: self.last_item.wrapping_add(1),
+            cmd,
+            length,
+            session_key,
+            target_key: NO_KEY_ID,
+            second_key: NO_KEY_ID,
+            result,
+            tick,
+            digest: LogDigest([0u8; LOG_DIGEST_SIZE]),
+        };
+
+        entry.digest = entry.chained_digest(&self.last_digest).unwrap();
+
+        self.last_item = entry.item;
+        self.last_digest = entry.digest.clone();
+
+        if self.is_full() {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(entry);
+    }
+}
+
+/// Result code recorded in the log for a given response
+fn audit_result(response: &response::Message) -> AuditResponseCode {
+    if response.is_err() {
+        if let Some(&kind) = response.data.first() {
+            let code = response::Code::DeviceOk.to_u8().wrapping_sub(kind);
+
+            if let Ok(code) = response::Code::from_u8(code) {
+                return AuditResponseCode(code);
+            }
+        }
+
+        AuditResponseCode(response::Code::GenericError)
+    } else {
+        AuditResponseCode(response.code)
+    }
+}
@@ -0,0 +1,273 @@
+//! Builder for `MockHsm` instances emulating a particular device
+
+#![allow(clippy::too_many_arguments)]
+
+use super::{firmware, rng::Rng, state::State, MockHsm, MOCK_SERIAL_NUMBER};
+use crate::{
+    asymmetric, authentication,
+    device::{self, SerialNumber},
+    hmac, object, opaque, wrap, Algorithm, Capability, Domain,
+};
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+/// Builder for `MockHsm` instances, allowing the device's serial number,
+/// firmware version, and supported algorithms to be configured, and objects
+/// to be preloaded into it.
+///
+/// Commands and options the emulated firmware version doesn't support are
+/// rejected, as are attempts to generate or import objects using algorithms
+/// which aren't in the list of supported algorithms.
+///
+/// Preloaded objects replace any default object with the same ID and type
+/// (e.g. the default authentication key), but are lost if the device is
+/// reset. Key material is given in the same format as the corresponding
+/// `Client::put_*` method.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    /// Serial number of the device
+    serial_number: SerialNumber,
+
+    /// Firmware version of the device
+    firmware_version: firmware::Version,
+
+    /// Algorithms supported by the device (if not those of its firmware
+    /// version)
+    algorithms: Option<Vec<Algorithm>>,
+
+    /// Seed for a deterministic RNG, if any
+    rng_seed: Option<u64>,
+
+    /// Objects to load into the device
+    objects: Vec<PreloadedObject>,
+}
+
+impl Builder {
+    /// Create a new `MockHsm` builder with the default settings
+    pub fn new() -> Self {
+        Self {
+            serial_number: SerialNumber::from_str(MOCK_SERIAL_NUMBER).unwrap(),
+            firmware_version: firmware::DEFAULT_VERSION,
+            algorithms: None,
+            rng_seed: None,
+            objects: vec![],
+        }
+    }
+
+    /// Set the serial number of the device
+    pub fn serial_number(mut self, serial_number: SerialNumber) -> Self {
+        self.serial_number = serial_number;
+        self
+    }
+
+    /// Set the firmware version of the device
+    pub fn firmware_version(mut self, major: u8, minor: u8, build: u8) -> Self {
+        self.firmware_version = (major, minor, build);
+        self
+    }
+
+    /// Set the algorithms supported by the device, in place of the ones
+    /// supported by its firmware version
+    pub fn algorithms(mut self, algorithms: impl IntoIterator<Item = Algorithm>) -> Self {
+        self.algorithms = Some(algorithms.into_iter().collect());
+        self
+    }
+
+    /// Use a deterministic RNG derived from the given seed in place of the
+    /// operating system's RNG, so generated keys, wrap nonces, card
+    /// challenges and `GetPseudoRandom` output are reproducible given the
+    /// same sequence of commands.
+    ///
+    /// This is predictable by design: use it only for tests!
+    pub fn rng_seed(mut self, seed: u64) -> Self {
+        self.rng_seed = Some(seed);
+        self
+    }
+
+    /// Preload an authentication key
+    pub fn authentication_key(
+        self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        authentication_key: impl Into<authentication::Key>,
+    ) -> Self {
+        self.object(PreloadedObject {
+            object_id: key_id,
+            object_type: object::Type::AuthenticationKey,
+            algorithm: authentication::Algorithm::YubicoAes.into(),
+            label,
+            domains,
+            capabilities,
+            delegated_capabilities,
+            data: authentication_key.into().as_secret_slice().to_vec(),
+        })
+    }
+
+    /// Preload an asymmetric key
+    pub fn asymmetric_key(
+        self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: asymmetric::Algorithm,
+        key_bytes: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.object(PreloadedObject {
+            object_id: key_id,
+            object_type: object::Type::AsymmetricKey,
+            algorithm: algorithm.into(),
+            label,
+            domains,
+            capabilities,
+            delegated_capabilities: Capability::default(),
+            data: key_bytes.into(),
+        })
+    }
+
+    /// Preload an HMAC key
+    pub fn hmac_key(
+        self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: hmac::Algorithm,
+        key_bytes: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.object(PreloadedObject {
+            object_id: key_id,
+            object_type: object::Type::HmacKey,
+            algorithm: algorithm.into(),
+            label,
+            domains,
+            capabilities,
+            delegated_capabilities: Capability::default(),
+            data: key_bytes.into(),
+        })
+    }
+
+    /// Preload a wrap key
+    pub fn wrap_key(
+        self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        algorithm: wrap::Algorithm,
+        key_bytes: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.object(PreloadedObject {
+            object_id: key_id,
+            object_type: object::Type::WrapKey,
+            algorithm: algorithm.into(),
+            label,
+            domains,
+            capabilities,
+            delegated_capabilities,
+            data: key_bytes.into(),
+        })
+    }
+
+    /// Preload an opaque object
+    pub fn opaque(
+        self,
+        object_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: opaque::Algorithm,
+        data: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.object(PreloadedObject {
+            object_id,
+            object_type: object::Type::Opaque,
+            algorithm: algorithm.into(),
+            label,
+            domains,
+            capabilities,
+            delegated_capabilities: Capability::default(),
+            data: data.into(),
+        })
+    }
+
+    /// Build the `MockHsm`.
+    ///
+    /// Panics if any of the preloaded objects are invalid (e.g. malformed key
+    /// material or an algorithm the device doesn't support).
+    pub fn build(self) -> MockHsm {
+        let (major_version, minor_version, build_version) = self.firmware_version;
+
+        let device_info = device::Info {
+            major_version,
+            minor_version,
+            build_version,
+            serial_number: self.serial_number,
+            log_store_capacity: 62,
+            log_store_used: 62,
+            algorithms: self
+                .algorithms
+                .unwrap_or_else(|| firmware::algorithms(self.firmware_version)),
+        };
+
+        let rng = self.rng_seed.map(Rng::from_seed).unwrap_or_default();
+        let mut state = State::new(device_info, rng);
+
+        for object in self.objects {
+            state.objects.remove(object.object_id, object.object_type);
+
+            if let Err(e) = state.objects.put(
+                object.object_id,
+                object.object_type,
+                object.algorithm,
+                object.label,
+                object.capabilities,
+                object.delegated_capabilities,
+                object.domains,
+                &object.data,
+            ) {
+                panic!(
+                    "invalid preloaded {:?} object 0x{:04x}: {}",
+                    object.object_type, object.object_id, e
+                );
+            }
+        }
+
+        MockHsm(Arc::new(Mutex::new(state)))
+    }
+
+    /// Add an object to preload, replacing any previous object with the same
+    /// ID and type
+    fn object(mut self, object: PreloadedObject) -> Self {
+        self.objects
+            .retain(|o| (o.object_id, o.object_type) != (object.object_id, object.object_type));
+
+        self.objects.push(object);
+        self
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Object to be loaded into the `MockHsm` when it's built
+#[derive(Clone, Debug)]
+struct PreloadedObject {
+    object_id: object::Id,
+    object_type: object::Type,
+    algorithm: Algorithm,
+    label: object::Label,
+    domains: Domain,
+    capabilities: Capability,
+    delegated_capabilities: Capability,
+    data: Vec<u8>,
+}
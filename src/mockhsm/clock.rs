@@ -0,0 +1,26 @@
+//! Simulated clock for the `MockHsm`
+//!
+//! Follows the system's monotonic clock, but can be moved forward on demand
+//! so tests can exercise time-dependent behavior (e.g. session timeouts)
+//! without sleeping.
+
+use std::time::{Duration, Instant};
+
+/// Clock used by the `MockHsm` to track session activity
+#[derive(Debug, Default)]
+pub(crate) struct Clock {
+    /// Amount of time the clock has been advanced past the system clock
+    offset: Duration,
+}
+
+impl Clock {
+    /// Get the current time according to this clock
+    pub fn now(&self) -> Instant {
+        Instant::now() + self.offset
+    }
+
+    /// Move the clock forward by the given duration
+    pub fn advance(&mut self, duration: Duration) {
+        self.offset += duration;
+    }
+}
@@ -1,31 +1,33 @@
 //! Commands supported by the `MockHsm`
 
-use super::{object::Payload, state::State, MOCK_SERIAL_NUMBER};
+use super::{
+    attestation::issue_certificate, firmware, object::Payload, rng::Rng, ssh, state::State,
+};
 use crate::{
-    algorithm::*,
-    asymmetric::{self, commands::*, PublicKey},
+    asymmetric::{commands::*, PublicKey},
+    attestation::{self, commands::*},
     audit::{commands::*, AuditCommand, AuditOption, AuditTag},
-    authentication::{self, commands::*},
+    authentication::commands::*,
     command::{Code, Message},
     connector,
-    device::{self, commands::*, SerialNumber, StorageInfo},
-    ecdh,
-    ecdsa::{self, commands::*},
+    device::{self, commands::*},
+    ecdsa::commands::*,
     ed25519::commands::*,
     hmac::{self, commands::*},
     object::{self, commands::*},
-    opaque::{self, commands::*},
-    otp,
+    opaque::commands::*,
+    otp::commands::*,
     response::{self, Response},
     rsa::{
-        self, mgf,
+        mgf,
         oaep::{commands::*, DecryptedData},
         pkcs1::commands::*,
         pss::commands::*,
     },
     serialization::deserialize,
     session::{self, commands::*},
-    template,
+    ssh::{self as ssh_certificate, commands::*},
+    template::commands::*,
     wrap::{self, commands::*},
     Capability,
 };
@@ -42,18 +44,43 @@ use signature::{
     hazmat::{PrehashSigner, RandomizedPrehashSigner},
     Signer,
 };
-use std::{io::Cursor, str::FromStr};
+use std::io::Cursor;
 use subtle::ConstantTimeEq;
 
+/// Deserialize command data, returning an `InvalidData` error response from
+/// the enclosing command handler if it's malformed
+macro_rules! parse_command {
+    ($data:expr) => {
+        match deserialize($data) {
+            Ok(command) => command,
+            Err(e) => {
+                debug!("malformed command data: {}", e);
+                return device::ErrorKind::InvalidData.into();
+            }
+        }
+    };
+}
+
 /// Create a new HSM session
 pub(crate) fn create_session(
     state: &mut State,
     cmd_message: &Message,
 ) -> Result<Vec<u8>, connector::Error> {
-    let cmd: CreateSessionCommand = deserialize(cmd_message.data.as_ref())
-        .unwrap_or_else(|e| panic!("error parsing CreateSession command data: {e:?}"));
+    let cmd: CreateSessionCommand = match deserialize(cmd_message.data.as_ref()) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            debug!("malformed CreateSession command data: {}", e);
+            return Ok(response::Message::from(device::ErrorKind::InvalidData).into());
+        }
+    };
 
-    let session = state.create_session(cmd.authentication_key_id, cmd.host_challenge);
+    let session = match state.create_session(cmd.authentication_key_id, cmd.host_challenge) {
+        Ok(session) => session,
+        Err(kind) => {
+            debug!("error creating session: {:?}", kind);
+            return Ok(response::Message::from(kind).into());
+        }
+    };
 
     let mut response = CreateSessionResponse {
         card_challenge: *session.card_challenge(),
@@ -70,16 +97,30 @@ pub(crate) fn authenticate_session(
     state: &mut State,
     command: &Message,
 ) -> Result<Vec<u8>, connector::Error> {
-    let session_id = command
-        .session_id
-        .unwrap_or_else(|| panic!("no session ID in command: {:?}", command.command_type));
-
-    Ok(state
-        .get_session(session_id)?
-        .channel
-        .verify_authenticate_session(command)
-        .unwrap()
-        .into())
+    let session_id = match command.session_id {
+        Some(session_id) => session_id,
+        None => {
+            debug!("no session ID in command: {:?}", command.command_type);
+            return Ok(response::Message::from(device::ErrorKind::InvalidSession).into());
+        }
+    };
+
+    let session = match state.get_session(session_id) {
+        Ok(session) => session,
+        Err(kind) => {
+            debug!("invalid session ID: {:?}", session_id);
+            return Ok(response::Message::from(kind).into());
+        }
+    };
+
+    match session.channel.verify_authenticate_session(command) {
+        Ok(response) => Ok(response.into()),
+        Err(e) => {
+            debug!("session authentication failed: {}", e);
+            state.close_session(session_id);
+            Ok(response::Message::from(device::ErrorKind::AuthenticationFailed).into())
+        }
+    }
 }
 
 /// Encrypted session messages
@@ -87,28 +128,71 @@ pub(crate) fn session_message(
     state: &mut State,
     encrypted_command: Message,
 ) -> Result<Vec<u8>, connector::Error> {
-    let session_id = encrypted_command.session_id.unwrap_or_else(|| {
-        panic!(
-            "no session ID in command: {:?}",
-            encrypted_command.command_type
-        )
-    });
+    let session_id = match encrypted_command.session_id {
+        Some(session_id) => session_id,
+        None => {
+            debug!(
+                "no session ID in command: {:?}",
+                encrypted_command.command_type
+            );
+            return Ok(response::Message::from(device::ErrorKind::InvalidSession).into());
+        }
+    };
+
+    let command = match state
+        .get_session(session_id)
+        .map(|session| session.decrypt_command(encrypted_command))
+    {
+        Ok(Ok(command)) => command,
+        Ok(Err(e)) => {
+            debug!("error decrypting command: {}", e);
+            state.close_session(session_id);
+            return Ok(response::Message::from(device::ErrorKind::SessionFailed).into());
+        }
+        Err(kind) => {
+            debug!("invalid session ID: {:?}", session_id);
+            return Ok(response::Message::from(kind).into());
+        }
+    };
+
+    if !firmware::supports_command(state.firmware_version(), command.command_type) {
+        debug!(
+            "command not supported by firmware {:?}: {:?}",
+            state.firmware_version(),
+            command.command_type
+        );
+
+        return Ok(state
+            .get_session(session_id)
+            .unwrap()
+            .encrypt_response(device::ErrorKind::InvalidCommand.into())
+            .into());
+    }
 
-    let command = state
-        .get_session(session_id)?
-        .decrypt_command(encrypted_command);
+    if state.is_log_full_for(command.command_type) {
+        debug!("audit log full: refusing {:?}", command.command_type);
+
+        return Ok(state
+            .get_session(session_id)
+            .unwrap()
+            .encrypt_response(device::ErrorKind::LogFull.into())
+            .into());
+    }
 
     let response = match command.command_type {
         Code::BlinkDevice => BlinkDeviceResponse {}.serialize(),
-        Code::CloseSession => return close_session(state, session_id),
+        Code::ChangeAuthenticationKey => {
+            change_authentication_key(state, session_id, &command.data)
+        }
+        Code::CloseSession => CloseSessionResponse {}.serialize(),
         Code::DeleteObject => delete_object(state, &command.data),
-        Code::DeviceInfo => device_info(),
+        Code::DeviceInfo => device_info(state),
         Code::Echo => echo(&command.data),
         Code::ExportWrapped => export_wrapped(state, &command.data),
         Code::GenerateAsymmetricKey => gen_asymmetric_key(state, &command.data),
         Code::GenerateHmacKey => gen_hmac_key(state, &command.data),
         Code::GenerateWrapKey => gen_wrap_key(state, &command.data),
-        Code::GetLogEntries => get_log_entries(),
+        Code::GetLogEntries => get_log_entries(state),
         Code::GetObjectInfo => get_object_info(state, &command.data),
         Code::GetOpaqueObject => get_opaque(state, &command.data),
         Code::GetOption => get_option(state, &command.data),
@@ -121,40 +205,50 @@ pub(crate) fn session_message(
         Code::PutAuthenticationKey => put_authentication_key(state, &command.data),
         Code::PutHmacKey => put_hmac_key(state, &command.data),
         Code::PutOpaqueObject => put_opaque(state, &command.data),
+        Code::PutOtpAead => put_otp_aead_key(state, &command.data),
         Code::SetOption => put_option(state, &command.data),
         Code::PutWrapKey => put_wrap_key(state, &command.data),
-        Code::ResetDevice => return Ok(reset_device(state, session_id)),
-        Code::SetLogIndex => SetLogIndexResponse {}.serialize(),
+        Code::ResetDevice => ResetDeviceResponse(0x01).serialize(),
+        Code::SetLogIndex => set_log_index(state, &command.data),
         Code::SignEcdsa => sign_ecdsa(state, &command.data),
         Code::SignEddsa => sign_eddsa(state, &command.data),
-        Code::GetStorageInfo => get_storage_info(),
+        Code::GetStorageInfo => get_storage_info(state),
+        Code::GetTemplate => get_template(state, &command.data),
+        Code::PutTemplate => put_template(state, &command.data),
+        Code::SignAttestationCertificate => sign_attestation_certificate(state, &command.data),
+        Code::SignSshCertificate => sign_ssh_certificate(state, &command.data),
+        Code::UnwrapData => unwrap_data(state, &command.data),
+        Code::WrapData => wrap_data(state, &command.data),
         Code::VerifyHmac => verify_hmac(state, &command.data),
         Code::SignPss => sign_pss(state, &command.data),
         Code::SignPkcs1 => sign_pkcs1v15(state, &command.data),
         Code::DecryptOaep => decrypt_oaep(state, &command.data),
-        unsupported => panic!("unsupported command type: {unsupported:?}"),
+        unsupported => {
+            debug!("unsupported command type: {:?}", unsupported);
+            device::ErrorKind::InvalidCommand.into()
+        }
     };
 
-    Ok(state
-        .get_session(session_id)?
-        .encrypt_response(response)
-        .into())
-}
+    state.audit_command(session_id, &command, &response);
 
-/// Close an active session
-fn close_session(state: &mut State, session_id: session::Id) -> Result<Vec<u8>, connector::Error> {
     let response = state
-        .get_session(session_id)?
-        .encrypt_response(CloseSessionResponse {}.serialize());
+        .get_session(session_id)
+        .unwrap()
+        .encrypt_response(response)
+        .into();
 
-    state.close_session(session_id);
-    Ok(response.into())
+    match command.command_type {
+        Code::CloseSession => state.close_session(session_id),
+        Code::ResetDevice => state.reset(),
+        _ => (),
+    }
+
+    Ok(response)
 }
 
 /// Delete an object
 fn delete_object(state: &mut State, cmd_data: &[u8]) -> response::Message {
-    let command: DeleteObjectCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::DeleteObject: {e:?}"));
+    let command: DeleteObjectCommand = parse_command!(cmd_data);
 
     if state
         .objects
@@ -169,65 +263,9 @@ fn delete_object(state: &mut State, cmd_data: &[u8]) -> response::Message {
 }
 
 /// Generate a mock device information report
-fn device_info() -> response::Message {
-    let info = device::Info {
-        major_version: 2,
-        minor_version: 0,
-        build_version: 0,
-        serial_number: SerialNumber::from_str(MOCK_SERIAL_NUMBER).unwrap(),
-        log_store_capacity: 62,
-        log_store_used: 62,
-        algorithms: vec![
-            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha1)),
-            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha256)),
-            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha384)),
-            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha512)),
-            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha1)),
-            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha256)),
-            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha384)),
-            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha512)),
-            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa2048),
-            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa3072),
-            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa4096),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcP256),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcP384),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcP521),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcK256),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp256),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp384),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp512),
-            Algorithm::Hmac(hmac::Algorithm::Sha1),
-            Algorithm::Hmac(hmac::Algorithm::Sha256),
-            Algorithm::Hmac(hmac::Algorithm::Sha384),
-            Algorithm::Hmac(hmac::Algorithm::Sha512),
-            Algorithm::Ecdsa(ecdsa::Algorithm::Sha1),
-            Algorithm::Ecdh(ecdh::Algorithm::Ecdh),
-            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha1)),
-            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha256)),
-            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha384)),
-            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha512)),
-            Algorithm::Wrap(wrap::Algorithm::Aes128Ccm),
-            Algorithm::Opaque(opaque::Algorithm::Data),
-            Algorithm::Opaque(opaque::Algorithm::X509Certificate),
-            Algorithm::Mgf(mgf::Algorithm::Sha1),
-            Algorithm::Mgf(mgf::Algorithm::Sha256),
-            Algorithm::Mgf(mgf::Algorithm::Sha384),
-            Algorithm::Mgf(mgf::Algorithm::Sha512),
-            Algorithm::Template(template::Algorithm::Ssh),
-            Algorithm::YubicoOtp(otp::Algorithm::Aes128),
-            Algorithm::Authentication(authentication::Algorithm::YubicoAes),
-            Algorithm::YubicoOtp(otp::Algorithm::Aes192),
-            Algorithm::YubicoOtp(otp::Algorithm::Aes256),
-            Algorithm::Wrap(wrap::Algorithm::Aes192Ccm),
-            Algorithm::Wrap(wrap::Algorithm::Aes256Ccm),
-            Algorithm::Ecdsa(ecdsa::Algorithm::Sha256),
-            Algorithm::Ecdsa(ecdsa::Algorithm::Sha384),
-            Algorithm::Ecdsa(ecdsa::Algorithm::Sha512),
-            Algorithm::Asymmetric(asymmetric::Algorithm::Ed25519),
-            Algorithm::Asymmetric(asymmetric::Algorithm::EcP224),
-        ],
-    };
-
+fn device_info(state: &State) -> response::Message {
+    let mut info = state.device_info.clone();
+    info.log_store_used = state.audit_log.used();
     DeviceInfoResponse(info).serialize()
 }
 
@@ -242,10 +280,9 @@ fn export_wrapped(state: &mut State, cmd_data: &[u8]) -> response::Message {
         wrap_key_id,
         object_type,
         object_id,
-    } = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::ExportWrapped: {e:?}"));
+    } = parse_command!(cmd_data);
 
-    let nonce = wrap::Nonce::generate();
+    let nonce = wrap::Nonce::from_rng(&mut state.rng);
 
     match state
         .objects
@@ -254,17 +291,16 @@ fn export_wrapped(state: &mut State, cmd_data: &[u8]) -> response::Message {
         Ok(ciphertext) => ExportWrappedResponse(wrap::Message { nonce, ciphertext }).serialize(),
         Err(e) => {
             debug!("error wrapping object: {}", e);
-            device::ErrorKind::InvalidCommand.into()
+            device::ErrorKind::from(*e.kind()).into()
         }
     }
 }
 
 /// Generate a new random asymmetric key
 fn gen_asymmetric_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
-    let GenAsymmetricKeyCommand(command) = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::GenAsymmetricKey: {e:?}"));
+    let GenAsymmetricKeyCommand(command) = parse_command!(cmd_data);
 
-    state.objects.generate(
+    if let Err(e) = state.objects.generate(
         command.key_id,
         object::Type::AsymmetricKey,
         command.algorithm,
@@ -272,7 +308,11 @@ fn gen_asymmetric_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
         command.capabilities,
         Capability::default(),
         command.domains,
-    );
+        &mut state.rng,
+    ) {
+        debug!("error generating asymmetric key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     GenAsymmetricKeyResponse {
         key_id: command.key_id,
@@ -282,10 +322,9 @@ fn gen_asymmetric_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
 
 /// Generate a new random HMAC key
 fn gen_hmac_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
-    let GenHmacKeyCommand(command) =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::GenHMACKey: {e:?}"));
+    let GenHmacKeyCommand(command) = parse_command!(cmd_data);
 
-    state.objects.generate(
+    if let Err(e) = state.objects.generate(
         command.key_id,
         object::Type::HmacKey,
         command.algorithm,
@@ -293,7 +332,11 @@ fn gen_hmac_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
         command.capabilities,
         Capability::default(),
         command.domains,
-    );
+        &mut state.rng,
+    ) {
+        debug!("error generating HMAC key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     GenHmacKeyResponse {
         key_id: command.key_id,
@@ -306,9 +349,9 @@ fn gen_wrap_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
     let GenWrapKeyCommand {
         params,
         delegated_capabilities,
-    } = deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::GenWrapKey: {e:?}"));
+    } = parse_command!(cmd_data);
 
-    state.objects.generate(
+    if let Err(e) = state.objects.generate(
         params.key_id,
         object::Type::WrapKey,
         params.algorithm,
@@ -316,7 +359,11 @@ fn gen_wrap_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
         params.capabilities,
         delegated_capabilities,
         params.domains,
-    );
+        &mut state.rng,
+    ) {
+        debug!("error generating wrap key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     GenWrapKeyResponse {
         key_id: params.key_id,
@@ -325,21 +372,19 @@ fn gen_wrap_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
 }
 
 /// Get mock log information
-fn get_log_entries() -> response::Message {
-    // TODO: mimic the YubiHSM's actual audit log
+fn get_log_entries(state: &State) -> response::Message {
     LogEntries {
         unlogged_boot_events: 0,
         unlogged_auth_events: 0,
-        num_entries: 0,
-        entries: vec![],
+        num_entries: state.audit_log.used(),
+        entries: state.audit_log.entries().cloned().collect(),
     }
     .serialize()
 }
 
 /// Get detailed info about a specific object
 fn get_object_info(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: GetObjectInfoCommand = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::GetObjectInfo: {e:?}"));
+    let command: GetObjectInfoCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state
         .objects
@@ -354,8 +399,7 @@ fn get_object_info(state: &State, cmd_data: &[u8]) -> response::Message {
 
 /// Get an opaque object (X.509 certificate or other data) stored in the HSM
 fn get_opaque(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: GetOpaqueCommand = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::GetOpaqueObject: {e:?}"));
+    let command: GetOpaqueCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state.objects.get(command.object_id, object::Type::Opaque) {
         GetOpaqueResponse(obj.payload.to_bytes()).serialize()
@@ -367,8 +411,12 @@ fn get_opaque(state: &State, cmd_data: &[u8]) -> response::Message {
 
 /// Get an auditing option
 fn get_option(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: GetOptionCommand = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::GetOpaqueObject: {e:?}"));
+    let command: GetOptionCommand = parse_command!(cmd_data);
+
+    if !firmware::supports_option(state.firmware_version(), command.tag) {
+        debug!("option not supported by firmware: {:?}", command.tag);
+        return device::ErrorKind::InvalidData.into();
+    }
 
     let results = match command.tag {
         AuditTag::Command => state.command_audit_options.serialize(),
@@ -380,20 +428,18 @@ fn get_option(state: &State, cmd_data: &[u8]) -> response::Message {
 }
 
 /// Get bytes of random data
-fn get_pseudo_random(_state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: GetPseudoRandomCommand = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::GetPseudoRandom: {e:?}"));
+fn get_pseudo_random(state: &mut State, cmd_data: &[u8]) -> response::Message {
+    let command: GetPseudoRandomCommand = parse_command!(cmd_data);
 
     let mut bytes = vec![0u8; command.bytes as usize];
-    OsRng.fill_bytes(&mut bytes);
+    state.rng.fill_bytes(&mut bytes);
 
     GetPseudoRandomResponse { bytes }.serialize()
 }
 
 /// Get the public key associated with a key in the HSM
 fn get_public_key(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: GetPublicKeyCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::GetPubKey: {e:?}"));
+    let command: GetPublicKeyCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state
         .objects
@@ -411,17 +457,20 @@ fn get_public_key(state: &State, cmd_data: &[u8]) -> response::Message {
 }
 
 /// Generate a mock storage status report
-fn get_storage_info() -> response::Message {
-    // TODO: model actual free storage
-    let info = StorageInfo {
-        total_records: 256,
-        free_records: 256,
-        total_pages: 1024,
-        free_pages: 1024,
-        page_size: 126,
-    };
+fn get_storage_info(state: &State) -> response::Message {
+    GetStorageInfoResponse(state.objects.storage_info()).serialize()
+}
+
+/// Get a certificate template stored in the HSM
+fn get_template(state: &State, cmd_data: &[u8]) -> response::Message {
+    let command: GetTemplateCommand = parse_command!(cmd_data);
 
-    GetStorageInfoResponse(info).serialize()
+    if let Some(obj) = state.objects.get(command.object_id, object::Type::Template) {
+        GetTemplateResponse(obj.payload.to_bytes()).serialize()
+    } else {
+        debug!("no such template ID: {:?}", command.object_id);
+        device::ErrorKind::ObjectNotFound.into()
+    }
 }
 
 /// Import an object encrypted under a wrap key into the HSM
@@ -430,8 +479,7 @@ fn import_wrapped(state: &mut State, cmd_data: &[u8]) -> response::Message {
         wrap_key_id,
         nonce,
         ciphertext,
-    } = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::ImportWrapped: {e:?}"));
+    } = parse_command!(cmd_data);
 
     match state.objects.unwrap_obj(wrap_key_id, &nonce, ciphertext) {
         Ok(obj) => ImportWrappedResponse {
@@ -441,22 +489,27 @@ fn import_wrapped(state: &mut State, cmd_data: &[u8]) -> response::Message {
         .serialize(),
         Err(e) => {
             debug!("error unwrapping object: {}", e);
-            device::ErrorKind::InvalidCommand.into()
+            device::ErrorKind::from(*e.kind()).into()
         }
     }
 }
 
 /// List all objects presently accessible to a session
 fn list_objects(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: ListObjectsCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::ListObjects: {e:?}"));
+    let command: ListObjectsCommand = parse_command!(cmd_data);
 
     let len = command.0.len() as u64;
     let mut cursor = Cursor::new(command.0);
     let mut filters = vec![];
 
     while cursor.position() < len {
-        filters.push(object::Filter::deserialize(&mut cursor).unwrap());
+        match object::Filter::deserialize(&mut cursor) {
+            Ok(filter) => filters.push(filter),
+            Err(e) => {
+                debug!("malformed object filter: {}", e);
+                return device::ErrorKind::InvalidData.into();
+            }
+        }
     }
 
     let list_entries = state
@@ -486,10 +539,9 @@ fn list_objects(state: &State, cmd_data: &[u8]) -> response::Message {
 
 /// Put an existing asymmetric key into the HSM
 fn put_asymmetric_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
-    let PutAsymmetricKeyCommand { params, data } = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::PutAsymmetricKey: {e:?}"));
+    let PutAsymmetricKeyCommand { params, data } = parse_command!(cmd_data);
 
-    state.objects.put(
+    if let Err(e) = state.objects.put(
         params.id,
         object::Type::AsymmetricKey,
         params.algorithm,
@@ -498,21 +550,56 @@ fn put_asymmetric_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
         Capability::default(),
         params.domains,
         &data,
-    );
+    ) {
+        debug!("error putting asymmetric key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     PutAsymmetricKeyResponse { key_id: params.id }.serialize()
 }
 
+/// Change the authentication key of the current session
+fn change_authentication_key(
+    state: &mut State,
+    session_id: session::Id,
+    cmd_data: &[u8],
+) -> response::Message {
+    let ChangeAuthenticationKeyCommand {
+        key_id,
+        authentication_key,
+        ..
+    } = parse_command!(cmd_data);
+
+    let session_key_id = state.get_session(session_id).unwrap().authentication_key_id;
+
+    if key_id != session_key_id {
+        debug!(
+            "can't change authentication key {:?} from a session using {:?}",
+            key_id, session_key_id
+        );
+        return device::ErrorKind::InvalidId.into();
+    }
+
+    if let Err(e) = state
+        .objects
+        .change_authentication_key(key_id, authentication_key)
+    {
+        debug!("error changing authentication key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
+
+    ChangeAuthenticationKeyResponse { key_id }.serialize()
+}
+
 /// Put a new authentication key into the HSM
 fn put_authentication_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
     let PutAuthenticationKeyCommand {
         params,
         delegated_capabilities,
         authentication_key,
-    } = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::PutAuthenticationKey: {e:?}"));
+    } = parse_command!(cmd_data);
 
-    state.objects.put(
+    if let Err(e) = state.objects.put(
         params.id,
         object::Type::AuthenticationKey,
         params.algorithm,
@@ -521,17 +608,19 @@ fn put_authentication_key(state: &mut State, cmd_data: &[u8]) -> response::Messa
         delegated_capabilities,
         params.domains,
         &authentication_key.0,
-    );
+    ) {
+        debug!("error putting authentication key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     PutAuthenticationKeyResponse { key_id: params.id }.serialize()
 }
 
 /// Put a new HMAC key into the HSM
 fn put_hmac_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
-    let PutHmacKeyCommand { params, hmac_key } =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::PutHMACKey: {e:?}"));
+    let PutHmacKeyCommand { params, hmac_key } = parse_command!(cmd_data);
 
-    state.objects.put(
+    if let Err(e) = state.objects.put(
         params.id,
         object::Type::HmacKey,
         params.algorithm,
@@ -540,17 +629,19 @@ fn put_hmac_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
         Capability::default(),
         params.domains,
         &hmac_key,
-    );
+    ) {
+        debug!("error putting HMAC key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     PutHmacKeyResponse { key_id: params.id }.serialize()
 }
 
 /// Put an opaque object (X.509 cert or other data) into the HSM
 fn put_opaque(state: &mut State, cmd_data: &[u8]) -> response::Message {
-    let PutOpaqueCommand { params, data } = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::PutOpaqueObject: {e:?}"));
+    let PutOpaqueCommand { params, data } = parse_command!(cmd_data);
 
-    state.objects.put(
+    if let Err(e) = state.objects.put(
         params.id,
         object::Type::Opaque,
         params.algorithm,
@@ -559,7 +650,10 @@ fn put_opaque(state: &mut State, cmd_data: &[u8]) -> response::Message {
         Capability::default(),
         params.domains,
         &data,
-    );
+    ) {
+        debug!("error putting opaque object: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     PutOpaqueResponse {
         object_id: params.id,
@@ -567,43 +661,106 @@ fn put_opaque(state: &mut State, cmd_data: &[u8]) -> response::Message {
     .serialize()
 }
 
+/// Put an existing OTP AEAD key into the HSM
+fn put_otp_aead_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
+    let PutOtpAeadKeyCommand { params, data } = parse_command!(cmd_data);
+
+    if let Err(e) = state.objects.put(
+        params.id,
+        object::Type::OtpAeadKey,
+        params.algorithm,
+        params.label,
+        params.capabilities,
+        Capability::default(),
+        params.domains,
+        &data,
+    ) {
+        debug!("error putting OTP AEAD key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
+
+    PutOtpAeadKeyResponse { key_id: params.id }.serialize()
+}
+
 /// Change an HSM auditing setting
 fn put_option(state: &mut State, cmd_data: &[u8]) -> response::Message {
-    let SetOptionCommand { tag, length, value } =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::PutOption: {e:?}"));
+    let SetOptionCommand { tag, length, value } = parse_command!(cmd_data);
+
+    if !firmware::supports_option(state.firmware_version(), tag) {
+        debug!("option not supported by firmware: {:?}", tag);
+        return device::ErrorKind::InvalidData.into();
+    }
+
+    let expected_length = match tag {
+        AuditTag::Force | AuditTag::Fips => 1,
+        AuditTag::Command => 2,
+    };
+
+    if length != expected_length || value.len() != usize::from(expected_length) {
+        debug!("invalid length for {:?} option: {}", tag, length);
+        return device::ErrorKind::WrongLength.into();
+    }
 
     match tag {
-        AuditTag::Force => {
-            assert_eq!(length, 1);
-            state.force_audit = AuditOption::from_u8(value[0]).unwrap()
-        }
+        AuditTag::Force => match AuditOption::from_u8(value[0]) {
+            Ok(option) => state.force_audit = option,
+            Err(e) => {
+                debug!("{}", e);
+                return device::ErrorKind::InvalidData.into();
+            }
+        },
         AuditTag::Command => {
-            assert_eq!(length, 2);
-            let audit_cmd: AuditCommand =
-                deserialize(&value).unwrap_or_else(|e| panic!("error parsing AuditCommand: {e:?}"));
+            let audit_cmd: AuditCommand = parse_command!(&value);
 
             state
                 .command_audit_options
                 .put(audit_cmd.command_type(), audit_cmd.audit_option());
         }
-        AuditTag::Fips => {
-            assert_eq!(length, 1);
-            state.fips = AuditOption::from_u8(value[0]).unwrap()
-        }
+        AuditTag::Fips => match AuditOption::from_u8(value[0]) {
+            Ok(option) => state.fips = option,
+            Err(e) => {
+                debug!("{}", e);
+                return device::ErrorKind::InvalidData.into();
+            }
+        },
     }
 
     PutOptionResponse {}.serialize()
 }
 
+/// Put a certificate template (i.e. for SSH CA) into the HSM
+fn put_template(state: &mut State, cmd_data: &[u8]) -> response::Message {
+    let PutTemplateCommand { params, data } = parse_command!(cmd_data);
+
+    if let Err(e) = state.objects.put(
+        params.id,
+        object::Type::Template,
+        params.algorithm,
+        params.label,
+        params.capabilities,
+        Capability::default(),
+        params.domains,
+        &data,
+    ) {
+        debug!("error putting template: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
+
+    PutTemplateResponse {
+        object_id: params.id,
+    }
+    .serialize()
+}
+
 /// Put an existing wrap (i.e. AES-CCM) key into the HSM
 fn put_wrap_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
     let PutWrapKeyCommand {
         params,
         delegated_capabilities,
         data,
-    } = deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::PutWrapKey: {e:?}"));
+    } = parse_command!(cmd_data);
 
-    state.objects.put(
+    if let Err(e) = state.objects.put(
         params.id,
         object::Type::WrapKey,
         params.algorithm,
@@ -612,69 +769,99 @@ fn put_wrap_key(state: &mut State, cmd_data: &[u8]) -> response::Message {
         delegated_capabilities,
         params.domains,
         &data,
-    );
+    ) {
+        debug!("error putting wrap key: {}", e);
+        return device::ErrorKind::from(*e.kind()).into();
+    }
 
     PutWrapKeyResponse { key_id: params.id }.serialize()
 }
 
-/// Reset the MockHsm back to its default state
-fn reset_device(state: &mut State, session_id: session::Id) -> Vec<u8> {
-    let response = state
-        .get_session(session_id)
-        .unwrap()
-        .encrypt_response(ResetDeviceResponse(0x01).serialize())
-        .into();
+/// Mark audit log entries up to the given index as consumed
+fn set_log_index(state: &mut State, cmd_data: &[u8]) -> response::Message {
+    let SetLogIndexCommand { log_index } = parse_command!(cmd_data);
+    state.audit_log.set_index(log_index);
+    SetLogIndexResponse {}.serialize()
+}
+
+/// Issue an X.509 certificate attesting to a key generated in the HSM
+fn sign_attestation_certificate(state: &mut State, cmd_data: &[u8]) -> response::Message {
+    let SignAttestationCertificateCommand {
+        key_id,
+        attestation_key_id,
+    } = parse_command!(cmd_data);
+
+    let subject_key = match state.objects.get(key_id, object::Type::AsymmetricKey) {
+        Some(obj) => obj,
+        None => {
+            debug!("no such object ID: {:?}", key_id);
+            return device::ErrorKind::ObjectNotFound.into();
+        }
+    };
+
+    let (attestation_key, attestation_certificate) = match (
+        state
+            .objects
+            .get(attestation_key_id, object::Type::AsymmetricKey),
+        state.objects.get(attestation_key_id, object::Type::Opaque),
+    ) {
+        (Some(key), Some(certificate)) => (key, certificate),
+        _ => {
+            debug!(
+                "no attestation key and certificate with ID: {:?}",
+                attestation_key_id
+            );
+            return device::ErrorKind::ObjectNotFound.into();
+        }
+    };
 
-    state.reset();
-    response
+    match issue_certificate(
+        key_id,
+        &subject_key.payload,
+        &attestation_key.payload,
+        &attestation_certificate.payload.to_bytes(),
+        &mut state.rng,
+    ) {
+        Ok(certificate) => attestation::Certificate(certificate).serialize(),
+        Err(e) => {
+            debug!("error issuing attestation certificate: {}", e);
+            device::ErrorKind::InvalidData.into()
+        }
+    }
 }
 
 /// Sign a message using the ECDSA signature algorithm
 fn sign_ecdsa(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: SignEcdsaCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::SignEcdsa: {e:?}"));
+    let command: SignEcdsaCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state
         .objects
         .get(command.key_id, object::Type::AsymmetricKey)
     {
-        match &obj.payload {
-            Payload::EcdsaNistP256(secret_key) => {
-                let signing_key = p256::ecdsa::SigningKey::from(secret_key);
-                let signature: p256::ecdsa::Signature = signing_key
-                    .sign_prehash(&command.digest)
-                    .expect("ECDSA failure!");
-
-                SignEcdsaResponse(signature.to_der().as_ref().into()).serialize()
-            }
-            Payload::EcdsaSecp256k1(secret_key) => {
-                let signing_key = k256::ecdsa::SigningKey::from(secret_key);
-                let signature: k256::ecdsa::Signature = signing_key
-                    .sign_prehash(&command.digest)
-                    .expect("ECDSA failure!");
-
-                SignEcdsaResponse(signature.to_der().as_ref().into()).serialize()
-            }
-
-            Payload::EcdsaNistP384(secret_key) => {
-                let signing_key = p384::ecdsa::SigningKey::from(secret_key);
-                let signature: p384::ecdsa::Signature = signing_key
-                    .sign_prehash(&command.digest)
-                    .expect("ECDSA failure!");
-
-                SignEcdsaResponse(signature.to_der().as_ref().into()).serialize()
-            }
-            Payload::EcdsaNistP521(secret_key) => {
-                let signing_key = p521::ecdsa::SigningKey::from(secret_key);
-                let signature: p521::ecdsa::Signature = signing_key
-                    .sign_prehash(&command.digest)
-                    .expect("ECDSA failure!");
-
-                SignEcdsaResponse(signature.to_der().as_ref().into()).serialize()
-            }
+        let signature = match &obj.payload {
+            Payload::EcdsaNistP256(secret_key) => p256::ecdsa::SigningKey::from(secret_key)
+                .sign_prehash(&command.digest)
+                .map(|signature: p256::ecdsa::Signature| signature.to_der().as_ref().to_vec()),
+            Payload::EcdsaSecp256k1(secret_key) => k256::ecdsa::SigningKey::from(secret_key)
+                .sign_prehash(&command.digest)
+                .map(|signature: k256::ecdsa::Signature| signature.to_der().as_ref().to_vec()),
+            Payload::EcdsaNistP384(secret_key) => p384::ecdsa::SigningKey::from(secret_key)
+                .sign_prehash(&command.digest)
+                .map(|signature: p384::ecdsa::Signature| signature.to_der().as_ref().to_vec()),
+            Payload::EcdsaNistP521(secret_key) => p521::ecdsa::SigningKey::from(secret_key)
+                .sign_prehash(&command.digest)
+                .map(|signature: p521::ecdsa::Signature| signature.to_der().as_ref().to_vec()),
             _ => {
                 debug!("not an ECDSA key: {:?}", obj.algorithm());
-                device::ErrorKind::InvalidCommand.into()
+                return device::ErrorKind::InvalidCommand.into();
+            }
+        };
+
+        match signature {
+            Ok(signature) => SignEcdsaResponse(signature).serialize(),
+            Err(e) => {
+                debug!("ECDSA failure: {}", e);
+                device::ErrorKind::InvalidData.into()
             }
         }
     } else {
@@ -685,8 +872,7 @@ fn sign_ecdsa(state: &State, cmd_data: &[u8]) -> response::Message {
 
 /// Sign a message using the Ed25519 signature algorithm
 fn sign_eddsa(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: SignEddsaCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::SignEdDSA: {e:?}"));
+    let command: SignEddsaCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state
         .objects
@@ -707,16 +893,17 @@ fn sign_eddsa(state: &State, cmd_data: &[u8]) -> response::Message {
 
 /// Compute the HMAC tag for the given data
 fn sign_hmac(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: SignHmacCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::HMACData: {e:?}"));
+    let command: SignHmacCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state.objects.get(command.key_id, object::Type::HmacKey) {
         if let Payload::HmacKey(alg, ref key) = obj.payload {
-            assert_eq!(alg, hmac::Algorithm::Sha256);
-            let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
-            mac.update(&command.data);
-            let tag = mac.finalize();
-            SignHmacResponse(hmac::Tag(tag.into_bytes().as_slice().into())).serialize()
+            match hmac_tag(alg, key, &command.data) {
+                Some(tag) => SignHmacResponse(hmac::Tag(tag)).serialize(),
+                None => {
+                    debug!("invalid HMAC key length: {}", key.len());
+                    device::ErrorKind::InvalidData.into()
+                }
+            }
         } else {
             debug!("not an HMAC key: {:?}", obj.algorithm());
             device::ErrorKind::InvalidCommand.into()
@@ -727,21 +914,37 @@ fn sign_hmac(state: &State, cmd_data: &[u8]) -> response::Message {
     }
 }
 
+/// Compute an HMAC tag over the given data using the given algorithm
+fn hmac_tag(alg: hmac::Algorithm, key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    macro_rules! hmac_tag {
+        ($hash:ty) => {
+            Hmac::<$hash>::new_from_slice(key)
+                .ok()
+                .map(|mac| mac.chain_update(data).finalize().into_bytes().to_vec())
+        };
+    }
+
+    match alg {
+        hmac::Algorithm::Sha1 => hmac_tag!(Sha1),
+        hmac::Algorithm::Sha256 => hmac_tag!(Sha256),
+        hmac::Algorithm::Sha384 => hmac_tag!(Sha384),
+        hmac::Algorithm::Sha512 => hmac_tag!(Sha512),
+    }
+}
+
 /// Sign a message using the RSASSA-PSS signature algorithm
-fn sign_pss(state: &State, cmd_data: &[u8]) -> response::Message {
+fn sign_pss(state: &mut State, cmd_data: &[u8]) -> response::Message {
     #[inline]
     fn sign_pss_digest<D: Digest + FixedOutputReset>(
         private_key: &RsaPrivateKey,
         msg: &[u8],
-    ) -> pss::Signature {
+        rng: &mut Rng,
+    ) -> signature::Result<pss::Signature> {
         let signing_key = pss::SigningKey::<D>::new(private_key.clone());
-        signing_key
-            .sign_prehash_with_rng(&mut OsRng, msg)
-            .expect("unable to sign with prehash, wrong payload length?")
+        signing_key.sign_prehash_with_rng(rng, msg)
     }
 
-    let command: SignPssCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::SignPss: {e:?}"));
+    let command: SignPssCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state
         .objects
@@ -750,20 +953,26 @@ fn sign_pss(state: &State, cmd_data: &[u8]) -> response::Message {
         if let Payload::RsaKey(private_key) = &obj.payload {
             let signature = match command.mgf1_hash_alg {
                 mgf::Algorithm::Sha1 => {
-                    sign_pss_digest::<Sha1>(private_key, command.digest.as_ref())
+                    sign_pss_digest::<Sha1>(private_key, command.digest.as_ref(), &mut state.rng)
                 }
                 mgf::Algorithm::Sha256 => {
-                    sign_pss_digest::<Sha256>(private_key, command.digest.as_ref())
+                    sign_pss_digest::<Sha256>(private_key, command.digest.as_ref(), &mut state.rng)
                 }
                 mgf::Algorithm::Sha384 => {
-                    sign_pss_digest::<Sha384>(private_key, command.digest.as_ref())
+                    sign_pss_digest::<Sha384>(private_key, command.digest.as_ref(), &mut state.rng)
                 }
                 mgf::Algorithm::Sha512 => {
-                    sign_pss_digest::<Sha512>(private_key, command.digest.as_ref())
+                    sign_pss_digest::<Sha512>(private_key, command.digest.as_ref(), &mut state.rng)
                 }
             };
 
-            SignPssResponse((&signature).into()).serialize()
+            match signature {
+                Ok(signature) => SignPssResponse((&signature).into()).serialize(),
+                Err(e) => {
+                    debug!("RSASSA-PSS failure: {}", e);
+                    device::ErrorKind::InvalidData.into()
+                }
+            }
         } else {
             debug!("not an Rsa key: {:?}", obj.algorithm());
             device::ErrorKind::InvalidCommand.into()
@@ -780,15 +989,12 @@ fn sign_pkcs1v15(state: &State, cmd_data: &[u8]) -> response::Message {
     fn sign_pkcs1v15_prehash<D: Digest + AssociatedOid>(
         private_key: &RsaPrivateKey,
         prehash: &[u8],
-    ) -> pkcs1v15::Signature {
+    ) -> signature::Result<pkcs1v15::Signature> {
         let signing_key = pkcs1v15::SigningKey::<D>::new(private_key.clone());
-        signing_key
-            .sign_prehash(prehash)
-            .expect("unable to sign with prehash, wrong payload length?")
+        signing_key.sign_prehash(prehash)
     }
 
-    let command: SignPkcs1Command =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::SignPss: {e:?}"));
+    let command: SignPkcs1Command = parse_command!(cmd_data);
 
     if let Some(obj) = state
         .objects
@@ -814,7 +1020,13 @@ fn sign_pkcs1v15(state: &State, cmd_data: &[u8]) -> response::Message {
                 }
             };
 
-            SignPkcs1Response((&signature).into()).serialize()
+            match signature {
+                Ok(signature) => SignPkcs1Response((&signature).into()).serialize(),
+                Err(e) => {
+                    debug!("RSASSA-PKCS1-v1_5 failure: {}", e);
+                    device::ErrorKind::InvalidData.into()
+                }
+            }
         } else {
             debug!("not an Rsa key: {:?}", obj.algorithm());
             device::ErrorKind::InvalidCommand.into()
@@ -825,24 +1037,95 @@ fn sign_pkcs1v15(state: &State, cmd_data: &[u8]) -> response::Message {
     }
 }
 
+/// Sign an SSH certificate request using a template
+fn sign_ssh_certificate(state: &State, cmd_data: &[u8]) -> response::Message {
+    let SignSshCertificateCommand {
+        key_id,
+        template_id,
+        algorithm,
+        timestamp,
+        signature,
+        request,
+    } = parse_command!(cmd_data);
+
+    let template = match state.objects.get(template_id, object::Type::Template) {
+        Some(obj) => obj,
+        None => {
+            debug!("no such template ID: {:?}", template_id);
+            return device::ErrorKind::ObjectNotFound.into();
+        }
+    };
+
+    let key = match state.objects.get(key_id, object::Type::AsymmetricKey) {
+        Some(obj) => obj,
+        None => {
+            debug!("no such object ID: {:?}", key_id);
+            return device::ErrorKind::ObjectNotFound.into();
+        }
+    };
+
+    if let Err(e) = ssh::Template::parse(&template.payload.to_bytes())
+        .and_then(|template| template.check_request(key_id, timestamp, &signature, &request))
+    {
+        debug!("SSH template {:?} rejected request: {}", template_id, e);
+        return device::ErrorKind::InvalidData.into();
+    }
+
+    match ssh::sign_certificate(&key.payload, algorithm, &request) {
+        Ok(certificate) => {
+            SignSshCertificateResponse(ssh_certificate::Certificate::from_bytes(certificate))
+                .serialize()
+        }
+        Err(e) => {
+            debug!("error signing SSH certificate: {}", e);
+            device::ErrorKind::InvalidCommand.into()
+        }
+    }
+}
+
+/// Decrypt data which was encrypted (using AES-CCM) under a wrap key
+fn unwrap_data(state: &State, cmd_data: &[u8]) -> response::Message {
+    let UnwrapDataCommand {
+        wrap_key_id,
+        nonce,
+        ciphertext,
+    } = parse_command!(cmd_data);
+
+    match state.objects.unwrap_data(wrap_key_id, &nonce, ciphertext) {
+        Ok(plaintext) => UnwrapDataResponse(plaintext).serialize(),
+        Err(e) => {
+            debug!("error unwrapping data: {}", e);
+            device::ErrorKind::from(*e.kind()).into()
+        }
+    }
+}
+
 /// Verify the HMAC tag for the given data
 fn verify_hmac(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: VerifyHmacCommand =
-        deserialize(cmd_data).unwrap_or_else(|e| panic!("error parsing Code::HMACData: {e:?}"));
+    let command: VerifyHmacCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state.objects.get(command.key_id, object::Type::HmacKey) {
         if let Payload::HmacKey(alg, ref key) = obj.payload {
-            assert_eq!(alg, hmac::Algorithm::Sha256);
-
             // Because of a quirk of our serde parser everything winds up in the tag field
             let data = command.tag.into_vec();
+            let tag_len = alg.key_len();
+
+            if data.len() < tag_len {
+                debug!("truncated HMAC tag: {} bytes", data.len());
+                return device::ErrorKind::InvalidData.into();
+            }
 
-            let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
-            mac.update(&data[32..]);
-            let tag = mac.finalize().into_bytes();
-            let is_ok = tag.as_slice().ct_eq(&data[..32]).unwrap_u8();
+            let (expected_tag, message) = data.split_at(tag_len);
 
-            VerifyHmacResponse(is_ok).serialize()
+            match hmac_tag(alg, key, message) {
+                Some(tag) => {
+                    VerifyHmacResponse(tag.as_slice().ct_eq(expected_tag).unwrap_u8()).serialize()
+                }
+                None => {
+                    debug!("invalid HMAC key length: {}", key.len());
+                    device::ErrorKind::InvalidData.into()
+                }
+            }
         } else {
             debug!("not an HMAC key: {:?}", obj.algorithm());
             device::ErrorKind::InvalidCommand.into()
@@ -853,6 +1136,24 @@ fn verify_hmac(state: &State, cmd_data: &[u8]) -> response::Message {
     }
 }
 
+/// Encrypt data (with AES-CCM) using the given wrap key
+fn wrap_data(state: &mut State, cmd_data: &[u8]) -> response::Message {
+    let WrapDataCommand {
+        wrap_key_id,
+        plaintext,
+    } = parse_command!(cmd_data);
+
+    let nonce = wrap::Nonce::from_rng(&mut state.rng);
+
+    match state.objects.wrap_data(wrap_key_id, &nonce, plaintext) {
+        Ok(ciphertext) => WrapDataResponse(wrap::Message { nonce, ciphertext }).serialize(),
+        Err(e) => {
+            debug!("error wrapping data: {}", e);
+            device::ErrorKind::from(*e.kind()).into()
+        }
+    }
+}
+
 /// [`PrecomputedHashDigest`] provides a backend for storing a fixed hash.
 ///
 /// When an OAEP decrypt command is sent by the client, it will carry the hash of the label (and
@@ -903,8 +1204,7 @@ impl<D: OutputSizeUser> FixedOutputReset for PrecomputedHashDigest<D> {
 }
 
 fn decrypt_oaep(state: &State, cmd_data: &[u8]) -> response::Message {
-    let command: DecryptOaepCommand = deserialize(cmd_data)
-        .unwrap_or_else(|e| panic!("error parsing Code::DecryptOaepCommand: {e:?}"));
+    let command: DecryptOaepCommand = parse_command!(cmd_data);
 
     if let Some(obj) = state
         .objects
@@ -913,11 +1213,16 @@ fn decrypt_oaep(state: &State, cmd_data: &[u8]) -> response::Message {
         if let Payload::RsaKey(private_key) = &obj.payload {
             macro_rules! decrypt_oaep {
                 ($hash:ty) => {{
+                    let fixed = match Array::try_from(command.label_hash.as_slice()) {
+                        Ok(fixed) => fixed,
+                        Err(_) => {
+                            debug!("invalid label hash length: {}", command.label_hash.len());
+                            return device::ErrorKind::InvalidData.into();
+                        }
+                    };
+
                     let oaep = Oaep {
-                        digest: Box::new(PrecomputedHashDigest::<$hash> {
-                            fixed: Array::try_from(command.label_hash.as_slice())
-                                .expect("hash size invariant violated"),
-                        }),
+                        digest: Box::new(PrecomputedHashDigest::<$hash> { fixed }),
                         mgf_digest: Box::new(<$hash>::new()),
                         label: None,
                     };
@@ -4,6 +4,7 @@ use super::{command, state::State, MockHsm};
 use crate::{
     command::Code,
     connector::{self, Connection, ErrorKind::ConnectionFailed, Message},
+    device, response,
 };
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
@@ -21,9 +22,13 @@ impl MockConnection {
 impl Connection for MockConnection {
     /// Send a message to the MockHsm
     fn send_message(&self, _uuid: Uuid, message: Message) -> Result<Message, connector::Error> {
-        let command = message
-            .parse()
-            .map_err(|e| format_err!(ConnectionFailed, "error parsing command: {}", e))?;
+        let command = match message.parse() {
+            Ok(command) => command,
+            Err(e) => {
+                debug!("error parsing command: {}", e);
+                return Ok(invalid_command());
+            }
+        };
 
         let mut state = self
             .0
@@ -34,8 +39,17 @@ impl Connection for MockConnection {
             Code::CreateSession => command::create_session(&mut state, &command),
             Code::AuthenticateSession => command::authenticate_session(&mut state, &command),
             Code::SessionMessage => command::session_message(&mut state, command),
-            unsupported => fail!(ConnectionFailed, "unsupported command: {:?}", unsupported),
+            unsupported => {
+                debug!("unsupported command: {:?}", unsupported);
+                return Ok(invalid_command());
+            }
         }
         .map(Message::from)
     }
 }
+
+/// Error response for commands the `MockHsm` doesn't understand
+fn invalid_command() -> Message {
+    let response: Vec<u8> = response::Message::from(device::ErrorKind::InvalidCommand).into();
+    response.into()
+}
@@ -1,6 +1,9 @@
 //! MockHSM errors
 
-use crate::error::{BoxError, Context};
+use crate::{
+    device,
+    error::{BoxError, Context},
+};
 use thiserror::Error;
 
 /// `MockHsm`-related errors
@@ -17,9 +20,25 @@ pub enum ErrorKind {
     #[error("crypto error")]
     CryptoError,
 
+    /// Object already exists
+    #[error("object exists")]
+    ObjectExists,
+
     /// Object does not exist
     #[error("object not found")]
     ObjectNotFound,
+
+    /// Not enough free storage for a new object
+    #[error("storage full")]
+    StorageFull,
+
+    /// Malformed certificate template
+    #[error("invalid template")]
+    TemplateInvalid,
+
+    /// Algorithm not supported by the `MockHsm`
+    #[error("unsupported algorithm")]
+    UnsupportedAlgorithm,
 }
 
 impl ErrorKind {
@@ -28,3 +47,18 @@ impl ErrorKind {
         Context::new(self, Some(source.into()))
     }
 }
+
+impl From<ErrorKind> for device::ErrorKind {
+    /// Error the device responds with for a given `MockHsm` error
+    fn from(kind: ErrorKind) -> device::ErrorKind {
+        match kind {
+            ErrorKind::AccessDenied => device::ErrorKind::InsufficientPermissions,
+            ErrorKind::CryptoError => device::ErrorKind::InvalidData,
+            ErrorKind::ObjectExists => device::ErrorKind::ObjectExists,
+            ErrorKind::ObjectNotFound => device::ErrorKind::ObjectNotFound,
+            ErrorKind::StorageFull => device::ErrorKind::StorageFailed,
+            ErrorKind::TemplateInvalid => device::ErrorKind::InvalidData,
+            ErrorKind::UnsupportedAlgorithm => device::ErrorKind::InvalidData,
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Firmware versions emulated by the `MockHsm`
+//!
+//! Each `YubiHSM 2` firmware release added commands, device options and
+//! algorithms to the ones supported by the releases before it. Commands and
+//! options a release doesn't support are rejected when the `MockHsm`
+//! emulates it, the same way a real device rejects commands it doesn't know
+//! about, and by default it only supports the algorithms of that release.
+//!
+//! Only the commands and algorithms this crate knows about are listed:
+//! firmware 2.3 and 2.4 added symmetric (AES) keys and asymmetric wrapping,
+//! which it doesn't support, so emulating them is the same as emulating 2.2.
+
+use crate::{
+    asymmetric,
+    audit::AuditTag,
+    authentication, command, ecdh, ecdsa, hmac, opaque, otp,
+    rsa::{self, mgf},
+    template, wrap, Algorithm,
+};
+
+/// Firmware version as a `(major, minor, build)` tuple
+pub(crate) type Version = (u8, u8, u8);
+
+/// Firmware version emulated by default
+pub(crate) const DEFAULT_VERSION: Version = (2, 2, 0);
+
+/// Firmware release and what it added
+struct Release {
+    /// Version of the release
+    version: Version,
+
+    /// Commands added in this release
+    commands: &'static [command::Code],
+
+    /// Device options added in this release
+    options: &'static [AuditTag],
+
+    /// Algorithms added in this release
+    algorithms: &'static [Algorithm],
+}
+
+/// `YubiHSM 2` firmware releases, oldest first
+const RELEASES: &[Release] = &[
+    Release {
+        version: (2, 0, 0),
+        commands: &[
+            command::Code::Echo,
+            command::Code::CreateSession,
+            command::Code::AuthenticateSession,
+            command::Code::SessionMessage,
+            command::Code::DeviceInfo,
+            command::Code::ResetDevice,
+            command::Code::CloseSession,
+            command::Code::GetStorageInfo,
+            command::Code::PutOpaqueObject,
+            command::Code::GetOpaqueObject,
+            command::Code::PutAuthenticationKey,
+            command::Code::PutAsymmetricKey,
+            command::Code::GenerateAsymmetricKey,
+            command::Code::SignPkcs1,
+            command::Code::ListObjects,
+            command::Code::DecryptPkcs1,
+            command::Code::ExportWrapped,
+            command::Code::ImportWrapped,
+            command::Code::PutWrapKey,
+            command::Code::GetLogEntries,
+            command::Code::GetObjectInfo,
+            command::Code::SetOption,
+            command::Code::GetOption,
+            command::Code::GetPseudoRandom,
+            command::Code::PutHmacKey,
+            command::Code::SignHmac,
+            command::Code::GetPublicKey,
+            command::Code::SignPss,
+            command::Code::SignEcdsa,
+            command::Code::DeriveEcdh,
+            command::Code::DeleteObject,
+            command::Code::DecryptOaep,
+            command::Code::GenerateHmacKey,
+            command::Code::GenerateWrapKey,
+            command::Code::VerifyHmac,
+            command::Code::SignSshCertificate,
+            command::Code::PutTemplate,
+            command::Code::GetTemplate,
+            command::Code::DecryptOtp,
+            command::Code::CreateOtpAead,
+            command::Code::RandomizeOtpAead,
+            command::Code::RewrapOtpAead,
+            command::Code::SignAttestationCertificate,
+            command::Code::PutOtpAead,
+            command::Code::GenerateOtpAead,
+            command::Code::SetLogIndex,
+            command::Code::WrapData,
+            command::Code::UnwrapData,
+            command::Code::SignEddsa,
+            command::Code::BlinkDevice,
+        ],
+        options: &[AuditTag::Force, AuditTag::Command],
+        algorithms: &[
+            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha1)),
+            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha256)),
+            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha384)),
+            Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha512)),
+            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha1)),
+            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha256)),
+            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha384)),
+            Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha512)),
+            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa2048),
+            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa3072),
+            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa4096),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP256),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP384),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP521),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcK256),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp256),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp384),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp512),
+            Algorithm::Hmac(hmac::Algorithm::Sha1),
+            Algorithm::Hmac(hmac::Algorithm::Sha256),
+            Algorithm::Hmac(hmac::Algorithm::Sha384),
+            Algorithm::Hmac(hmac::Algorithm::Sha512),
+            Algorithm::Ecdsa(ecdsa::Algorithm::Sha1),
+            Algorithm::Ecdh(ecdh::Algorithm::Ecdh),
+            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha1)),
+            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha256)),
+            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha384)),
+            Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha512)),
+            Algorithm::Wrap(wrap::Algorithm::Aes128Ccm),
+            Algorithm::Opaque(opaque::Algorithm::Data),
+            Algorithm::Opaque(opaque::Algorithm::X509Certificate),
+            Algorithm::Mgf(mgf::Algorithm::Sha1),
+            Algorithm::Mgf(mgf::Algorithm::Sha256),
+            Algorithm::Mgf(mgf::Algorithm::Sha384),
+            Algorithm::Mgf(mgf::Algorithm::Sha512),
+            Algorithm::Template(template::Algorithm::Ssh),
+            Algorithm::YubicoOtp(otp::Algorithm::Aes128),
+            Algorithm::Authentication(authentication::Algorithm::YubicoAes),
+            Algorithm::YubicoOtp(otp::Algorithm::Aes192),
+            Algorithm::YubicoOtp(otp::Algorithm::Aes256),
+            Algorithm::Wrap(wrap::Algorithm::Aes192Ccm),
+            Algorithm::Wrap(wrap::Algorithm::Aes256Ccm),
+            Algorithm::Ecdsa(ecdsa::Algorithm::Sha256),
+            Algorithm::Ecdsa(ecdsa::Algorithm::Sha384),
+            Algorithm::Ecdsa(ecdsa::Algorithm::Sha512),
+            Algorithm::Asymmetric(asymmetric::Algorithm::Ed25519),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP224),
+        ],
+    },
+    Release {
+        version: (2, 2, 0),
+        commands: &[command::Code::ChangeAuthenticationKey],
+        options: &[AuditTag::Fips],
+        algorithms: &[],
+    },
+];
+
+/// Releases up to and including the given version
+fn releases(version: Version) -> impl Iterator<Item = &'static Release> {
+    RELEASES
+        .iter()
+        .take_while(move |release| release.version <= version)
+}
+
+/// Does the given firmware version support the given command?
+pub(crate) fn supports_command(version: Version, code: command::Code) -> bool {
+    releases(version).any(|release| release.commands.contains(&code))
+}
+
+/// Does the given firmware version support the given device option?
+pub(crate) fn supports_option(version: Version, tag: AuditTag) -> bool {
+    releases(version).any(|release| release.options.contains(&tag))
+}
+
+/// Algorithms supported by the given firmware version
+pub(crate) fn algorithms(version: Version) -> Vec<Algorithm> {
+    releases(version)
+        .flat_map(|release| release.algorithms.iter().copied())
+        .collect()
+}
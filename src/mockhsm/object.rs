@@ -12,6 +12,12 @@ use serde::{Deserialize, Serialize};
 /// Label for the default auth key
 const DEFAULT_AUTHENTICATION_KEY_LABEL: &str = "DEFAULT AUTHKEY CHANGE THIS ASAP";
 
+/// Label for the device attestation key
+const ATTESTATION_KEY_LABEL: &str = "Attestation key";
+
+/// Label for the device attestation certificate
+const ATTESTATION_CERTIFICATE_LABEL: &str = "Attestation certificate";
+
 /// An individual object in the `MockHsm`, specialized for a given object type
 #[derive(Debug)]
 pub(crate) struct Object {
@@ -1,10 +1,20 @@
 //! Objects stored in the `MockHsm`
 
-use super::{Object, Payload, WrappedObject, DEFAULT_AUTHENTICATION_KEY_LABEL};
+use super::{
+    Object, Payload, WrappedObject, ATTESTATION_CERTIFICATE_LABEL, ATTESTATION_KEY_LABEL,
+    DEFAULT_AUTHENTICATION_KEY_LABEL,
+};
 use crate::{
+    asymmetric,
     authentication::{self, DEFAULT_AUTHENTICATION_KEY_ID},
-    mockhsm::{Error, ErrorKind},
+    device::{SerialNumber, StorageInfo},
+    mockhsm::{
+        attestation::{self, ATTESTATION_KEY_ID},
+        rng::Rng,
+        Error, ErrorKind,
+    },
     object::{Handle, Id, Info, Label, Origin, Type},
+    opaque,
     serialization::{deserialize, serialize},
     wrap, Algorithm, Capability, Domain,
 };
@@ -12,6 +22,15 @@ use aes::cipher::consts::{U13, U16};
 use ccm::aead::{AeadInPlace, KeyInit};
 use std::collections::{btree_map::Iter as MapIter, BTreeMap as Map};
 
+/// Total number of object records in the `MockHsm`'s storage
+const TOTAL_RECORDS: u16 = 256;
+
+/// Total number of pages in the `MockHsm`'s storage
+const TOTAL_PAGES: u16 = 1024;
+
+/// Size of a storage page in bytes
+const PAGE_SIZE: u16 = 126;
+
 /// AES-CCM with a 128-bit key
 pub(crate) type Aes128Ccm = ccm::Ccm<aes::Aes128, U16, U13>;
 
@@ -90,44 +109,90 @@ impl AesCcmKey {
 
 /// Objects stored in the `MockHsm`
 #[derive(Debug)]
-pub(crate) struct Objects(Map<Handle, Object>);
+pub(crate) struct Objects {
+    /// Objects indexed by their handle
+    objects: Map<Handle, Object>,
 
-impl Default for Objects {
-    fn default() -> Self {
+    /// Algorithms which objects can be generated or imported with
+    algorithms: Vec<Algorithm>,
+}
+
+impl Objects {
+    /// Create the initial set of objects for a device with the given serial
+    /// number which supports the given algorithms
+    pub fn new(serial_number: SerialNumber, algorithms: Vec<Algorithm>, rng: &mut Rng) -> Self {
         let mut objects = Map::new();
 
-        // Insert default authentication key
-        let authentication_key_handle =
-            Handle::new(DEFAULT_AUTHENTICATION_KEY_ID, Type::AuthenticationKey);
+        // Insert device attestation key and its certificate
+        let attestation_key = p256::SecretKey::random(rng);
+        let attestation_certificate =
+            attestation::device_certificate(&attestation_key, serial_number, rng);
+
+        let attestation_key_info = Info {
+            object_id: ATTESTATION_KEY_ID,
+            object_type: Type::AsymmetricKey,
+            algorithm: Algorithm::Asymmetric(asymmetric::Algorithm::EcP256),
+            capabilities: Capability::SIGN_ATTESTATION_CERTIFICATE,
+            delegated_capabilities: Capability::default(),
+            domains: Domain::all(),
+            length: 32,
+            sequence: 0,
+            origin: Origin::Generated,
+            label: ATTESTATION_KEY_LABEL.into(),
+        };
 
-        let authentication_key_info = Info {
-            object_id: DEFAULT_AUTHENTICATION_KEY_ID,
-            object_type: Type::AuthenticationKey,
-            algorithm: Algorithm::Authentication(authentication::Algorithm::YubicoAes),
-            capabilities: Capability::all(),
-            delegated_capabilities: Capability::all(),
+        let attestation_certificate_info = Info {
+            object_id: ATTESTATION_KEY_ID,
+            object_type: Type::Opaque,
+            algorithm: Algorithm::Opaque(opaque::Algorithm::X509Certificate),
+            capabilities: Capability::default(),
+            delegated_capabilities: Capability::default(),
             domains: Domain::all(),
-            length: authentication::key::SIZE as u16,
-            sequence: 1,
+            length: attestation_certificate.len() as u16,
+            sequence: 0,
             origin: Origin::Imported,
-            label: DEFAULT_AUTHENTICATION_KEY_LABEL.into(),
+            label: ATTESTATION_CERTIFICATE_LABEL.into(),
         };
 
-        let authentication_key_payload = Payload::AuthenticationKey(authentication::Key::default());
+        let _ = objects.insert(
+            Handle::new(ATTESTATION_KEY_ID, Type::AsymmetricKey),
+            Object {
+                object_info: attestation_key_info,
+                payload: Payload::EcdsaNistP256(attestation_key),
+            },
+        );
 
         let _ = objects.insert(
-            authentication_key_handle,
+            Handle::new(ATTESTATION_KEY_ID, Type::Opaque),
             Object {
-                object_info: authentication_key_info,
-                payload: authentication_key_payload,
+                object_info: attestation_certificate_info,
+                payload: Payload::Opaque(
+                    opaque::Algorithm::X509Certificate,
+                    attestation_certificate,
+                ),
             },
         );
 
-        Objects(objects)
+        let mut objects = Objects {
+            objects,
+            algorithms,
+        };
+
+        objects.insert_default_authentication_key();
+        objects
+    }
+
+    /// Delete all objects except for the device attestation key and its
+    /// certificate, and restore the default authentication key
+    pub fn reset(&mut self) {
+        self.objects.retain(|handle, _| {
+            handle.object_id == ATTESTATION_KEY_ID
+                && matches!(handle.object_type, Type::AsymmetricKey | Type::Opaque)
+        });
+
+        self.insert_default_authentication_key();
     }
-}
 
-impl Objects {
     /// Generate a new object in the MockHsm
     pub fn generate(
         &mut self,
@@ -138,9 +203,15 @@ impl Objects {
         capabilities: Capability,
         delegated_capabilities: Capability,
         domains: Domain,
-    ) {
-        let payload = Payload::generate(algorithm);
+        rng: &mut Rng,
+    ) -> Result<(), Error> {
+        let handle = Handle::new(object_id, object_type);
+        self.ensure_not_exists(&handle)?;
+        self.ensure_supported(algorithm)?;
+
+        let payload = Payload::generate(algorithm, rng)?;
         let length = payload.len();
+        self.ensure_free_storage(length)?;
 
         let object_info = Info {
             object_id,
@@ -155,19 +226,18 @@ impl Objects {
             label,
         };
 
-        let handle = Handle::new(object_id, object_type);
-
         let object = Object {
             object_info,
             payload,
         };
 
-        assert!(self.0.insert(handle, object).is_none());
+        assert!(self.objects.insert(handle, object).is_none());
+        Ok(())
     }
 
     /// Get an object
     pub fn get(&self, object_id: Id, object_type: Type) -> Option<&Object> {
-        self.0.get(&Handle::new(object_id, object_type))
+        self.objects.get(&Handle::new(object_id, object_type))
     }
 
     /// Put a new object in the MockHsm
@@ -181,9 +251,14 @@ impl Objects {
         delegated_capabilities: Capability,
         domains: Domain,
         data: &[u8],
-    ) {
-        let payload = Payload::new(algorithm, data);
+    ) -> Result<(), Error> {
+        let handle = Handle::new(object_id, object_type);
+        self.ensure_not_exists(&handle)?;
+        self.ensure_supported(algorithm)?;
+
+        let payload = Payload::new(algorithm, data)?;
         let length = payload.len();
+        self.ensure_free_storage(length)?;
 
         let object_info = Info {
             object_id,
@@ -198,19 +273,52 @@ impl Objects {
             label,
         };
 
-        let handle = Handle::new(object_id, object_type);
-
         let object = Object {
             object_info,
             payload,
         };
 
-        assert!(self.0.insert(handle, object).is_none());
+        assert!(self.objects.insert(handle, object).is_none());
+        Ok(())
+    }
+
+    /// Replace the key material of an authentication key, keeping its
+    /// other attributes
+    pub fn change_authentication_key(
+        &mut self,
+        key_id: Id,
+        authentication_key: authentication::Key,
+    ) -> Result<(), Error> {
+        let object = match self
+            .objects
+            .get_mut(&Handle::new(key_id, Type::AuthenticationKey))
+        {
+            Some(object) => object,
+            None => fail!(
+                ErrorKind::ObjectNotFound,
+                "no such authentication key: {:?}",
+                key_id
+            ),
+        };
+
+        ensure!(
+            object
+                .object_info
+                .capabilities
+                .contains(Capability::CHANGE_AUTHENTICATION_KEY),
+            ErrorKind::AccessDenied,
+            "authentication key {:?} does not have CHANGE_AUTHENTICATION_KEY capability",
+            key_id
+        );
+
+        object.payload = Payload::AuthenticationKey(authentication_key);
+        object.object_info.sequence = object.object_info.sequence.wrapping_add(1);
+        Ok(())
     }
 
     /// Remove an object
     pub fn remove(&mut self, object_id: Id, object_type: Type) -> Option<Object> {
-        self.0.remove(&Handle::new(object_id, object_type))
+        self.objects.remove(&Handle::new(object_id, object_type))
     }
 
     /// Encrypt and serialize an object as ciphertext
@@ -254,17 +362,23 @@ impl Objects {
             Origin::WrappedGenerated | Origin::WrappedImported => (),
         }
 
+        let mut data = vec![];
+
+        // Authentication and wrap keys are prefixed with their delegated capabilities
+        if matches!(object_type, Type::AuthenticationKey | Type::WrapKey) {
+            data.extend_from_slice(&serialize(&object_info.delegated_capabilities).unwrap());
+        }
+
+        data.extend_from_slice(&object_to_wrap.payload.to_bytes());
+
         let mut wrapped_object = serialize(&WrappedObject {
             alg_id: wrap_key.algorithm(),
             object_info: object_info.into(),
-            data: object_to_wrap.payload.to_bytes(),
+            data,
         })
         .unwrap();
 
-        wrap_key
-            .encrypt_in_place(nonce, b"", &mut wrapped_object)
-            .unwrap();
-
+        wrap_key.encrypt_in_place(nonce, b"", &mut wrapped_object)?;
         Ok(wrapped_object)
     }
 
@@ -279,45 +393,200 @@ impl Objects {
         let mut wrapped_data: Vec<u8> = ciphertext.into();
         wrap_key.decrypt_in_place(nonce, b"", &mut wrapped_data)?;
 
-        let unwrapped_object: WrappedObject = deserialize(&wrapped_data).unwrap();
-
-        let payload = match unwrapped_object.object_info.algorithm {
-            Algorithm::Asymmetric(alg) if alg.is_rsa() => Payload::new(
-                unwrapped_object.object_info.algorithm,
-                // RSA encoding will include:
-                //  - p
-                //  - q
-                //  - dp    -\
-                //  - dq     +- internal state
-                //  - qinv  -/
-                //
-                //  We can rebuild the key from the primes and we'll just discard the internal state here
-                &unwrapped_object.data[..alg.key_len()],
-            ),
-            _ => Payload::new(
-                unwrapped_object.object_info.algorithm,
-                &unwrapped_object.data,
-            ),
+        let unwrapped_object: WrappedObject = deserialize(&wrapped_data)
+            .map_err(|e| format_err!(ErrorKind::CryptoError, "malformed wrapped object: {}", e))?;
+
+        let mut object_info: Info = unwrapped_object.object_info.into();
+        let mut data = unwrapped_object.data.as_slice();
+
+        // Authentication and wrap keys are prefixed with their delegated capabilities
+        if matches!(
+            object_info.object_type,
+            Type::AuthenticationKey | Type::WrapKey
+        ) {
+            ensure!(
+                data.len() >= wrap::DELEGATED_CAPABILITIES_SIZE,
+                ErrorKind::CryptoError,
+                "wrapped {:?} is missing delegated capabilities",
+                object_info.object_type
+            );
+
+            let (delegated_capabilities, key) = data.split_at(wrap::DELEGATED_CAPABILITIES_SIZE);
+            object_info.delegated_capabilities =
+                deserialize(delegated_capabilities).map_err(|e| {
+                    format_err!(
+                        ErrorKind::CryptoError,
+                        "malformed delegated capabilities: {}",
+                        e
+                    )
+                })?;
+
+            data = key;
+        }
+
+        let object_key = Handle::new(object_info.object_id, object_info.object_type);
+        self.ensure_not_exists(&object_key)?;
+        self.ensure_supported(object_info.algorithm)?;
+
+        let payload = match object_info.algorithm {
+            Algorithm::Asymmetric(alg) if alg.is_rsa() => {
+                ensure!(
+                    data.len() >= alg.key_len(),
+                    ErrorKind::CryptoError,
+                    "truncated wrapped {:?} key",
+                    alg
+                );
+
+                Payload::new(
+                    object_info.algorithm,
+                    // RSA encoding will include:
+                    //  - p
+                    //  - q
+                    //  - dp    -\
+                    //  - dq     +- internal state
+                    //  - qinv  -/
+                    //
+                    //  We can rebuild the key from the primes and we'll just discard the internal state here
+                    &data[..alg.key_len()],
+                )?
+            }
+            _ => Payload::new(object_info.algorithm, data)?,
         };
 
-        let object_key = Handle::new(
-            unwrapped_object.object_info.object_id,
-            unwrapped_object.object_info.object_type,
-        );
+        self.ensure_free_storage(payload.len())?;
 
         let object = Object {
-            object_info: unwrapped_object.object_info.into(),
+            object_info,
             payload,
         };
 
-        assert!(self.0.insert(object_key.clone(), object).is_none());
+        assert!(self.objects.insert(object_key.clone(), object).is_none());
 
         Ok(object_key)
     }
 
+    /// Encrypt arbitrary data under a wrap key
+    pub fn wrap_data(
+        &self,
+        wrap_key_id: Id,
+        nonce: &wrap::Nonce,
+        mut plaintext: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        self.get_wrap_key(wrap_key_id)?
+            .encrypt_in_place(nonce, b"", &mut plaintext)?;
+
+        Ok(plaintext)
+    }
+
+    /// Decrypt data which was encrypted under a wrap key
+    pub fn unwrap_data(
+        &self,
+        wrap_key_id: Id,
+        nonce: &wrap::Nonce,
+        mut ciphertext: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        self.get_wrap_key(wrap_key_id)?
+            .decrypt_in_place(nonce, b"", &mut ciphertext)?;
+
+        Ok(ciphertext)
+    }
+
+    /// Get the amount of total and free storage in the MockHsm
+    pub fn storage_info(&self) -> StorageInfo {
+        let used_pages: u16 = self
+            .objects
+            .values()
+            .map(|object| pages_for_length(object.object_info.length))
+            .sum();
+
+        StorageInfo {
+            total_records: TOTAL_RECORDS,
+            free_records: TOTAL_RECORDS.saturating_sub(self.objects.len() as u16),
+            total_pages: TOTAL_PAGES,
+            free_pages: TOTAL_PAGES.saturating_sub(used_pages),
+            page_size: PAGE_SIZE,
+        }
+    }
+
     /// Iterate over the objects
     pub fn iter(&self) -> Iter<'_> {
-        self.0.iter()
+        self.objects.iter()
+    }
+
+    /// Insert the default authentication key
+    fn insert_default_authentication_key(&mut self) {
+        let authentication_key_handle =
+            Handle::new(DEFAULT_AUTHENTICATION_KEY_ID, Type::AuthenticationKey);
+
+        let authentication_key_info = Info {
+            object_id: DEFAULT_AUTHENTICATION_KEY_ID,
+            object_type: Type::AuthenticationKey,
+            algorithm: Algorithm::Authentication(authentication::Algorithm::YubicoAes),
+            capabilities: Capability::all(),
+            delegated_capabilities: Capability::all(),
+            domains: Domain::all(),
+            length: authentication::key::SIZE as u16,
+            sequence: 1,
+            origin: Origin::Imported,
+            label: DEFAULT_AUTHENTICATION_KEY_LABEL.into(),
+        };
+
+        let authentication_key_payload = Payload::AuthenticationKey(authentication::Key::default());
+
+        let _ = self.objects.insert(
+            authentication_key_handle,
+            Object {
+                object_info: authentication_key_info,
+                payload: authentication_key_payload,
+            },
+        );
+    }
+
+    /// Ensure there isn't already an object with the given handle
+    fn ensure_not_exists(&self, handle: &Handle) -> Result<(), Error> {
+        ensure!(
+            !self.objects.contains_key(handle),
+            ErrorKind::ObjectExists,
+            "{:?} object already exists: {:?}",
+            handle.object_type,
+            handle.object_id
+        );
+
+        Ok(())
+    }
+
+    /// Ensure objects can be created with the given algorithm
+    fn ensure_supported(&self, algorithm: Algorithm) -> Result<(), Error> {
+        ensure!(
+            self.algorithms.contains(&algorithm),
+            ErrorKind::UnsupportedAlgorithm,
+            "algorithm not supported by device: {:?}",
+            algorithm
+        );
+
+        Ok(())
+    }
+
+    /// Ensure there is a free record and enough free pages to store an
+    /// object of the given length
+    fn ensure_free_storage(&self, length: u16) -> Result<(), Error> {
+        let storage_info = self.storage_info();
+
+        ensure!(
+            storage_info.free_records > 0,
+            ErrorKind::StorageFull,
+            "no free object records"
+        );
+
+        ensure!(
+            storage_info.free_pages >= pages_for_length(length),
+            ErrorKind::StorageFull,
+            "not enough free pages for a {}-byte object ({} free)",
+            length,
+            storage_info.free_pages
+        );
+
+        Ok(())
     }
 
     /// Get a wrapping key
@@ -347,3 +616,8 @@ impl Objects {
 
 /// Iterator over objects
 pub(crate) type Iter<'a> = MapIter<'a, Handle, Object>;
+
+/// Number of storage pages consumed by an object of the given length
+fn pages_for_length(length: u16) -> u16 {
+    length.div_ceil(PAGE_SIZE).max(1)
+}
@@ -1,7 +1,12 @@
 //! Object "payloads" in the MockHsm are instances of software implementations
 //! of supported cryptographic primitives, already initialized with a private key
 
-use crate::{algorithm::Algorithm, asymmetric, authentication, hmac, opaque, wrap};
+use crate::{
+    algorithm::Algorithm,
+    asymmetric, authentication, hmac,
+    mockhsm::{rng::Rng, Error, ErrorKind},
+    opaque, otp, template, wrap,
+};
 use digest::{typenum::Unsigned, OutputSizeUser};
 use ecdsa::{
     elliptic_curve::{sec1::ToEncodedPoint, FieldBytesSize},
@@ -9,7 +14,7 @@ use ecdsa::{
 };
 use ed25519_dalek as ed25519;
 use num_traits::cast::FromPrimitive;
-use rand_core::{OsRng, RngCore};
+use rand_core::RngCore;
 use rsa::{traits::PublicKeyParts, BigUint};
 
 /// Loaded instances of a cryptographic primitives in the MockHsm
@@ -42,107 +47,150 @@ pub(crate) enum Payload {
     /// Opaque data
     Opaque(opaque::Algorithm, Vec<u8>),
 
+    /// Certificate template (i.e. for SSH CA)
+    Template(template::Algorithm, Vec<u8>),
+
+    /// Yubico OTP AEAD key
+    OtpAeadKey(otp::Algorithm, Vec<u8>),
+
     /// Wrapping (i.e. symmetric encryption keys)
     WrapKey(wrap::Algorithm, Vec<u8>),
 }
 
 impl Payload {
     /// Create a new payload from the given algorithm and data
-    pub fn new(algorithm: Algorithm, data: &[u8]) -> Self {
-        match algorithm {
-            Algorithm::Wrap(alg) => Payload::WrapKey(alg, data.into()),
+    pub fn new(algorithm: Algorithm, data: &[u8]) -> Result<Self, Error> {
+        let payload = match algorithm {
+            Algorithm::Wrap(alg) => {
+                ensure_key_len(algorithm, data, alg.key_len())?;
+                Payload::WrapKey(alg, data.into())
+            }
             Algorithm::Asymmetric(asymmetric_alg) => match asymmetric_alg {
                 asymmetric::Algorithm::EcP256 => {
-                    assert_eq!(data.len(), 32);
-                    Payload::EcdsaNistP256(p256::SecretKey::from_slice(data).unwrap())
+                    ensure_key_len(algorithm, data, 32)?;
+                    Payload::EcdsaNistP256(
+                        p256::SecretKey::from_slice(data).map_err(|_| invalid_key(algorithm))?,
+                    )
                 }
                 asymmetric::Algorithm::EcK256 => {
-                    assert_eq!(data.len(), 32);
-                    Payload::EcdsaSecp256k1(k256::SecretKey::from_slice(data).unwrap())
+                    ensure_key_len(algorithm, data, 32)?;
+                    Payload::EcdsaSecp256k1(
+                        k256::SecretKey::from_slice(data).map_err(|_| invalid_key(algorithm))?,
+                    )
                 }
                 asymmetric::Algorithm::EcP384 => {
-                    assert_eq!(data.len(), FieldBytesSize::<p384::NistP384>::USIZE);
-                    Payload::EcdsaNistP384(p384::SecretKey::from_slice(data).unwrap())
+                    ensure_key_len(algorithm, data, FieldBytesSize::<p384::NistP384>::USIZE)?;
+                    Payload::EcdsaNistP384(
+                        p384::SecretKey::from_slice(data).map_err(|_| invalid_key(algorithm))?,
+                    )
                 }
                 asymmetric::Algorithm::EcP521 => {
-                    assert_eq!(data.len(), FieldBytesSize::<p521::NistP521>::USIZE);
-                    Payload::EcdsaNistP521(p521::SecretKey::from_slice(data).unwrap())
+                    ensure_key_len(algorithm, data, FieldBytesSize::<p521::NistP521>::USIZE)?;
+                    Payload::EcdsaNistP521(
+                        p521::SecretKey::from_slice(data).map_err(|_| invalid_key(algorithm))?,
+                    )
                 }
 
-                asymmetric::Algorithm::Ed25519 => {
-                    assert_eq!(data.len(), ed25519::SECRET_KEY_LENGTH);
-                    Payload::Ed25519Key(ed25519::SigningKey::try_from(data).unwrap())
-                }
+                asymmetric::Algorithm::Ed25519 => Payload::Ed25519Key(
+                    ed25519::SigningKey::try_from(data).map_err(|_| invalid_key(algorithm))?,
+                ),
                 asymmetric::Algorithm::Rsa2048
                 | asymmetric::Algorithm::Rsa3072
                 | asymmetric::Algorithm::Rsa4096 => {
-                    assert_eq!(data.len(), asymmetric_alg.key_len());
+                    ensure_key_len(algorithm, data, asymmetric_alg.key_len())?;
                     let exp = BigUint::from_u64(65537).expect("invalid static exponent");
                     let p = BigUint::from_bytes_be(&data[..asymmetric_alg.key_len() / 2]);
                     let q = BigUint::from_bytes_be(&data[asymmetric_alg.key_len() / 2..]);
 
-                    let key = rsa::RsaPrivateKey::from_p_q(p, q, exp).unwrap();
+                    let key = rsa::RsaPrivateKey::from_p_q(p, q, exp)
+                        .map_err(|_| invalid_key(algorithm))?;
+
+                    // Primes too short for the algorithm make a smaller modulus
+                    if key.size() != asymmetric_alg.key_len() {
+                        return Err(invalid_key(algorithm));
+                    }
+
                     Payload::RsaKey(key)
                 }
-                _ => {
-                    panic!("MockHsm doesn't support this asymmetric algorithm: {asymmetric_alg:?}")
-                }
+                _ => fail!(
+                    ErrorKind::UnsupportedAlgorithm,
+                    "MockHsm doesn't support this asymmetric algorithm: {:?}",
+                    asymmetric_alg
+                ),
             },
             Algorithm::Hmac(alg) => Payload::HmacKey(alg, data.into()),
             Algorithm::Opaque(alg) => Payload::Opaque(alg, data.into()),
-            Algorithm::Authentication(_) => {
-                Payload::AuthenticationKey(authentication::Key::from_slice(data).unwrap())
+            Algorithm::Template(alg) => Payload::Template(alg, data.into()),
+            Algorithm::YubicoOtp(alg) => {
+                ensure_key_len(algorithm, data, alg.key_len())?;
+                Payload::OtpAeadKey(alg, data.into())
             }
-            _ => panic!("MockHsm does not support putting {algorithm:?} objects"),
-        }
+            Algorithm::Authentication(_) => Payload::AuthenticationKey(
+                authentication::Key::from_slice(data).map_err(|_| invalid_key(algorithm))?,
+            ),
+            _ => fail!(
+                ErrorKind::UnsupportedAlgorithm,
+                "MockHsm does not support putting {:?} objects",
+                algorithm
+            ),
+        };
+
+        Ok(payload)
     }
 
     /// Generate a new key with the given algorithm
-    pub fn generate(algorithm: Algorithm) -> Self {
-        fn gen_rsa(len: usize) -> Payload {
-            let private_key =
-                rsa::RsaPrivateKey::new(&mut OsRng, len).expect("failed to generate a key");
-
-            Payload::RsaKey(private_key)
+    pub fn generate(algorithm: Algorithm, rng: &mut Rng) -> Result<Self, Error> {
+        fn gen_rsa(rng: &mut Rng, len: usize) -> Result<Payload, Error> {
+            rsa::RsaPrivateKey::new(rng, len)
+                .map(Payload::RsaKey)
+                .map_err(|e| format_err!(ErrorKind::CryptoError, "error generating RSA key: {}", e))
         }
 
-        match algorithm {
+        let payload = match algorithm {
             Algorithm::Wrap(wrap_alg) => {
                 let mut bytes = vec![0u8; wrap_alg.key_len()];
-                OsRng.fill_bytes(&mut bytes);
+                rng.fill_bytes(&mut bytes);
                 Payload::WrapKey(wrap_alg, bytes)
             }
             Algorithm::Asymmetric(asymmetric_alg) => match asymmetric_alg {
                 asymmetric::Algorithm::EcP256 => {
-                    Payload::EcdsaNistP256(p256::SecretKey::random(&mut OsRng))
+                    Payload::EcdsaNistP256(p256::SecretKey::random(rng))
                 }
                 asymmetric::Algorithm::EcK256 => {
-                    Payload::EcdsaSecp256k1(k256::SecretKey::random(&mut OsRng))
+                    Payload::EcdsaSecp256k1(k256::SecretKey::random(rng))
                 }
                 asymmetric::Algorithm::EcP384 => {
-                    Payload::EcdsaNistP384(p384::SecretKey::random(&mut OsRng))
+                    Payload::EcdsaNistP384(p384::SecretKey::random(rng))
                 }
                 asymmetric::Algorithm::EcP521 => {
-                    Payload::EcdsaNistP521(p521::SecretKey::random(&mut OsRng))
+                    Payload::EcdsaNistP521(p521::SecretKey::random(rng))
                 }
 
                 asymmetric::Algorithm::Ed25519 => {
-                    Payload::Ed25519Key(ed25519::SigningKey::generate(&mut OsRng))
-                }
-                asymmetric::Algorithm::Rsa2048 => gen_rsa(2048),
-                asymmetric::Algorithm::Rsa3072 => gen_rsa(3072),
-                asymmetric::Algorithm::Rsa4096 => gen_rsa(4096),
-                _ => {
-                    panic!("MockHsm doesn't support this asymmetric algorithm: {asymmetric_alg:?}")
+                    Payload::Ed25519Key(ed25519::SigningKey::generate(rng))
                 }
+                asymmetric::Algorithm::Rsa2048 => gen_rsa(rng, 2048)?,
+                asymmetric::Algorithm::Rsa3072 => gen_rsa(rng, 3072)?,
+                asymmetric::Algorithm::Rsa4096 => gen_rsa(rng, 4096)?,
+                _ => fail!(
+                    ErrorKind::UnsupportedAlgorithm,
+                    "MockHsm doesn't support this asymmetric algorithm: {:?}",
+                    asymmetric_alg
+                ),
             },
             Algorithm::Hmac(hmac_alg) => {
                 let mut bytes = vec![0u8; hmac_alg.key_len()];
-                OsRng.fill_bytes(&mut bytes);
+                rng.fill_bytes(&mut bytes);
                 Payload::HmacKey(hmac_alg, bytes)
             }
-            _ => panic!("MockHsm does not support generating {algorithm:?} objects"),
-        }
+            _ => fail!(
+                ErrorKind::UnsupportedAlgorithm,
+                "MockHsm does not support generating {:?} objects",
+                algorithm
+            ),
+        };
+
+        Ok(payload)
     }
 
     /// Get the algorithm type for this payload
@@ -164,6 +212,8 @@ impl Payload {
             },
             Payload::HmacKey(alg, _) => alg.into(),
             Payload::Opaque(alg, _) => alg.into(),
+            Payload::Template(alg, _) => alg.into(),
+            Payload::OtpAeadKey(alg, _) => alg.into(),
             Payload::WrapKey(alg, _) => alg.into(),
         }
     }
@@ -178,13 +228,14 @@ impl Payload {
             Payload::EcdsaNistP384(_) => {
                 <<p384::NistP384 as DigestPrimitive>::Digest as OutputSizeUser>::OutputSize::USIZE
             }
-            Payload::EcdsaNistP521(_) => {
-                <<p521::NistP521 as DigestPrimitive>::Digest as OutputSizeUser>::OutputSize::USIZE
-            }
+            Payload::EcdsaNistP521(_) => FieldBytesSize::<p521::NistP521>::USIZE,
             Payload::Ed25519Key(_) => ed25519::SECRET_KEY_LENGTH,
-            Payload::RsaKey(k) => k.size(),
+            // p, q, dp, dq and qinv followed by the modulus, as serialized
+            Payload::RsaKey(k) => k.size() / 2 * 5 + k.size(),
             Payload::HmacKey(_, ref data) => data.len(),
             Payload::Opaque(_, ref data) => data.len(),
+            Payload::Template(_, ref data) => data.len(),
+            Payload::OtpAeadKey(_, ref data) => data.len(),
             Payload::WrapKey(_, ref data) => data.len(),
         };
         l as u16
@@ -228,40 +279,65 @@ impl Payload {
             Payload::EcdsaSecp256k1(k) => k.to_bytes().to_vec(),
             Payload::EcdsaNistP384(k) => k.to_bytes().to_vec(),
             Payload::EcdsaNistP521(k) => k.to_bytes().to_vec(),
-            Payload::Ed25519Key(k) => k.verifying_key().to_bytes().into(),
+            Payload::Ed25519Key(k) => k.to_bytes().into(),
             Payload::RsaKey(k) => {
                 use rsa::traits::PrivateKeyParts;
+
+                // Components are zero-padded to a fixed width
+                let modulus_size = k.size();
+                let component_size = modulus_size / 2;
                 let mut out = Vec::new();
 
                 {
                     let primes = k.primes();
                     // p
-                    out.extend_from_slice(&primes[0].to_bytes_be());
+                    wrap::extend_padded(&mut out, &primes[0].to_bytes_be(), component_size);
                     // q
-                    out.extend_from_slice(&primes[1].to_bytes_be());
+                    wrap::extend_padded(&mut out, &primes[1].to_bytes_be(), component_size);
                 }
 
                 // dp
                 if let Some(dp) = k.dp() {
-                    out.extend_from_slice(&dp.to_bytes_be())
+                    wrap::extend_padded(&mut out, &dp.to_bytes_be(), component_size);
                 }
                 // dq
                 if let Some(dq) = k.dq() {
-                    out.extend_from_slice(&dq.to_bytes_be())
+                    wrap::extend_padded(&mut out, &dq.to_bytes_be(), component_size);
                 }
                 // qinv
                 // Note(baloo): The sign is just dropped here.
                 if let Some(qinv) = k.qinv() {
-                    out.extend_from_slice(&qinv.to_bytes_be().1)
+                    wrap::extend_padded(&mut out, &qinv.to_bytes_be().1, component_size);
                 }
                 // n
-                out.extend_from_slice(&k.n().to_bytes_be());
+                wrap::extend_padded(&mut out, &k.n().to_bytes_be(), modulus_size);
 
                 out
             }
             Payload::HmacKey(_, data) => data.clone(),
             Payload::Opaque(_, data) => data.clone(),
+            Payload::Template(_, data) => data.clone(),
+            Payload::OtpAeadKey(_, data) => data.clone(),
             Payload::WrapKey(_, data) => data.clone(),
         }
     }
 }
+
+/// Ensure key material is the expected length for the given algorithm
+fn ensure_key_len(algorithm: Algorithm, data: &[u8], expected_len: usize) -> Result<(), Error> {
+    ensure!(
+        data.len() == expected_len,
+        ErrorKind::CryptoError,
+        "invalid {:?} key length: {} (expected {})",
+        algorithm,
+        data.len(),
+        expected_len
+    );
+
+    Ok(())
+}
+
+/// Error for key material which can't be used with the given algorithm
+fn invalid_key(algorithm: Algorithm) -> Error {
+    format_err!(ErrorKind::CryptoError, "invalid {:?} key", algorithm).into()
+}
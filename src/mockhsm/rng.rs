@@ -0,0 +1,100 @@
+//! Random number generation for the `MockHsm`
+//!
+//! By default the `MockHsm` uses the operating system's RNG. When seeded via
+//! `Builder::rng_seed`, it instead uses a deterministic generator (SHA-256 in
+//! counter mode) for everything it would otherwise randomize: generated keys,
+//! card challenges, wrap nonces, certificate serial numbers, and
+//! `GetPseudoRandom` output. Together with deterministic (RFC 6979) ECDSA and
+//! attestation certificates valid from a fixed time, this makes the output of
+//! the `MockHsm` reproducible across test runs.
+//!
+//! The seeded generator is predictable by design and must never be used for
+//! anything but testing.
+
+use rand_core::{impls, CryptoRng, OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Size of a block of output from the seeded generator
+const BLOCK_SIZE: usize = 32;
+
+/// RNG used by the `MockHsm`
+#[derive(Debug, Default)]
+pub(crate) enum Rng {
+    /// Operating system RNG
+    #[default]
+    Os,
+
+    /// Deterministic RNG derived from a seed
+    Seeded {
+        /// Seed the generator was created with
+        seed: u64,
+
+        /// Number of blocks generated so far
+        counter: u64,
+
+        /// Current block of output
+        block: [u8; BLOCK_SIZE],
+
+        /// Number of bytes of the current block which have been consumed
+        offset: usize,
+    },
+}
+
+impl Rng {
+    /// Create a deterministic RNG from the given seed
+    pub fn from_seed(seed: u64) -> Self {
+        Rng::Seeded {
+            seed,
+            counter: 0,
+            block: [0u8; BLOCK_SIZE],
+            offset: BLOCK_SIZE,
+        }
+    }
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let (seed, counter, block, offset) = match self {
+            Rng::Os => {
+                OsRng.fill_bytes(dest);
+                return;
+            }
+            Rng::Seeded {
+                seed,
+                counter,
+                block,
+                offset,
+            } => (*seed, counter, block, offset),
+        };
+
+        for byte in dest {
+            if *offset == BLOCK_SIZE {
+                let mut hasher = Sha256::new();
+                hasher.update(seed.to_be_bytes());
+                hasher.update(counter.to_be_bytes());
+                block.copy_from_slice(&hasher.finalize());
+
+                *counter += 1;
+                *offset = 0;
+            }
+
+            *byte = block[*offset];
+            *offset += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for Rng {}
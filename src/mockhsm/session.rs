@@ -1,34 +1,85 @@
 //! Sessions with the `MockHsm`
 
-use std::fmt::{self, Debug};
+use std::{
+    fmt::{self, Debug},
+    time::{Duration, Instant},
+};
 
 use crate::{
-    command, response,
+    command, object, response,
     session::{
+        self,
         securechannel::{Challenge, Cryptogram, SecureChannel},
         Id,
     },
 };
 
+/// Maximum number of concurrent sessions supported by the `MockHsm`
+pub(crate) const MAX_SESSIONS: u8 = 16;
+
+/// Sessions are closed after this much time has elapsed without activity
+pub(crate) const SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Information about a session open with the `MockHsm`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SessionInfo {
+    /// ID of the session
+    pub id: Id,
+
+    /// ID of the authentication key used to open the session
+    pub authentication_key_id: object::Id,
+
+    /// Time elapsed since the last message received in the session
+    pub idle: Duration,
+}
+
 /// Session with the `MockHsm`
 pub(crate) struct HsmSession {
     /// ID of the session
     pub id: Id,
 
+    /// ID of the authentication key used to open this session
+    pub authentication_key_id: object::Id,
+
     /// Card challenge for this session
     pub card_challenge: Challenge,
 
     /// Encrypted channel
     pub channel: SecureChannel,
+
+    /// Time of the last message received in this session
+    pub last_active: Instant,
 }
 
 impl HsmSession {
     /// Create a new session
-    pub fn new(id: Id, card_challenge: Challenge, channel: SecureChannel) -> Self {
+    pub fn new(
+        id: Id,
+        authentication_key_id: object::Id,
+        card_challenge: Challenge,
+        channel: SecureChannel,
+        now: Instant,
+    ) -> Self {
         Self {
             id,
+            authentication_key_id,
             card_challenge,
             channel,
+            last_active: now,
+        }
+    }
+
+    /// Has this session been inactive for longer than the session timeout?
+    pub fn is_timed_out(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_active) >= SESSION_TIMEOUT
+    }
+
+    /// Get information about this session
+    pub fn info(&self, now: Instant) -> SessionInfo {
+        SessionInfo {
+            id: self.id,
+            authentication_key_id: self.authentication_key_id,
+            idle: now.saturating_duration_since(self.last_active),
         }
     }
 
@@ -43,8 +94,11 @@ impl HsmSession {
     }
 
     /// Decrypt an incoming command
-    pub fn decrypt_command(&mut self, command: command::Message) -> command::Message {
-        self.channel.decrypt_command(command).unwrap()
+    pub fn decrypt_command(
+        &mut self,
+        command: command::Message,
+    ) -> Result<command::Message, session::Error> {
+        self.channel.decrypt_command(command)
     }
 
     /// Encrypt an outgoing response
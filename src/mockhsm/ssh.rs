@@ -0,0 +1,373 @@
+//! SSH certificate signing in the `MockHsm`
+//!
+//! Templates are parsed as the TLV structure used by `yubihsm-shell`: a 1-byte
+//! tag, a 2-byte big endian length, and the value. The `MockHsm` checks the
+//! RSA-2048 timestamp signature on each request, that the CA key is in the
+//! allowlist of the template and that the validity period of the certificate
+//! is within the timestamp window of the template. Principal constraints
+//! aren't checked.
+
+use super::{object::Payload, Error, ErrorKind};
+use crate::{algorithm::Algorithm, asymmetric, ecdsa, object, rsa};
+use ::rsa::{pkcs1v15, traits::PublicKeyParts, BigUint, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use signature::{SignatureEncoding, Signer, Verifier};
+
+/// Template tag for the algorithm of the timestamp key
+const TEMPLATE_TAG_TIMESTAMP_KEY_ALGORITHM: u8 = 1;
+
+/// Template tag for the modulus of the RSA timestamp public key
+const TEMPLATE_TAG_TIMESTAMP_PUBLIC_KEY: u8 = 2;
+
+/// Template tag for the allowlist of CA key IDs
+const TEMPLATE_TAG_CA_KEYS_ALLOWLIST: u8 = 3;
+
+/// Template tag for how many seconds before the timestamp certificates may
+/// become valid
+const TEMPLATE_TAG_NOT_BEFORE: u8 = 4;
+
+/// Template tag for how many seconds after the timestamp certificates may
+/// stay valid
+const TEMPLATE_TAG_NOT_AFTER: u8 = 5;
+
+/// Public exponent of the timestamp key
+const TIMESTAMP_KEY_EXPONENT: u32 = 65537;
+
+/// Parsed SSH certificate template
+pub(crate) struct Template {
+    /// Key the timestamp signature on requests is verified with
+    timestamp_key: RsaPublicKey,
+
+    /// CA keys which may sign certificates with this template
+    ca_keys: Vec<object::Id>,
+
+    /// Seconds before the timestamp certificates may become valid
+    not_before: Option<u32>,
+
+    /// Seconds after the timestamp certificates may stay valid
+    not_after: Option<u32>,
+}
+
+impl Template {
+    /// Parse an SSH certificate template
+    pub fn parse(template: &[u8]) -> Result<Self, Error> {
+        let mut remaining = template;
+        let mut timestamp_key_algorithm = None;
+        let mut timestamp_key = None;
+        let mut ca_keys = None;
+        let mut not_before = None;
+        let mut not_after = None;
+
+        while !remaining.is_empty() {
+            ensure!(
+                remaining.len() >= 3,
+                ErrorKind::TemplateInvalid,
+                "truncated SSH template entry"
+            );
+
+            let tag = remaining[0];
+            let length = u16::from_be_bytes([remaining[1], remaining[2]]) as usize;
+
+            ensure!(
+                remaining.len() >= 3 + length,
+                ErrorKind::TemplateInvalid,
+                "truncated SSH template value (tag {})",
+                tag
+            );
+
+            let value = &remaining[3..3 + length];
+            remaining = &remaining[3 + length..];
+
+            match tag {
+                TEMPLATE_TAG_TIMESTAMP_KEY_ALGORITHM => {
+                    ensure!(
+                        value.len() == 1,
+                        ErrorKind::TemplateInvalid,
+                        "malformed timestamp key algorithm"
+                    );
+
+                    timestamp_key_algorithm = Some(value[0]);
+                }
+                TEMPLATE_TAG_TIMESTAMP_PUBLIC_KEY => {
+                    timestamp_key = Some(
+                        RsaPublicKey::new(
+                            BigUint::from_bytes_be(value),
+                            BigUint::from(TIMESTAMP_KEY_EXPONENT),
+                        )
+                        .map_err(|e| {
+                            format_err!(ErrorKind::TemplateInvalid, "bad timestamp key: {}", e)
+                        })?,
+                    );
+                }
+                TEMPLATE_TAG_CA_KEYS_ALLOWLIST => {
+                    ensure!(
+                        value.len() % 2 == 0,
+                        ErrorKind::TemplateInvalid,
+                        "malformed CA key allowlist"
+                    );
+
+                    ca_keys = Some(
+                        value
+                            .chunks(2)
+                            .map(|id| u16::from_be_bytes([id[0], id[1]]))
+                            .collect(),
+                    );
+                }
+                TEMPLATE_TAG_NOT_BEFORE => not_before = Some(parse_u32(tag, value)?),
+                TEMPLATE_TAG_NOT_AFTER => not_after = Some(parse_u32(tag, value)?),
+                _ => (),
+            }
+        }
+
+        ensure!(
+            timestamp_key_algorithm == Some(asymmetric::Algorithm::Rsa2048 as u8),
+            ErrorKind::TemplateInvalid,
+            "SSH template has no RSA-2048 timestamp key"
+        );
+
+        let timestamp_key = timestamp_key.ok_or_else(|| {
+            format_err!(
+                ErrorKind::TemplateInvalid,
+                "SSH template has no timestamp public key"
+            )
+        })?;
+
+        ensure!(
+            timestamp_key.size() == asymmetric::Algorithm::Rsa2048.key_len(),
+            ErrorKind::TemplateInvalid,
+            "SSH template timestamp key isn't RSA-2048"
+        );
+
+        let ca_keys = ca_keys.ok_or_else(|| {
+            format_err!(
+                ErrorKind::TemplateInvalid,
+                "SSH template has no CA key allowlist"
+            )
+        })?;
+
+        Ok(Self {
+            timestamp_key,
+            ca_keys,
+            not_before,
+            not_after,
+        })
+    }
+
+    /// Ensure this template permits signing the given request with the given
+    /// CA key
+    pub fn check_request(
+        &self,
+        key_id: object::Id,
+        timestamp: u32,
+        signature: &[u8],
+        request: &[u8],
+    ) -> Result<(), Error> {
+        ensure!(
+            self.ca_keys.contains(&key_id),
+            ErrorKind::AccessDenied,
+            "key 0x{:04x} is not an allowed CA key",
+            key_id
+        );
+
+        let signature = pkcs1v15::Signature::try_from(signature)
+            .map_err(|e| format_err!(ErrorKind::CryptoError, "bad timestamp signature: {}", e))?;
+
+        let mut message = timestamp.to_be_bytes().to_vec();
+        message.extend_from_slice(request);
+
+        pkcs1v15::VerifyingKey::<Sha256>::new(self.timestamp_key.clone())
+            .verify(&message, &signature)
+            .map_err(|e| format_err!(ErrorKind::AccessDenied, "bad timestamp signature: {}", e))?;
+
+        let (valid_after, valid_before) = validity(request)?;
+
+        if let Some(not_before) = self.not_before {
+            ensure!(
+                valid_after >= u64::from(timestamp.saturating_sub(not_before)),
+                ErrorKind::AccessDenied,
+                "certificate is valid from {}, before the template allows",
+                valid_after
+            );
+        }
+
+        if let Some(not_after) = self.not_after {
+            ensure!(
+                valid_before <= u64::from(timestamp) + u64::from(not_after),
+                ErrorKind::AccessDenied,
+                "certificate is valid until {}, after the template allows",
+                valid_before
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a big endian `u32` template value
+fn parse_u32(tag: u8, value: &[u8]) -> Result<u32, Error> {
+    value.try_into().map(u32::from_be_bytes).map_err(|_| {
+        format_err!(
+            ErrorKind::TemplateInvalid,
+            "malformed SSH template value (tag {})",
+            tag
+        )
+    })
+}
+
+/// Get the `valid after` and `valid before` fields of an SSH certificate
+/// request (the certificate without its signature, see `PROTOCOL.certkeys`
+/// in OpenSSH)
+fn validity(request: &[u8]) -> Result<(u64, u64), Error> {
+    let mut reader = Reader(request);
+    let key_type = reader.string()?;
+
+    // Number of fields holding the public key
+    let key_fields = match key_type {
+        b"ssh-ed25519-cert-v01@openssh.com" => 1,
+        b"ssh-rsa-cert-v01@openssh.com"
+        | b"ecdsa-sha2-nistp256-cert-v01@openssh.com"
+        | b"ecdsa-sha2-nistp384-cert-v01@openssh.com"
+        | b"ecdsa-sha2-nistp521-cert-v01@openssh.com" => 2,
+        b"ssh-dss-cert-v01@openssh.com" => 4,
+        other => fail!(
+            ErrorKind::CryptoError,
+            "unsupported SSH certificate type: {}",
+            String::from_utf8_lossy(other)
+        ),
+    };
+
+    // Nonce and public key
+    for _ in 0..=key_fields {
+        reader.string()?;
+    }
+
+    // Serial, type, key ID and principals
+    reader.take(8 + 4)?;
+    reader.string()?;
+    reader.string()?;
+
+    let valid_after = u64::from_be_bytes(reader.take(8)?.try_into().unwrap());
+    let valid_before = u64::from_be_bytes(reader.take(8)?.try_into().unwrap());
+    Ok((valid_after, valid_before))
+}
+
+/// Reader for SSH wire format data
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Read the given number of bytes
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        ensure!(
+            self.0.len() >= len,
+            ErrorKind::CryptoError,
+            "truncated SSH certificate request"
+        );
+
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// Read an SSH `string` (RFC 4251 Section 5)
+    fn string(&mut self) -> Result<&'a [u8], Error> {
+        let len = u32::from_be_bytes(self.take(4)?.try_into().unwrap());
+        self.take(len as usize)
+    }
+}
+
+/// Sign an SSH certificate request, returning the request with the
+/// signature appended in SSH wire format
+pub(crate) fn sign_certificate(
+    key: &Payload,
+    algorithm: Algorithm,
+    request: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let (signature_type, signature_blob) = match (key, algorithm) {
+        (Payload::EcdsaNistP256(secret_key), Algorithm::Ecdsa(ecdsa::Algorithm::Sha256)) => {
+            let signature: p256::ecdsa::Signature =
+                p256::ecdsa::SigningKey::from(secret_key).sign(request);
+            let (r, s) = signature.split_bytes();
+            ("ecdsa-sha2-nistp256", ecdsa_signature_blob(&r, &s))
+        }
+        (Payload::EcdsaNistP384(secret_key), Algorithm::Ecdsa(ecdsa::Algorithm::Sha384)) => {
+            let signature: p384::ecdsa::Signature =
+                p384::ecdsa::SigningKey::from(secret_key).sign(request);
+            let (r, s) = signature.split_bytes();
+            ("ecdsa-sha2-nistp384", ecdsa_signature_blob(&r, &s))
+        }
+        (Payload::EcdsaNistP521(secret_key), Algorithm::Ecdsa(ecdsa::Algorithm::Sha512)) => {
+            let signature: p521::ecdsa::Signature =
+                p521::ecdsa::SigningKey::from(secret_key).sign(request);
+            let (r, s) = signature.split_bytes();
+            ("ecdsa-sha2-nistp521", ecdsa_signature_blob(&r, &s))
+        }
+        (Payload::RsaKey(private_key), Algorithm::Rsa(rsa::Algorithm::Pkcs1(alg))) => match alg {
+            rsa::pkcs1::Algorithm::Sha1 => (
+                "ssh-rsa",
+                pkcs1v15::SigningKey::<Sha1>::new(private_key.clone())
+                    .sign(request)
+                    .to_vec(),
+            ),
+            rsa::pkcs1::Algorithm::Sha256 => (
+                "rsa-sha2-256",
+                pkcs1v15::SigningKey::<Sha256>::new(private_key.clone())
+                    .sign(request)
+                    .to_vec(),
+            ),
+            rsa::pkcs1::Algorithm::Sha512 => (
+                "rsa-sha2-512",
+                pkcs1v15::SigningKey::<Sha512>::new(private_key.clone())
+                    .sign(request)
+                    .to_vec(),
+            ),
+            other => fail!(
+                ErrorKind::CryptoError,
+                "unsupported SSH signature algorithm: {:?}",
+                other
+            ),
+        },
+        (key, algorithm) => fail!(
+            ErrorKind::CryptoError,
+            "can't sign SSH certificate using {:?} with a {:?} key",
+            algorithm,
+            key.algorithm()
+        ),
+    };
+
+    let mut signature = vec![];
+    put_string(&mut signature, signature_type.as_bytes());
+    put_string(&mut signature, &signature_blob);
+
+    let mut certificate = request.to_vec();
+    put_string(&mut certificate, &signature);
+    Ok(certificate)
+}
+
+/// Encode an ECDSA signature as an SSH signature blob (RFC 5656 Section 3.1.2)
+fn ecdsa_signature_blob(r: &[u8], s: &[u8]) -> Vec<u8> {
+    let mut blob = vec![];
+    put_mpint(&mut blob, r);
+    put_mpint(&mut blob, s);
+    blob
+}
+
+/// Append an SSH `string` (RFC 4251 Section 5)
+fn put_string(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+/// Append an unsigned big endian integer as an SSH `mpint` (RFC 4251 Section 5)
+fn put_mpint(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    let bytes = &bytes[start..];
+
+    if bytes.first().is_some_and(|&b| b & 0x80 != 0) {
+        buffer.extend_from_slice(&(bytes.len() as u32 + 1).to_be_bytes());
+        buffer.push(0);
+        buffer.extend_from_slice(bytes);
+    } else {
+        put_string(buffer, bytes);
+    }
+}
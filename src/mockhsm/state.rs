@@ -1,10 +1,17 @@
 //! `MockHsm` presents a thread-safe API by locking interior mutable state,
 //! contained in the `State` struct defined in this module.
 
-use super::{audit::CommandAuditOptions, object::Objects, session::HsmSession};
+use super::{
+    audit::{AuditLog, CommandAuditOptions},
+    clock::Clock,
+    firmware,
+    object::Objects,
+    rng::Rng,
+    session::{HsmSession, SessionInfo, MAX_SESSIONS},
+};
 use crate::{
     audit::AuditOption,
-    connector, object,
+    command, device, object, response,
     session::{
         self,
         securechannel::{Challenge, SecureChannel},
@@ -18,6 +25,9 @@ pub(crate) struct State {
     /// Command-specific audit options
     pub(super) command_audit_options: CommandAuditOptions,
 
+    /// Log of audited commands
+    pub(super) audit_log: AuditLog,
+
     /// Don't allow command to be performed until log data has been consumed
     /// via the `SetLogIndex` command.
     pub(super) force_audit: AuditOption,
@@ -25,76 +35,160 @@ pub(crate) struct State {
     /// Fips mode
     pub(super) fips: AuditOption,
 
+    /// Identity of the device: serial number, firmware version, and
+    /// supported algorithms
+    pub(super) device_info: device::Info,
+
     /// Active sessions with the MockHsm
     sessions: BTreeMap<session::Id, HsmSession>,
 
+    /// Clock used to time out inactive sessions
+    pub(super) clock: Clock,
+
     /// Objects within the MockHsm (i.e. keys)
     pub(super) objects: Objects,
+
+    /// Source of randomness for keys, nonces, and challenges
+    pub(super) rng: Rng,
 }
 
 impl State {
     /// Create a new instance of the server's mutable interior state
-    pub fn new() -> Self {
+    pub fn new(device_info: device::Info, mut rng: Rng) -> Self {
+        let objects = Objects::new(
+            device_info.serial_number,
+            device_info.algorithms.clone(),
+            &mut rng,
+        );
+
+        let clock = Clock::default();
+        let audit_log = AuditLog::new(clock.now());
+
         Self {
             command_audit_options: CommandAuditOptions::default(),
+            audit_log,
             force_audit: AuditOption::Off,
             fips: AuditOption::Off,
+            device_info,
             sessions: BTreeMap::new(),
-            objects: Objects::default(),
+            clock,
+            objects,
+            rng,
         }
     }
 
+    /// Firmware version of the emulated device
+    pub fn firmware_version(&self) -> firmware::Version {
+        (
+            self.device_info.major_version,
+            self.device_info.minor_version,
+            self.device_info.build_version,
+        )
+    }
+
     /// Create a new session with the MockHsm
     pub fn create_session(
         &mut self,
         authentication_key_id: object::Id,
         host_challenge: Challenge,
-    ) -> &HsmSession {
+    ) -> Result<&HsmSession, device::ErrorKind> {
+        self.close_timed_out_sessions();
+
+        // Use the lowest session ID which isn't presently in use
+        let session_id = (0..MAX_SESSIONS)
+            .map(|id| session::Id::from_u8(id).unwrap())
+            .find(|id| !self.sessions.contains_key(id))
+            .ok_or(device::ErrorKind::SessionsFull)?;
+
         // Generate a random card challenge to send back to the client
-        let card_challenge = Challenge::new();
+        let card_challenge = Challenge::from_rng(&mut self.rng);
+
+        let authentication_key = self
+            .objects
+            .get(authentication_key_id, object::Type::AuthenticationKey)
+            .and_then(|obj| obj.payload.authentication_key())
+            .ok_or(device::ErrorKind::ObjectNotFound)?;
+
+        let channel = SecureChannel::new(
+            session_id,
+            authentication_key,
+            host_challenge,
+            card_challenge,
+        );
+
+        let session = HsmSession::new(
+            session_id,
+            authentication_key_id,
+            card_challenge,
+            channel,
+            self.clock.now(),
+        );
+        assert!(self.sessions.insert(session_id, session).is_none());
+
+        Ok(&self.sessions[&session_id])
+    }
+
+    /// Obtain the channel for a session by its ID, recording activity on it
+    pub fn get_session(&mut self, id: session::Id) -> Result<&mut HsmSession, device::ErrorKind> {
+        self.close_timed_out_sessions();
 
-        let session_id = self
+        let now = self.clock.now();
+        let session = self
             .sessions
-            .keys()
-            .max()
-            .map(|id| id.succ().expect("session count exceeded"))
-            .unwrap_or_else(|| session::Id::from_u8(0).unwrap());
-
-        let channel = {
-            let authentication_key_obj = self
-                .objects
-                .get(authentication_key_id, object::Type::AuthenticationKey)
-                .unwrap_or_else(|| {
-                    panic!("MockHsm has no authentication::Key in slot {authentication_key_id:?}")
-                });
-
-            SecureChannel::new(
-                session_id,
-                authentication_key_obj
-                    .payload
-                    .authentication_key()
-                    .expect("auth key payload"),
-                host_challenge,
-                card_challenge,
+            .get_mut(&id)
+            .ok_or(device::ErrorKind::InvalidSession)?;
+
+        session.last_active = now;
+        Ok(session)
+    }
+
+    /// Is the given command refused because forced auditing is enabled and
+    /// the audit log is full? Draining the log is always allowed.
+    pub fn is_log_full_for(&self, command_type: command::Code) -> bool {
+        self.force_audit != AuditOption::Off
+            && self.command_audit_options.get(command_type) != AuditOption::Off
+            && !matches!(
+                command_type,
+                command::Code::GetLogEntries | command::Code::SetLogIndex
             )
-        };
+            && self.audit_log.is_full()
+    }
 
-        let session = HsmSession::new(session_id, card_challenge, channel);
-        assert!(self.sessions.insert(session_id, session).is_none());
+    /// Record a command performed in the given session in the audit log,
+    /// if auditing is enabled for it
+    pub fn audit_command(
+        &mut self,
+        session_id: session::Id,
+        command: &command::Message,
+        response: &response::Message,
+    ) {
+        if self.command_audit_options.get(command.command_type) == AuditOption::Off {
+            return;
+        }
+
+        let session_key = match self.sessions.get(&session_id) {
+            Some(session) => session.authentication_key_id,
+            None => return,
+        };
 
-        self.get_session(session_id).unwrap()
+        self.audit_log.record(
+            self.clock.now(),
+            command.command_type,
+            command.data.len(),
+            session_key,
+            response,
+        );
     }
 
-    /// Obtain the channel for a session by its ID
-    pub fn get_session(&mut self, id: session::Id) -> Result<&mut HsmSession, connector::Error> {
-        self.sessions.get_mut(&id).ok_or_else(|| {
-            format_err!(
-                connector::ErrorKind::RequestError,
-                "invalid session ID: {:?}",
-                id
-            )
-            .into()
-        })
+    /// Get information about the sessions which haven't timed out
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        let now = self.clock.now();
+
+        self.sessions
+            .values()
+            .filter(|session| !session.is_timed_out(now))
+            .map(|session| session.info(now))
+            .collect()
     }
 
     /// Close an active session
@@ -102,10 +196,17 @@ impl State {
         assert!(self.sessions.remove(&id).is_some());
     }
 
+    /// Close all sessions which have exceeded the inactivity timeout
+    fn close_timed_out_sessions(&mut self) {
+        let now = self.clock.now();
+        self.sessions
+            .retain(|_, session| !session.is_timed_out(now));
+    }
+
     /// Reset the internal HSM state, closing all connections
     pub fn reset(&mut self) {
         self.command_audit_options = CommandAuditOptions::default();
         self.sessions = BTreeMap::new();
-        self.objects = Objects::default();
+        self.objects.reset();
     }
 }
@@ -13,6 +13,8 @@ mod info;
 mod label;
 mod origins;
 pub mod put;
+mod query;
+pub mod typed;
 mod types;
 
 pub use self::{
@@ -23,6 +25,7 @@ pub use self::{
     info::Info,
     label::{Label, LABEL_SIZE},
     origins::Origin,
+    query::Query,
     types::Type,
 };
 
@@ -0,0 +1,181 @@
+//! Queries for finding objects by their attributes, including ones the
+//! device can't filter on (e.g. label prefixes).
+//!
+//! Use [`Client::find_objects`](crate::Client::find_objects) to run a query.
+
+use super::{Filter, Info, Label, Origin, Type, LABEL_SIZE};
+use crate::{Algorithm, Capability, Domain};
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+/// Query selecting objects by their attributes.
+///
+/// Criteria which the device supports are sent along with the `ListObjects`
+/// command, and the rest are applied to the information about each object
+/// it returns. All criteria must match for an object to be selected.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    /// Label criteria
+    label: Option<LabelMatch>,
+
+    /// Object types (any of which match)
+    types: Vec<Type>,
+
+    /// Algorithm of the object
+    algorithm: Option<Algorithm>,
+
+    /// Domains the object must be accessible from
+    domains: Option<Domain>,
+
+    /// Capabilities the object must have
+    capabilities: Capability,
+
+    /// Origins (any of which match)
+    origins: Vec<Origin>,
+}
+
+impl Query {
+    /// Create a query which selects every object
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select objects with exactly the given label
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(LabelMatch::Exact(label.into()));
+        self
+    }
+
+    /// Select objects whose label starts with the given prefix
+    pub fn label_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.label = Some(LabelMatch::Prefix(prefix.into()));
+        self
+    }
+
+    /// Select objects whose label matches the given regular expression
+    #[cfg(feature = "regex")]
+    pub fn label_regex(mut self, regex: Regex) -> Self {
+        self.label = Some(LabelMatch::Regex(regex));
+        self
+    }
+
+    /// Select objects of the given type. Can be called more than once to
+    /// select objects of any of several types.
+    pub fn object_type(mut self, object_type: Type) -> Self {
+        if !self.types.contains(&object_type) {
+            self.types.push(object_type);
+        }
+
+        self
+    }
+
+    /// Select objects intended for use with the given algorithm
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    /// Select objects accessible from all of the given domains
+    pub fn domains(mut self, domains: Domain) -> Self {
+        self.domains = Some(domains);
+        self
+    }
+
+    /// Select objects which have all of the given capabilities
+    pub fn capabilities(mut self, capabilities: Capability) -> Self {
+        self.capabilities |= capabilities;
+        self
+    }
+
+    /// Select objects with the given origin. Can be called more than once to
+    /// select objects with any of several origins.
+    pub fn origin(mut self, origin: Origin) -> Self {
+        if !self.origins.contains(&origin) {
+            self.origins.push(origin);
+        }
+
+        self
+    }
+
+    /// Filters for the criteria the device can apply itself
+    pub fn filters(&self) -> Vec<Filter> {
+        let mut filters = vec![];
+
+        if let [object_type] = self.types.as_slice() {
+            filters.push(Filter::Type(*object_type));
+        }
+
+        if let Some(algorithm) = self.algorithm {
+            filters.push(Filter::Algorithm(algorithm));
+        }
+
+        if let Some(domains) = self.domains {
+            filters.push(Filter::Domains(domains));
+        }
+
+        if !self.capabilities.is_empty() {
+            filters.push(Filter::Capabilities(self.capabilities));
+        }
+
+        if let Some(LabelMatch::Exact(label)) = &self.label {
+            if label.len() <= LABEL_SIZE {
+                filters.push(Filter::Label(Label::from_bytes(label.as_bytes()).unwrap()));
+            }
+        }
+
+        filters
+    }
+
+    /// Is an object of the given type possibly selected by this query?
+    pub fn matches_type(&self, object_type: Type) -> bool {
+        self.types.is_empty() || self.types.contains(&object_type)
+    }
+
+    /// Does this query select the object with the given information?
+    pub fn matches(&self, info: &Info) -> bool {
+        self.matches_type(info.object_type)
+            && self.algorithm.map_or(true, |alg| info.algorithm == alg)
+            && self
+                .domains
+                .map_or(true, |domains| info.domains.contains(domains))
+            && info.capabilities.contains(self.capabilities)
+            && (self.origins.is_empty() || self.origins.contains(&info.origin))
+            && self
+                .label
+                .as_ref()
+                .map_or(true, |label| label.matches(&info.label))
+    }
+}
+
+/// Criteria for matching labels
+#[derive(Clone, Debug)]
+enum LabelMatch {
+    /// Label is exactly the given string
+    Exact(String),
+
+    /// Label starts with the given string
+    Prefix(String),
+
+    /// Label matches the given regular expression
+    #[cfg(feature = "regex")]
+    Regex(Regex),
+}
+
+impl LabelMatch {
+    /// Does the given label match?
+    fn matches(&self, label: &Label) -> bool {
+        // Labels which aren't valid UTF-8 never match
+        let label = match label.try_as_str() {
+            Ok(label) => label,
+            Err(_) => return false,
+        };
+
+        match self {
+            LabelMatch::Exact(s) => label == s,
+            LabelMatch::Prefix(prefix) => label.starts_with(prefix.as_str()),
+            #[cfg(feature = "regex")]
+            LabelMatch::Regex(regex) => regex.is_match(label),
+        }
+    }
+}
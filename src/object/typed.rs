@@ -0,0 +1,611 @@
+//! Typed object handles: references to objects in the HSM whose type (and
+//! for keys, algorithm) is known at compile time, exposing only the
+//! operations which are valid for them.
+//!
+//! Handles are obtained by generating or putting an object, or by resolving
+//! an existing object by ID or label, which checks the object's type and
+//! algorithm with `GetObjectInfo`:
+//!
+//! ```no_run
+//! use yubihsm::{ecdsa::NistP256, object::typed::AsymmetricKey, Client};
+//! # fn example(client: Client) -> Result<(), yubihsm::client::Error> {
+//! let key = AsymmetricKey::<NistP256>::resolve(client, 100)?;
+//! let signature = key.sign_prehash_raw([0u8; 32])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    asymmetric::{self, PublicKey},
+    client::{Client, Error, ErrorKind},
+    ecdsa::{self, algorithm::CurveAlgorithm, NistP256, NistP384, NistP521},
+    ed25519, hmac, object, opaque, rsa, wrap, Algorithm, Capability, Domain,
+};
+use ::ecdsa::{
+    elliptic_curve::{
+        point::PointCompression,
+        sec1::{FromEncodedPoint, ModulusSize, ToEncodedPoint},
+        AffinePoint, CurveArithmetic, FieldBytesSize,
+    },
+    EcdsaCurve,
+};
+use std::marker::PhantomData;
+
+#[cfg(feature = "secp256k1")]
+use crate::ecdsa::Secp256k1;
+
+/// Objects referenced by typed handles
+pub trait TypedHandle {
+    /// Type of the object
+    const OBJECT_TYPE: object::Type;
+
+    /// Get the ID of the object
+    fn id(&self) -> object::Id;
+
+    /// Get the handle of the object
+    fn handle(&self) -> object::Handle {
+        object::Handle::new(self.id(), Self::OBJECT_TYPE)
+    }
+}
+
+/// Algorithms of asymmetric keys, identified by their curve (e.g.
+/// [`NistP256`]) or by the marker types in this module (e.g. [`Rsa2048`])
+pub trait AsymmetricAlgorithm {
+    /// YubiHSM asymmetric algorithm for keys of this type
+    fn asymmetric_algorithm() -> asymmetric::Algorithm;
+}
+
+/// RSA keys of a particular size
+pub trait RsaAlgorithm: AsymmetricAlgorithm {}
+
+/// Algorithms of HMAC keys, identified by their hash function (e.g.
+/// `sha2::Sha256`)
+pub trait HmacAlgorithm {
+    /// YubiHSM HMAC algorithm for keys of this type
+    fn hmac_algorithm() -> hmac::Algorithm;
+}
+
+/// Ed25519 keys
+#[derive(Copy, Clone, Debug)]
+pub struct Ed25519;
+
+/// 2048-bit RSA keys
+#[derive(Copy, Clone, Debug)]
+pub struct Rsa2048;
+
+/// 3072-bit RSA keys
+#[derive(Copy, Clone, Debug)]
+pub struct Rsa3072;
+
+/// 4096-bit RSA keys
+#[derive(Copy, Clone, Debug)]
+pub struct Rsa4096;
+
+macro_rules! impl_asymmetric_algorithm {
+    ($type:ty, $algorithm:ident) => {
+        impl AsymmetricAlgorithm for $type {
+            fn asymmetric_algorithm() -> asymmetric::Algorithm {
+                asymmetric::Algorithm::$algorithm
+            }
+        }
+    };
+}
+
+impl_asymmetric_algorithm!(NistP256, EcP256);
+impl_asymmetric_algorithm!(NistP384, EcP384);
+impl_asymmetric_algorithm!(NistP521, EcP521);
+#[cfg(feature = "secp256k1")]
+impl_asymmetric_algorithm!(Secp256k1, EcK256);
+impl_asymmetric_algorithm!(Ed25519, Ed25519);
+impl_asymmetric_algorithm!(Rsa2048, Rsa2048);
+impl_asymmetric_algorithm!(Rsa3072, Rsa3072);
+impl_asymmetric_algorithm!(Rsa4096, Rsa4096);
+
+impl RsaAlgorithm for Rsa2048 {}
+impl RsaAlgorithm for Rsa3072 {}
+impl RsaAlgorithm for Rsa4096 {}
+
+macro_rules! impl_hmac_algorithm {
+    ($type:ty, $algorithm:ident) => {
+        impl HmacAlgorithm for $type {
+            fn hmac_algorithm() -> hmac::Algorithm {
+                hmac::Algorithm::$algorithm
+            }
+        }
+    };
+}
+
+impl_hmac_algorithm!(sha1::Sha1, Sha1);
+impl_hmac_algorithm!(sha2::Sha256, Sha256);
+impl_hmac_algorithm!(sha2::Sha384, Sha384);
+impl_hmac_algorithm!(sha2::Sha512, Sha512);
+
+/// Asymmetric key of algorithm `A` (e.g. `AsymmetricKey<NistP256>`)
+pub struct AsymmetricKey<A: AsymmetricAlgorithm> {
+    /// Client for the HSM holding the key
+    client: Client,
+
+    /// ID of the key
+    id: object::Id,
+
+    /// Algorithm of the key
+    algorithm: PhantomData<fn() -> A>,
+}
+
+impl<A: AsymmetricAlgorithm> AsymmetricKey<A> {
+    /// Generate a new key in the HSM
+    pub fn generate(
+        client: Client,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+    ) -> Result<Self, Error> {
+        let id = client.generate_asymmetric_key(
+            key_id,
+            label,
+            domains,
+            capabilities,
+            A::asymmetric_algorithm(),
+        )?;
+
+        Ok(Self::new(client, id))
+    }
+
+    /// Put an existing private key into the HSM
+    pub fn put<K: Into<Vec<u8>>>(
+        client: Client,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        key_bytes: K,
+    ) -> Result<Self, Error> {
+        let id = client.put_asymmetric_key(
+            key_id,
+            label,
+            domains,
+            capabilities,
+            A::asymmetric_algorithm(),
+            key_bytes,
+        )?;
+
+        Ok(Self::new(client, id))
+    }
+
+    /// Resolve the key with the given ID, checking its algorithm
+    pub fn resolve(client: Client, key_id: object::Id) -> Result<Self, Error> {
+        let info = client.get_object_info(key_id, Self::OBJECT_TYPE)?;
+        check_algorithm(&info, A::asymmetric_algorithm())?;
+        Ok(Self::new(client, key_id))
+    }
+
+    /// Resolve the key with the given label, checking its algorithm
+    pub fn resolve_by_label(client: Client, label: &str) -> Result<Self, Error> {
+        let info = client.find_object(label, Self::OBJECT_TYPE)?;
+        check_algorithm(&info, A::asymmetric_algorithm())?;
+        Ok(Self::new(client, info.object_id))
+    }
+
+    /// Get information about the key
+    pub fn info(&self) -> Result<object::Info, Error> {
+        self.client.get_object_info(self.id, Self::OBJECT_TYPE)
+    }
+
+    /// Get the public key of the key
+    pub fn public_key(&self) -> Result<PublicKey, Error> {
+        self.client.get_public_key(self.id)
+    }
+
+    /// Obtain an X.509 attestation certificate for the key, signed by the
+    /// given attestation key (or the device's default attestation key)
+    pub fn attest(
+        &self,
+        attestation_key_id: Option<object::Id>,
+    ) -> Result<crate::attestation::Certificate, Error> {
+        self.client
+            .sign_attestation_certificate(self.id, attestation_key_id)
+    }
+
+    /// Delete the key from the HSM
+    pub fn delete(self) -> Result<(), Error> {
+        self.client.delete_object(self.id, Self::OBJECT_TYPE)
+    }
+
+    /// Create a handle to the key with the given ID
+    fn new(client: Client, id: object::Id) -> Self {
+        Self {
+            client,
+            id,
+            algorithm: PhantomData,
+        }
+    }
+}
+
+impl<C> AsymmetricKey<C>
+where
+    C: AsymmetricAlgorithm + CurveAlgorithm,
+{
+    /// Compute an ECDSA signature of the given digest, returning it
+    /// DER-encoded
+    pub fn sign_prehash_raw<T: Into<Vec<u8>>>(&self, digest: T) -> Result<Vec<u8>, Error> {
+        self.client.sign_ecdsa_prehash_raw(self.id, digest)
+    }
+}
+
+impl<C> AsymmetricKey<C>
+where
+    C: AsymmetricAlgorithm + CurveAlgorithm + EcdsaCurve + CurveArithmetic + PointCompression,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    /// Create an ECDSA signer for the key
+    pub fn signer(&self) -> Result<ecdsa::Signer<C>, signature::Error> {
+        ecdsa::Signer::create(self.client.clone(), self.id)
+    }
+}
+
+impl AsymmetricKey<Ed25519> {
+    /// Compute an Ed25519 signature of the given data
+    pub fn sign<T: Into<Vec<u8>>>(&self, data: T) -> Result<ed25519::Signature, Error> {
+        self.client.sign_ed25519(self.id, data)
+    }
+
+    /// Create an Ed25519 signer for the key
+    pub fn signer(&self) -> Result<ed25519::Signer, signature::Error> {
+        ed25519::Signer::create(self.client.clone(), self.id)
+    }
+}
+
+impl<R: RsaAlgorithm> AsymmetricKey<R> {
+    /// Compute an RSASSA-PKCS#1v1.5 signature of the SHA-256 hash of the
+    /// given data
+    pub fn sign_pkcs1v15_sha256(&self, data: &[u8]) -> Result<rsa::pkcs1::Signature, Error> {
+        self.client.sign_rsa_pkcs1v15_sha256(self.id, data)
+    }
+
+    /// Compute an RSASSA-PSS signature of the SHA-256 hash of the given data
+    pub fn sign_pss_sha256(&self, data: &[u8]) -> Result<rsa::pss::Signature, Error> {
+        self.client.sign_rsa_pss_sha256(self.id, data)
+    }
+
+    /// Decrypt data encrypted with RSA-OAEP
+    pub fn decrypt_oaep<T: Into<Vec<u8>>>(
+        &self,
+        mgf1_hash_alg: rsa::mgf::Algorithm,
+        data: T,
+        label_hash: Vec<u8>,
+    ) -> Result<rsa::oaep::DecryptedData, Error> {
+        self.client
+            .decrypt_oaep(self.id, mgf1_hash_alg, data, label_hash)
+    }
+}
+
+impl<A: AsymmetricAlgorithm> Clone for AsymmetricKey<A> {
+    fn clone(&self) -> Self {
+        Self::new(self.client.clone(), self.id)
+    }
+}
+
+impl<A: AsymmetricAlgorithm> TypedHandle for AsymmetricKey<A> {
+    const OBJECT_TYPE: object::Type = object::Type::AsymmetricKey;
+
+    fn id(&self) -> object::Id {
+        self.id
+    }
+}
+
+/// HMAC key using hash function `D` (e.g. `HmacKey<sha2::Sha256>`)
+pub struct HmacKey<D: HmacAlgorithm> {
+    /// Client for the HSM holding the key
+    client: Client,
+
+    /// ID of the key
+    id: object::Id,
+
+    /// Algorithm of the key
+    algorithm: PhantomData<fn() -> D>,
+}
+
+impl<D: HmacAlgorithm> HmacKey<D> {
+    /// Generate a new key in the HSM
+    pub fn generate(
+        client: Client,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+    ) -> Result<Self, Error> {
+        let id =
+            client.generate_hmac_key(key_id, label, domains, capabilities, D::hmac_algorithm())?;
+
+        Ok(Self::new(client, id))
+    }
+
+    /// Put an existing key into the HSM
+    pub fn put<K: Into<Vec<u8>>>(
+        client: Client,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        key_bytes: K,
+    ) -> Result<Self, Error> {
+        let id = client.put_hmac_key(
+            key_id,
+            label,
+            domains,
+            capabilities,
+            D::hmac_algorithm(),
+            key_bytes,
+        )?;
+
+        Ok(Self::new(client, id))
+    }
+
+    /// Resolve the key with the given ID, checking its algorithm
+    pub fn resolve(client: Client, key_id: object::Id) -> Result<Self, Error> {
+        let info = client.get_object_info(key_id, Self::OBJECT_TYPE)?;
+        check_algorithm(&info, D::hmac_algorithm())?;
+        Ok(Self::new(client, key_id))
+    }
+
+    /// Resolve the key with the given label, checking its algorithm
+    pub fn resolve_by_label(client: Client, label: &str) -> Result<Self, Error> {
+        let info = client.find_object(label, Self::OBJECT_TYPE)?;
+        check_algorithm(&info, D::hmac_algorithm())?;
+        Ok(Self::new(client, info.object_id))
+    }
+
+    /// Get information about the key
+    pub fn info(&self) -> Result<object::Info, Error> {
+        self.client.get_object_info(self.id, Self::OBJECT_TYPE)
+    }
+
+    /// Compute an HMAC tag of the given message
+    pub fn sign<M: Into<Vec<u8>>>(&self, msg: M) -> Result<hmac::Tag, Error> {
+        self.client.sign_hmac(self.id, msg)
+    }
+
+    /// Verify an HMAC tag of the given message
+    pub fn verify<M, T>(&self, msg: M, tag: T) -> Result<(), Error>
+    where
+        M: Into<Vec<u8>>,
+        T: Into<hmac::Tag>,
+    {
+        self.client.verify_hmac(self.id, msg, tag)
+    }
+
+    /// Delete the key from the HSM
+    pub fn delete(self) -> Result<(), Error> {
+        self.client.delete_object(self.id, Self::OBJECT_TYPE)
+    }
+
+    /// Create a handle to the key with the given ID
+    fn new(client: Client, id: object::Id) -> Self {
+        Self {
+            client,
+            id,
+            algorithm: PhantomData,
+        }
+    }
+}
+
+impl<D: HmacAlgorithm> Clone for HmacKey<D> {
+    fn clone(&self) -> Self {
+        Self::new(self.client.clone(), self.id)
+    }
+}
+
+impl<D: HmacAlgorithm> TypedHandle for HmacKey<D> {
+    const OBJECT_TYPE: object::Type = object::Type::HmacKey;
+
+    fn id(&self) -> object::Id {
+        self.id
+    }
+}
+
+/// Wrap key, for exporting and importing objects and wrapping data
+#[derive(Clone)]
+pub struct WrapKey {
+    /// Client for the HSM holding the key
+    client: Client,
+
+    /// ID of the key
+    id: object::Id,
+}
+
+impl WrapKey {
+    /// Generate a new key in the HSM
+    pub fn generate(
+        client: Client,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        algorithm: wrap::Algorithm,
+    ) -> Result<Self, Error> {
+        let id = client.generate_wrap_key(
+            key_id,
+            label,
+            domains,
+            capabilities,
+            delegated_capabilities,
+            algorithm,
+        )?;
+
+        Ok(Self { client, id })
+    }
+
+    /// Put an existing key into the HSM
+    #[allow(clippy::too_many_arguments)]
+    pub fn put<K: Into<Vec<u8>>>(
+        client: Client,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        algorithm: wrap::Algorithm,
+        key_bytes: K,
+    ) -> Result<Self, Error> {
+        let id = client.put_wrap_key(
+            key_id,
+            label,
+            domains,
+            capabilities,
+            delegated_capabilities,
+            algorithm,
+            key_bytes,
+        )?;
+
+        Ok(Self { client, id })
+    }
+
+    /// Resolve the key with the given ID, checking it's a wrap key
+    pub fn resolve(client: Client, key_id: object::Id) -> Result<Self, Error> {
+        client.get_object_info(key_id, Self::OBJECT_TYPE)?;
+        Ok(Self { client, id: key_id })
+    }
+
+    /// Resolve the key with the given label
+    pub fn resolve_by_label(client: Client, label: &str) -> Result<Self, Error> {
+        let info = client.find_object(label, Self::OBJECT_TYPE)?;
+        Ok(Self {
+            client,
+            id: info.object_id,
+        })
+    }
+
+    /// Get information about the key
+    pub fn info(&self) -> Result<object::Info, Error> {
+        self.client.get_object_info(self.id, Self::OBJECT_TYPE)
+    }
+
+    /// Export an object under this key
+    pub fn export<O: TypedHandle>(&self, object: &O) -> Result<wrap::Message, Error> {
+        self.client
+            .export_wrapped(self.id, O::OBJECT_TYPE, object.id())
+    }
+
+    /// Import an object exported under this key, returning its handle
+    pub fn import<M: Into<wrap::Message>>(&self, message: M) -> Result<object::Handle, Error> {
+        self.client.import_wrapped(self.id, message)
+    }
+
+    /// Encrypt data under this key
+    pub fn wrap_data(&self, plaintext: Vec<u8>) -> Result<wrap::Message, Error> {
+        self.client.wrap_data(self.id, plaintext)
+    }
+
+    /// Decrypt data encrypted under this key
+    pub fn unwrap_data<M: Into<wrap::Message>>(&self, message: M) -> Result<Vec<u8>, Error> {
+        self.client.unwrap_data(self.id, message)
+    }
+
+    /// Delete the key from the HSM
+    pub fn delete(self) -> Result<(), Error> {
+        self.client.delete_object(self.id, Self::OBJECT_TYPE)
+    }
+}
+
+impl TypedHandle for WrapKey {
+    const OBJECT_TYPE: object::Type = object::Type::WrapKey;
+
+    fn id(&self) -> object::Id {
+        self.id
+    }
+}
+
+/// Opaque object, e.g. a certificate
+#[derive(Clone)]
+pub struct OpaqueObject {
+    /// Client for the HSM holding the object
+    client: Client,
+
+    /// ID of the object
+    id: object::Id,
+}
+
+impl OpaqueObject {
+    /// Put an opaque object into the HSM
+    pub fn put<B: Into<Vec<u8>>>(
+        client: Client,
+        object_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: opaque::Algorithm,
+        opaque_data: B,
+    ) -> Result<Self, Error> {
+        let id = client.put_opaque(
+            object_id,
+            label,
+            domains,
+            capabilities,
+            algorithm,
+            opaque_data,
+        )?;
+
+        Ok(Self { client, id })
+    }
+
+    /// Resolve the object with the given ID, checking it's an opaque object
+    pub fn resolve(client: Client, object_id: object::Id) -> Result<Self, Error> {
+        client.get_object_info(object_id, Self::OBJECT_TYPE)?;
+        Ok(Self {
+            client,
+            id: object_id,
+        })
+    }
+
+    /// Resolve the object with the given label
+    pub fn resolve_by_label(client: Client, label: &str) -> Result<Self, Error> {
+        let info = client.find_object(label, Self::OBJECT_TYPE)?;
+        Ok(Self {
+            client,
+            id: info.object_id,
+        })
+    }
+
+    /// Get information about the object
+    pub fn info(&self) -> Result<object::Info, Error> {
+        self.client.get_object_info(self.id, Self::OBJECT_TYPE)
+    }
+
+    /// Get the contents of the object
+    pub fn get(&self) -> Result<Vec<u8>, Error> {
+        self.client.get_opaque(self.id)
+    }
+
+    /// Delete the object from the HSM
+    pub fn delete(self) -> Result<(), Error> {
+        self.client.delete_object(self.id, Self::OBJECT_TYPE)
+    }
+}
+
+impl TypedHandle for OpaqueObject {
+    const OBJECT_TYPE: object::Type = object::Type::Opaque;
+
+    fn id(&self) -> object::Id {
+        self.id
+    }
+}
+
+/// Check that an object has the expected algorithm
+fn check_algorithm(info: &object::Info, expected: impl Into<Algorithm>) -> Result<(), Error> {
+    let expected = expected.into();
+
+    ensure!(
+        info.algorithm == expected,
+        ErrorKind::AlgorithmMismatch,
+        "{:?} 0x{:04x} has algorithm {:?} (expected {:?})",
+        info.object_type,
+        info.object_id,
+        info.algorithm,
+        expected
+    );
+
+    Ok(())
+}
@@ -0,0 +1,318 @@
+//! Key rotation: manage a logical key (e.g. `prod-signer`) as a series of
+//! versioned objects in the HSM.
+//!
+//! Each version is a separate asymmetric (ECDSA, Ed25519 or RSA) or HMAC
+//! key whose label holds the name of the key, the version number and the
+//! time the version was created (see [`Version`]). The most recent version
+//! is the active one, used for signing. Older versions are retired when the
+//! next version is created, but remain available to verifiers until they've
+//! been retired for longer than the retention window, after which they can
+//! be deleted with [`RotatingKey::prune`].
+//!
+//! Since everything is recorded in the labels of the versions, no state is
+//! kept outside the HSM.
+
+mod error;
+mod version;
+
+pub use self::{
+    error::{Error, ErrorKind},
+    version::Version,
+};
+
+use crate::{asymmetric::PublicKey, object, Algorithm, Capability, Client, Domain};
+use std::time::Duration;
+use time::OffsetDateTime as DateTime;
+
+/// Default retention window for retired versions
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Logical key rotated between versioned objects in the HSM
+#[derive(Clone)]
+pub struct RotatingKey {
+    /// Client for the HSM holding the key
+    client: Client,
+
+    /// Name of the key, used as the prefix of the labels of its versions
+    name: String,
+
+    /// Algorithm of the key
+    algorithm: Algorithm,
+
+    /// Domains new versions are created in
+    domains: Domain,
+
+    /// Capabilities of new versions
+    capabilities: Capability,
+
+    /// How long retired versions are kept
+    retention: Duration,
+}
+
+impl RotatingKey {
+    /// Manage the key with the given name, whose versions are asymmetric keys
+    /// or HMAC keys of the given algorithm.
+    ///
+    /// The name must leave enough room in the 40-byte label of each version
+    /// for the version number and creation time, i.e. be at most 23 bytes.
+    pub fn new(
+        client: Client,
+        name: impl Into<String>,
+        algorithm: impl Into<Algorithm>,
+        domains: Domain,
+        capabilities: Capability,
+    ) -> Result<Self, Error> {
+        let name = name.into();
+        let algorithm = algorithm.into();
+
+        ensure!(
+            matches!(algorithm, Algorithm::Asymmetric(_) | Algorithm::Hmac(_)),
+            ErrorKind::AlgorithmInvalid,
+            "rotated keys must be asymmetric or HMAC keys, not {:?}",
+            algorithm
+        );
+
+        // Leave room for version numbers up to 9999 and the creation time
+        let longest_label = version::label(&name, 9999, DateTime::now_utc());
+
+        ensure!(
+            !name.is_empty() && longest_label.len() <= object::LABEL_SIZE,
+            ErrorKind::NameInvalid,
+            "key name must be between 1 and {} bytes: {:?}",
+            name.len() + object::LABEL_SIZE - longest_label.len(),
+            name
+        );
+
+        Ok(Self {
+            client,
+            name,
+            algorithm,
+            domains,
+            capabilities,
+            retention: DEFAULT_RETENTION,
+        })
+    }
+
+    /// Set how long retired versions are kept
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Name of the key
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get all of the versions of the key in the HSM, oldest first
+    pub fn versions(&self) -> Result<Vec<Version>, Error> {
+        let query = object::Query::new()
+            .label_prefix(format!("{}.v", self.name))
+            .object_type(self.object_type())
+            .algorithm(self.algorithm);
+
+        let objects = self.client.find_objects(&query).map_err(|e| {
+            format_err!(
+                ErrorKind::DeviceError,
+                "error finding versions of {}: {}",
+                self.name,
+                e
+            )
+        })?;
+
+        let mut versions: Vec<Version> = objects
+            .iter()
+            .filter_map(|info| {
+                let label = info.label.try_as_str().ok()?;
+                let (number, created) = version::parse_label(&self.name, label)?;
+
+                Some(Version {
+                    number,
+                    object_id: info.object_id,
+                    object_type: info.object_type,
+                    created,
+                    retired: None,
+                })
+            })
+            .collect();
+
+        versions.sort_by_key(|version| version.number);
+
+        // Each version is retired when the next one is created
+        for i in 1..versions.len() {
+            versions[i - 1].retired = Some(versions[i].created);
+        }
+
+        Ok(versions)
+    }
+
+    /// Get the active version of the key (i.e. the most recent one)
+    pub fn active(&self) -> Result<Version, Error> {
+        self.versions()?.pop().ok_or_else(|| {
+            format_err!(ErrorKind::NoVersions, "no versions of {} found", self.name).into()
+        })
+    }
+
+    /// Get the versions of the key which haven't expired, i.e. the active
+    /// version and those retired within the retention window
+    pub fn valid_versions(&self) -> Result<Vec<Version>, Error> {
+        let now = DateTime::now_utc();
+
+        Ok(self
+            .versions()?
+            .into_iter()
+            .filter(|version| !version.is_expired(self.retention, now))
+            .collect())
+    }
+
+    /// Get the public keys of the versions which haven't expired, for use by
+    /// verifiers
+    pub fn public_keys(&self) -> Result<Vec<(Version, PublicKey)>, Error> {
+        ensure!(
+            matches!(self.algorithm, Algorithm::Asymmetric(_)),
+            ErrorKind::AlgorithmInvalid,
+            "{} is an HMAC key, which has no public key",
+            self.name
+        );
+
+        self.valid_versions()?
+            .into_iter()
+            .map(|version| {
+                let public_key = self.client.get_public_key(version.object_id).map_err(|e| {
+                    format_err!(
+                        ErrorKind::DeviceError,
+                        "error getting public key of {} version {}: {}",
+                        self.name,
+                        version.number,
+                        e
+                    )
+                })?;
+
+                Ok((version, public_key))
+            })
+            .collect()
+    }
+
+    /// Generate the next version of the key, which becomes the active
+    /// version, retiring the previous one.
+    ///
+    /// The new version is stored under the lowest object ID not used by any
+    /// object of the same type.
+    pub fn rotate(&self) -> Result<Version, Error> {
+        let number = match self.versions()?.last() {
+            Some(version) => version.number.checked_add(1).ok_or_else(|| {
+                format_err!(
+                    ErrorKind::NameInvalid,
+                    "{} has run out of versions",
+                    self.name
+                )
+            })?,
+            None => 1,
+        };
+
+        let object_id = self.free_object_id()?;
+
+        // Labels record the creation time to the second
+        let created = DateTime::now_utc().replace_nanosecond(0).unwrap();
+        let label =
+            object::Label::from_bytes(version::label(&self.name, number, created).as_bytes())
+                .map_err(|e| ErrorKind::NameInvalid.context(e))?;
+
+        let result = match self.algorithm {
+            Algorithm::Asymmetric(alg) => self.client.generate_asymmetric_key(
+                object_id,
+                label,
+                self.domains,
+                self.capabilities,
+                alg,
+            ),
+            Algorithm::Hmac(alg) => self.client.generate_hmac_key(
+                object_id,
+                label,
+                self.domains,
+                self.capabilities,
+                alg,
+            ),
+            _ => unreachable!(),
+        };
+
+        result.map_err(|e| {
+            format_err!(
+                ErrorKind::DeviceError,
+                "error generating {} version {}: {}",
+                self.name,
+                number,
+                e
+            )
+        })?;
+
+        info!(
+            "generated {} version {} (0x{:04x})",
+            self.name, number, object_id
+        );
+
+        Ok(Version {
+            number,
+            object_id,
+            object_type: self.object_type(),
+            created,
+            retired: None,
+        })
+    }
+
+    /// Delete the versions which have been retired for longer than the
+    /// retention window, returning them. The active version is never deleted.
+    pub fn prune(&self) -> Result<Vec<Version>, Error> {
+        let now = DateTime::now_utc();
+        let mut deleted = vec![];
+
+        for version in self.versions()? {
+            if !version.is_expired(self.retention, now) {
+                continue;
+            }
+
+            self.client
+                .delete_object(version.object_id, version.object_type)
+                .map_err(|e| {
+                    format_err!(
+                        ErrorKind::DeviceError,
+                        "error deleting {} version {}: {}",
+                        self.name,
+                        version.number,
+                        e
+                    )
+                })?;
+
+            info!("deleted expired {} version {}", self.name, version.number);
+            deleted.push(version);
+        }
+
+        Ok(deleted)
+    }
+
+    /// Type of the objects holding versions of the key
+    fn object_type(&self) -> object::Type {
+        match self.algorithm {
+            Algorithm::Hmac(_) => object::Type::HmacKey,
+            _ => object::Type::AsymmetricKey,
+        }
+    }
+
+    /// Find the lowest object ID which isn't used by an object of the type
+    /// of the key's versions
+    fn free_object_id(&self) -> Result<object::Id, Error> {
+        let mut used: Vec<object::Id> = self
+            .client
+            .list_objects(&[object::Filter::Type(self.object_type())])
+            .map_err(|e| format_err!(ErrorKind::DeviceError, "error listing objects: {}", e))?
+            .iter()
+            .map(|entry| entry.object_id)
+            .collect();
+
+        used.sort_unstable();
+
+        (1..=object::Id::MAX)
+            .find(|id| used.binary_search(id).is_err())
+            .ok_or_else(|| format_err!(ErrorKind::DeviceError, "no free object IDs").into())
+    }
+}
@@ -0,0 +1,34 @@
+//! Key rotation errors
+
+use crate::error::{BoxError, Context};
+use thiserror::Error;
+
+/// Key rotation errors
+pub type Error = crate::Error<ErrorKind>;
+
+/// Kinds of key rotation errors
+#[derive(Copy, Clone, Debug, Eq, Error, PartialEq)]
+pub enum ErrorKind {
+    /// Algorithm can't be used for rotated keys
+    #[error("invalid algorithm")]
+    AlgorithmInvalid,
+
+    /// Error performing an operation on the HSM
+    #[error("device error")]
+    DeviceError,
+
+    /// Name of the key is empty or too long to label its versions
+    #[error("invalid name")]
+    NameInvalid,
+
+    /// Key has no versions (i.e. it was never rotated)
+    #[error("no versions")]
+    NoVersions,
+}
+
+impl ErrorKind {
+    /// Create an error context from this error
+    pub fn context(self, source: impl Into<BoxError>) -> Context<ErrorKind> {
+        Context::new(self, Some(source.into()))
+    }
+}
@@ -0,0 +1,64 @@
+//! Versions of a rotated key, and the labels which identify them
+
+use crate::object;
+use std::time::Duration;
+use time::OffsetDateTime as DateTime;
+
+/// Version of a rotated key: an object labeled with the name of the key,
+/// the version number and the time it was created
+/// (e.g. `prod-signer.v3.1792224000`)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Version {
+    /// Version number, starting from 1
+    pub number: u32,
+
+    /// ID of the object holding this version
+    pub object_id: object::Id,
+
+    /// Type of the object holding this version
+    pub object_type: object::Type,
+
+    /// Time this version was created
+    pub created: DateTime,
+
+    /// Time this version was retired, i.e. when the next version was
+    /// created (if it has been)
+    pub retired: Option<DateTime>,
+}
+
+impl Version {
+    /// Is this the active version (i.e. the most recent one)?
+    pub fn is_active(&self) -> bool {
+        self.retired.is_none()
+    }
+
+    /// Has this version been retired for longer than the retention window
+    /// as of the given time?
+    pub fn is_expired(&self, retention: Duration, now: DateTime) -> bool {
+        self.retired
+            .is_some_and(|retired| retired + retention <= now)
+    }
+
+    /// Get the handle of the object holding this version
+    pub fn handle(&self) -> object::Handle {
+        object::Handle::new(self.object_id, self.object_type)
+    }
+}
+
+/// Label for the given version of a key
+pub(super) fn label(name: &str, number: u32, created: DateTime) -> String {
+    format!("{}.v{}.{}", name, number, created.unix_timestamp())
+}
+
+/// Parse the version number and creation time from the label of a version
+/// of the key with the given name
+pub(super) fn parse_label(name: &str, label: &str) -> Option<(u32, DateTime)> {
+    let (number, created) = label
+        .strip_prefix(name)?
+        .strip_prefix(".v")?
+        .split_once('.')?;
+
+    let number = number.parse().ok()?;
+    let created = DateTime::from_unix_timestamp(created.parse().ok()?).ok()?;
+    Some((number, created))
+}
@@ -25,7 +25,7 @@ use crate::{
     authentication::Credentials,
     command::{self, Command},
     connector::Connector,
-    device, response,
+    device, object, response,
     serialization::deserialize,
 };
 use std::time::{Duration, Instant};
@@ -49,6 +49,9 @@ pub struct Session {
     /// Connector which communicates with the HSM (HTTP or USB)
     connector: Connector,
 
+    /// ID of the authentication key this session was opened with
+    authentication_key_id: object::Id,
+
     /// Encrypted channel (SCP03) to the HSM
     secure_channel: Option<SecureChannel>,
 
@@ -82,6 +85,7 @@ impl Session {
         let mut session = Session {
             id: channel.id(),
             connector,
+            authentication_key_id: credentials.authentication_key_id,
             secure_channel: Some(channel),
             created_at: now,
             last_active: now,
@@ -103,6 +107,11 @@ impl Session {
         self.id
     }
 
+    /// ID of the authentication key this session was opened with
+    pub fn authentication_key_id(&self) -> object::Id {
+        self.authentication_key_id
+    }
+
     /// How long has this session been open?
     pub fn duration(&self) -> Duration {
         Instant::now().duration_since(self.created_at)
@@ -13,8 +13,13 @@ pub struct Challenge([u8; CHALLENGE_SIZE]);
 impl Challenge {
     /// Create a new random `Challenge`
     pub fn new() -> Self {
+        Self::from_rng(&mut OsRng)
+    }
+
+    /// Create a new `Challenge` using the given RNG
+    pub fn from_rng(rng: &mut impl RngCore) -> Self {
         let mut challenge = [0u8; CHALLENGE_SIZE];
-        OsRng.fill_bytes(&mut challenge);
+        rng.fill_bytes(&mut challenge);
         Challenge(challenge)
     }
 
@@ -1,13 +1,21 @@
 //! Initial YubiHSM 2 setup functionality using declarative device profiles.
 
+mod compliance;
+mod config;
 mod error;
+mod objects;
+mod plan;
 mod profile;
 pub mod report;
 mod role;
 
 pub use self::{
+    compliance::{audit, Violation},
+    config::{Config, Secret},
     error::{Error, ErrorKind},
-    profile::Profile,
+    objects::Object,
+    plan::{apply, plan, Applied, ApplyError, Plan, Step},
+    profile::{Profile, DEFAULT_REPORT_OBJECT_ID, DEFAULT_SETUP_KEY_ID},
     report::Report,
     role::Role,
 };
@@ -20,6 +28,19 @@ use crate::{
 /// Label to place on the temporary setup auth key ID
 const SETUP_KEY_LABEL: &str = "yubihsm.rs temporary setup key";
 
+/// Object ID of the device attestation key and its certificate, which are
+/// installed by the manufacturer and survive a reset
+const DEVICE_ATTESTATION_ID: object::Id = 0;
+
+/// Is this the device attestation key or its certificate?
+fn is_device_attestation(info: &object::Info) -> bool {
+    info.object_id == DEVICE_ATTESTATION_ID
+        && matches!(
+            info.object_type,
+            object::Type::AsymmetricKey | object::Type::Opaque
+        )
+}
+
 /// Erase and reset an HSM device, then reinitialize it with the given
 /// profile.
 pub fn erase_device_and_init_with_profile(
@@ -0,0 +1,411 @@
+//! Compliance checks: compare a provisioned HSM against a profile and list
+//! the ways it has drifted from it (e.g. keys added by operators, changed
+//! capabilities or auditing being turned off).
+
+use super::{is_device_attestation, report::REPORT_OBJECT_LABEL, Error, Profile};
+use crate::{
+    authentication::{self, DEFAULT_AUTHENTICATION_KEY_ID},
+    command, object, Algorithm, AuditOption, Capability, Client, Domain,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// Ways in which an HSM doesn't comply with a profile
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "violation", rename_all = "snake_case")]
+pub enum Violation {
+    /// An object in the profile is missing from the HSM
+    MissingObject {
+        /// Object which is missing
+        handle: object::Handle,
+
+        /// Label of the object in the profile
+        label: String,
+    },
+
+    /// The HSM contains an object which isn't in the profile
+    UnexpectedObject {
+        /// Object which isn't in the profile
+        handle: object::Handle,
+
+        /// Label of the object in the HSM
+        label: String,
+    },
+
+    /// The default authentication key is still present (and isn't one of
+    /// the profile's roles)
+    DefaultAuthenticationKeyPresent,
+
+    /// An object has a different label
+    LabelMismatch {
+        /// Object with a different label
+        handle: object::Handle,
+
+        /// Label in the profile
+        expected: String,
+
+        /// Label in the HSM
+        actual: String,
+    },
+
+    /// An object has a different algorithm
+    AlgorithmMismatch {
+        /// Object with a different algorithm
+        handle: object::Handle,
+
+        /// Algorithm in the profile
+        expected: Algorithm,
+
+        /// Algorithm in the HSM
+        actual: Algorithm,
+    },
+
+    /// An object has different capabilities
+    CapabilitiesMismatch {
+        /// Object with different capabilities
+        handle: object::Handle,
+
+        /// Capabilities in the profile
+        expected: Capability,
+
+        /// Capabilities in the HSM
+        actual: Capability,
+    },
+
+    /// An object has different delegated capabilities
+    DelegatedCapabilitiesMismatch {
+        /// Object with different delegated capabilities
+        handle: object::Handle,
+
+        /// Delegated capabilities in the profile
+        expected: Capability,
+
+        /// Delegated capabilities in the HSM
+        actual: Capability,
+    },
+
+    /// An object is accessible from different domains
+    DomainsMismatch {
+        /// Object with different domains
+        handle: object::Handle,
+
+        /// Domains in the profile
+        expected: Domain,
+
+        /// Domains in the HSM
+        actual: Domain,
+    },
+
+    /// The force audit option differs from the profile
+    ForceAuditMismatch {
+        /// Option in the profile
+        expected: AuditOption,
+
+        /// Option in the HSM
+        actual: AuditOption,
+    },
+
+    /// The audit option for a command differs from the profile
+    CommandAuditMismatch {
+        /// Command whose audit option differs
+        command: command::Code,
+
+        /// Option in the profile
+        expected: AuditOption,
+
+        /// Option in the HSM
+        actual: AuditOption,
+    },
+
+    /// FIPS mode differs from the profile
+    FipsModeMismatch {
+        /// Is FIPS mode enabled in the profile?
+        expected: bool,
+
+        /// Is FIPS mode enabled in the HSM?
+        actual: bool,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MissingObject { handle, label } => {
+                write!(f, "missing {} \"{label}\"", DisplayHandle(handle))
+            }
+            Violation::UnexpectedObject { handle, label } => {
+                write!(f, "unexpected {} \"{label}\"", DisplayHandle(handle))
+            }
+            Violation::DefaultAuthenticationKeyPresent => write!(
+                f,
+                "default authentication key 0x{DEFAULT_AUTHENTICATION_KEY_ID:04x} is present"
+            ),
+            Violation::LabelMismatch {
+                handle,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} label is \"{actual}\" (expected \"{expected}\")",
+                DisplayHandle(handle)
+            ),
+            Violation::AlgorithmMismatch {
+                handle,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} algorithm is {actual:?} (expected {expected:?})",
+                DisplayHandle(handle)
+            ),
+            Violation::CapabilitiesMismatch {
+                handle,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} capabilities are 0x{:x} (expected 0x{:x})",
+                DisplayHandle(handle),
+                actual.bits(),
+                expected.bits()
+            ),
+            Violation::DelegatedCapabilitiesMismatch {
+                handle,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} delegated capabilities are 0x{:x} (expected 0x{:x})",
+                DisplayHandle(handle),
+                actual.bits(),
+                expected.bits()
+            ),
+            Violation::DomainsMismatch {
+                handle,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} domains are 0x{:x} (expected 0x{:x})",
+                DisplayHandle(handle),
+                actual.bits(),
+                expected.bits()
+            ),
+            Violation::ForceAuditMismatch { expected, actual } => {
+                write!(f, "force audit is {actual:?} (expected {expected:?})")
+            }
+            Violation::CommandAuditMismatch {
+                command,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "auditing for {command:?} is {actual:?} (expected {expected:?})"
+            ),
+            Violation::FipsModeMismatch { expected, actual } => {
+                write!(f, "FIPS mode is {actual} (expected {expected})")
+            }
+        }
+    }
+}
+
+/// Check whether the HSM complies with the given profile, returning every
+/// violation found (or an empty list if it complies).
+///
+/// Every object in the HSM must be in the profile, apart from the stored
+/// setup report and (if the profile keeps it) the setup authentication key.
+/// As the secrets of existing keys can't be read back, only their attributes
+/// are compared.
+pub fn audit(client: &Client, profile: &Profile) -> Result<Vec<Violation>, Error> {
+    let mut violations = vec![];
+    let mut expected = vec![];
+
+    for role in &profile.roles {
+        expected.push(Expected {
+            handle: object::Handle::new(
+                role.credentials.authentication_key_id,
+                object::Type::AuthenticationKey,
+            ),
+            label: &role.authentication_key_label,
+            algorithm: authentication::Algorithm::default().into(),
+            capabilities: role.capabilities,
+            delegated_capabilities: role.delegated_capabilities,
+            domains: role.domains,
+        });
+    }
+
+    let wrap_keys = profile
+        .wrap_keys
+        .iter()
+        .chain(profile.split_wrap_keys.iter().map(|split| &split.key));
+
+    for key in wrap_keys {
+        expected.push(Expected {
+            handle: object::Handle::new(key.import_params.id, object::Type::WrapKey),
+            label: &key.import_params.label,
+            algorithm: key.import_params.algorithm,
+            capabilities: key.import_params.capabilities,
+            delegated_capabilities: key.delegated_capabilities,
+            domains: key.import_params.domains,
+        });
+    }
+
+    for object in &profile.objects {
+        expected.push(Expected {
+            handle: object.handle(),
+            label: &object.label,
+            algorithm: object.algorithm(),
+            capabilities: object.capabilities,
+            delegated_capabilities: Capability::empty(),
+            domains: object.domains,
+        });
+    }
+
+    let default_key = object::Handle::new(
+        DEFAULT_AUTHENTICATION_KEY_ID,
+        object::Type::AuthenticationKey,
+    );
+
+    let mut present = vec![];
+
+    for entry in client.list_objects(&[])? {
+        let info = client.get_object_info(entry.object_id, entry.object_type)?;
+        let handle = object::Handle::new(info.object_id, info.object_type);
+
+        match expected.iter().find(|e| e.handle == handle) {
+            Some(expected) => expected.check(&info, &mut violations),
+            None if is_exempt(profile, &info) => (),
+            None if handle == default_key => {
+                violations.push(Violation::DefaultAuthenticationKeyPresent)
+            }
+            None => violations.push(Violation::UnexpectedObject {
+                handle: handle.clone(),
+                label: info.label.to_string(),
+            }),
+        }
+
+        present.push(handle);
+    }
+
+    for expected in &expected {
+        if !present.contains(&expected.handle) {
+            violations.push(Violation::MissingObject {
+                handle: expected.handle.clone(),
+                label: expected.label.to_string(),
+            });
+        }
+    }
+
+    let force_audit = client.get_force_audit_option()?;
+
+    if force_audit != profile.audit_option {
+        violations.push(Violation::ForceAuditMismatch {
+            expected: profile.audit_option,
+            actual: force_audit,
+        });
+    }
+
+    for (command, option) in &profile.command_audit_options {
+        let current = client.get_command_audit_option(*command)?;
+
+        if current != *option {
+            violations.push(Violation::CommandAuditMismatch {
+                command: *command,
+                expected: *option,
+                actual: current,
+            });
+        }
+    }
+
+    if let Some(enabled) = profile.fips_mode {
+        let fips_mode = client.get_fips_option()? != AuditOption::Off;
+
+        if fips_mode != enabled {
+            violations.push(Violation::FipsModeMismatch {
+                expected: enabled,
+                actual: fips_mode,
+            });
+        }
+    }
+
+    Ok(violations)
+}
+
+/// Attributes an object is expected to have
+struct Expected<'a> {
+    handle: object::Handle,
+    label: &'a object::Label,
+    algorithm: Algorithm,
+    capabilities: Capability,
+    delegated_capabilities: Capability,
+    domains: Domain,
+}
+
+impl Expected<'_> {
+    /// Compare the object in the HSM against the expected attributes
+    fn check(&self, info: &object::Info, violations: &mut Vec<Violation>) {
+        let handle = &self.handle;
+
+        if info.label != *self.label {
+            violations.push(Violation::LabelMismatch {
+                handle: handle.clone(),
+                expected: self.label.to_string(),
+                actual: info.label.to_string(),
+            });
+        }
+
+        if info.algorithm != self.algorithm {
+            violations.push(Violation::AlgorithmMismatch {
+                handle: handle.clone(),
+                expected: self.algorithm,
+                actual: info.algorithm,
+            });
+        }
+
+        if info.capabilities != self.capabilities {
+            violations.push(Violation::CapabilitiesMismatch {
+                handle: handle.clone(),
+                expected: self.capabilities,
+                actual: info.capabilities,
+            });
+        }
+
+        if info.delegated_capabilities != self.delegated_capabilities {
+            violations.push(Violation::DelegatedCapabilitiesMismatch {
+                handle: handle.clone(),
+                expected: self.delegated_capabilities,
+                actual: info.delegated_capabilities,
+            });
+        }
+
+        if info.domains != self.domains {
+            violations.push(Violation::DomainsMismatch {
+                handle: handle.clone(),
+                expected: self.domains,
+                actual: info.domains,
+            });
+        }
+    }
+}
+
+/// Is this object allowed in the HSM even though it isn't in the profile?
+fn is_exempt(profile: &Profile, info: &object::Info) -> bool {
+    let is_report = info.object_type == object::Type::Opaque
+        && Some(info.object_id) == profile.report_object_id
+        && info.label.to_string() == REPORT_OBJECT_LABEL;
+
+    let is_setup_key = info.object_type == object::Type::AuthenticationKey
+        && Some(info.object_id) == profile.setup_auth_key_id
+        && !profile.delete_setup_auth_key;
+
+    is_report || is_setup_key || is_device_attestation(info)
+}
+
+/// Display an object handle as its type and ID (e.g. `wrap-key 0x0100`)
+struct DisplayHandle<'a>(&'a object::Handle);
+
+impl Display for DisplayHandle<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} 0x{:04x}", self.0.object_type, self.0.object_id)
+    }
+}
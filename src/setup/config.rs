@@ -0,0 +1,1037 @@
+//! Declarative provisioning profiles stored in TOML or JSON files.
+//!
+//! Capabilities and domains are written the same way as for `yubihsm-shell`
+//! (e.g. `"sign-ecdsa,sign-eddsa"` and `"1,2,3"`, or `"all"`/`"none"`), and
+//! secrets are referenced from environment variables or files rather than
+//! being written inline, so profiles can be kept in version control:
+//!
+//! ```toml
+//! audit = "on"
+//! report_signing_key_id = 0x200
+//!
+//! [[roles]]
+//! authentication_key_id = 2
+//! authentication_key_label = "signer"
+//! authentication_key = { env = "SIGNER_PASSWORD" }
+//! capabilities = "sign-ecdsa,sign-eddsa"
+//! domains = "1,2"
+//!
+//! [[wrap_keys]]
+//! id = 0x100
+//! label = "backup"
+//! key = { file = "/etc/yubihsm/backup.key" }
+//! capabilities = "export-wrapped,import-wrapped"
+//! delegated_capabilities = "all"
+//! domains = "all"
+//!
+//! [[wrap_keys]]
+//! id = 0x101
+//! label = "escrow"
+//! algorithm = "aes256-ccm-wrap"
+//! split = { threshold = 2, shares = 3 }
+//! capabilities = "export-wrapped,import-wrapped"
+//! domains = "all"
+//!
+//! [[objects]]
+//! type = "asymmetric-key"
+//! id = 0x200
+//! label = "signer"
+//! algorithm = "ecp256"
+//! generate = true
+//! capabilities = "sign-ecdsa"
+//! domains = "1"
+//!
+//! [[objects]]
+//! type = "opaque"
+//! id = 0x200
+//! label = "signer certificate"
+//! algorithm = "opaque-x509-certificate"
+//! data = { file = "/etc/yubihsm/signer.der" }
+//! capabilities = "get-opaque"
+//! domains = "1"
+//!
+//! [command_audit]
+//! sign-ecdsa = "on"
+//! ```
+//!
+//! Authentication key secrets are passwords, while wrap key secrets and the
+//! `key` of imported asymmetric and HMAC keys are hex-encoded. The `data` of
+//! opaque objects and templates is used as is.
+//!
+//! Parsing a profile into a [`Config`] never reads secrets: they're read, and
+//! split wrap keys without a `key` generated, by [`Config::resolve`]. Errors
+//! parsing a profile (e.g. unknown fields or capability names) give the line
+//! they occurred at, while errors resolving it name the entry they occurred
+//! in (e.g. `wrap_keys[1]`).
+//!
+//! Relative `file` paths in a profile read by [`Config::load`] are relative to
+//! the profile's directory.
+
+use super::{
+    profile::{Profile, SplitWrapKey},
+    role::Role,
+    Error, ErrorKind, Object,
+};
+use crate::{
+    asymmetric, authentication, command, hmac, object, opaque, ssh, template, wrap, Algorithm,
+    AuditOption, Capability, Credentials, Domain,
+};
+use serde::{de, de::Error as _, ser, ser::Error as _, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Write},
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use zeroize::Zeroizing;
+
+/// Reference to a secret stored outside of a profile
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Secret {
+    /// Read the secret from the given environment variable
+    Env(String),
+
+    /// Read the secret from the given file (relative to the current
+    /// directory, or to the profile's directory for profiles read by
+    /// [`Config::load`]). Trailing whitespace is ignored.
+    File(PathBuf),
+}
+
+impl Secret {
+    /// Load the secret
+    pub fn load(&self) -> Result<Zeroizing<String>, Error> {
+        let secret = Zeroizing::new(match self {
+            Secret::Env(var) => env::var(var).map_err(|e| {
+                format_err!(
+                    ErrorKind::ProfileInvalid,
+                    "error reading secret from ${}: {}",
+                    var,
+                    e
+                )
+            })?,
+            Secret::File(path) => fs::read_to_string(path).map_err(|e| {
+                format_err!(
+                    ErrorKind::ProfileInvalid,
+                    "error reading secret from {}: {}",
+                    path.display(),
+                    e
+                )
+            })?,
+        });
+
+        Ok(Zeroizing::new(secret.trim_end().to_owned()))
+    }
+
+    /// Load the secret as raw bytes (e.g. a DER-encoded certificate file)
+    pub fn load_bytes(&self) -> Result<Zeroizing<Vec<u8>>, Error> {
+        match self {
+            Secret::Env(_) => Ok(Zeroizing::new(self.load()?.as_bytes().to_vec())),
+            Secret::File(path) => fs::read(path).map(Zeroizing::new).map_err(|e| {
+                format_err!(
+                    ErrorKind::ProfileInvalid,
+                    "error reading {}: {}",
+                    path.display(),
+                    e
+                )
+                .into()
+            }),
+        }
+    }
+}
+
+/// Provisioning profile as written in a TOML or JSON file.
+///
+/// Parsing a config only checks its syntax and schema: secrets are referenced
+/// rather than read, and split wrap keys aren't generated until the config is
+/// turned into a [`Profile`] by [`Config::resolve`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    setup_auth_key_id: Option<object::Id>,
+    delete_setup_auth_key: bool,
+    #[serde(with = "audit_option")]
+    audit: AuditOption,
+    report_object_id: Option<object::Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report_signing_key_id: Option<object::Id>,
+    reset_device_timeout_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fips: Option<bool>,
+    command_audit: BTreeMap<CommandName, AuditSetting>,
+    roles: Vec<RoleSpec>,
+    wrap_keys: Vec<WrapKeySpec>,
+    objects: Vec<ObjectSpec>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let profile = Profile::default();
+
+        Self {
+            setup_auth_key_id: profile.setup_auth_key_id,
+            delete_setup_auth_key: profile.delete_setup_auth_key,
+            audit: profile.audit_option,
+            report_object_id: profile.report_object_id,
+            report_signing_key_id: profile.report_signing_key_id,
+            reset_device_timeout_secs: profile.reset_device_timeout.as_secs(),
+            fips: None,
+            command_audit: BTreeMap::new(),
+            roles: vec![],
+            wrap_keys: vec![],
+            objects: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Load a config from a `.toml` or `.json` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let contents = fs::read_to_string(path).map_err(|e| {
+            format_err!(
+                ErrorKind::ProfileInvalid,
+                "error reading {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+
+        let mut config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&contents)?,
+            Some("json") => Self::from_json(&contents)?,
+            _ => fail!(
+                ErrorKind::ProfileInvalid,
+                "unknown profile format (expected .toml or .json): {}",
+                path.display()
+            ),
+        };
+
+        if let Some(dir) = path.parent() {
+            config.resolve_paths(dir);
+        }
+
+        Ok(config)
+    }
+
+    /// Make relative secret file paths relative to the given directory
+    fn resolve_paths(&mut self, dir: &Path) {
+        let secrets = self
+            .roles
+            .iter_mut()
+            .map(|role| &mut role.authentication_key)
+            .chain(self.wrap_keys.iter_mut().filter_map(|key| key.key.as_mut()))
+            .chain(
+                self.objects
+                    .iter_mut()
+                    .flat_map(|object| object.key.iter_mut().chain(object.data.iter_mut())),
+            );
+
+        for secret in secrets {
+            if let Secret::File(path) = secret {
+                if path.is_relative() {
+                    *path = dir.join(&*path);
+                }
+            }
+        }
+    }
+
+    /// Parse a config from TOML
+    pub fn from_toml(s: &str) -> Result<Self, Error> {
+        toml::from_str(s).map_err(|e| format_err!(ErrorKind::ProfileInvalid, "{}", e).into())
+    }
+
+    /// Parse a config from JSON
+    pub fn from_json(s: &str) -> Result<Self, Error> {
+        serde_json::from_str(s).map_err(|e| format_err!(ErrorKind::ProfileInvalid, "{}", e).into())
+    }
+
+    /// Serialize this config as TOML
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string_pretty(self)
+            .map_err(|e| format_err!(ErrorKind::ProfileInvalid, "{}", e).into())
+    }
+
+    /// Serialize this config as JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| format_err!(ErrorKind::ProfileInvalid, "{}", e).into())
+    }
+
+    /// Read the secrets this config references and generate its split wrap
+    /// keys, producing a profile which can be provisioned
+    pub fn resolve(&self) -> Result<Profile, Error> {
+        let mut profile = Profile {
+            setup_auth_key_id: self.setup_auth_key_id,
+            delete_setup_auth_key: self.delete_setup_auth_key,
+            audit_option: self.audit,
+            roles: self
+                .roles
+                .iter()
+                .enumerate()
+                .map(|(i, role)| {
+                    role.resolve()
+                        .map_err(|e| in_entry(e, format_args!("roles[{}]", i)))
+                })
+                .collect::<Result<_, _>>()?,
+            objects: self
+                .objects
+                .iter()
+                .enumerate()
+                .map(|(i, object)| {
+                    object
+                        .resolve()
+                        .map_err(|e| in_entry(e, format_args!("objects[{}]", i)))
+                })
+                .collect::<Result<_, _>>()?,
+            command_audit_options: self
+                .command_audit
+                .iter()
+                .map(|(command, setting)| (command.0, setting.0))
+                .collect(),
+            fips_mode: self.fips,
+            report_object_id: self.report_object_id,
+            report_signing_key_id: self.report_signing_key_id,
+            reset_device_timeout: Duration::from_secs(self.reset_device_timeout_secs),
+            ..Default::default()
+        };
+
+        for (i, spec) in self.wrap_keys.iter().enumerate() {
+            let key = spec
+                .resolve()
+                .map_err(|e| in_entry(e, format_args!("wrap_keys[{}]", i)))?;
+
+            if let Some(secret) = &spec.key {
+                profile.wrap_key_secrets.insert(spec.id, secret.clone());
+            }
+
+            match &spec.split {
+                Some(split) => profile.split_wrap_keys.push(SplitWrapKey {
+                    generated: spec.key.is_none(),
+                    key,
+                    threshold: split.threshold,
+                    count: split.shares,
+                }),
+                None => profile.wrap_keys.push(key),
+            }
+        }
+
+        Ok(profile)
+    }
+}
+
+impl Profile {
+    /// Get the config this profile can be saved as.
+    ///
+    /// Fails if the profile contains roles, wrap keys or objects which were
+    /// built in code with inline secrets, since those are never serialized.
+    pub fn to_config(&self) -> Result<Config, Error> {
+        let mut wrap_keys = vec![];
+
+        for key in &self.wrap_keys {
+            let secret = self.wrap_key_secret(key)?;
+            wrap_keys.push(WrapKeySpec::new(key, Some(secret), None));
+        }
+
+        for split in &self.split_wrap_keys {
+            let secret = if split.generated {
+                None
+            } else {
+                Some(self.wrap_key_secret(&split.key)?)
+            };
+
+            let split_spec = SplitSpec {
+                threshold: split.threshold,
+                shares: split.count,
+            };
+
+            wrap_keys.push(WrapKeySpec::new(&split.key, secret, Some(split_spec)));
+        }
+
+        Ok(Config {
+            setup_auth_key_id: self.setup_auth_key_id,
+            delete_setup_auth_key: self.delete_setup_auth_key,
+            audit: self.audit_option,
+            report_object_id: self.report_object_id,
+            report_signing_key_id: self.report_signing_key_id,
+            reset_device_timeout_secs: self.reset_device_timeout.as_secs(),
+            fips: self.fips_mode,
+            command_audit: self
+                .command_audit_options
+                .iter()
+                .map(|(command, option)| (CommandName(*command), AuditSetting(*option)))
+                .collect(),
+            roles: self
+                .roles
+                .iter()
+                .map(RoleSpec::new)
+                .collect::<Result<_, _>>()?,
+            wrap_keys,
+            objects: self
+                .objects
+                .iter()
+                .map(ObjectSpec::new)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Serialize this profile as TOML (see [`Profile::to_config`])
+    pub fn to_toml(&self) -> Result<String, Error> {
+        self.to_config()?.to_toml()
+    }
+
+    /// Serialize this profile as JSON (see [`Profile::to_config`])
+    pub fn to_json(&self) -> Result<String, Error> {
+        self.to_config()?.to_json()
+    }
+
+    /// Find the reference to the secret a wrap key was loaded from
+    fn wrap_key_secret(&self, key: &wrap::Key) -> Result<Secret, Error> {
+        self.wrap_key_secrets
+            .get(&key.import_params.id)
+            .cloned()
+            .ok_or_else(|| {
+                format_err!(
+                    ErrorKind::ProfileInvalid,
+                    "wrap key 0x{:04x} has an inline secret, which can't be serialized",
+                    key.import_params.id
+                )
+                .into()
+            })
+    }
+}
+
+impl Role {
+    /// Create a role whose authentication key is derived from a password
+    /// stored outside of the profile
+    pub fn from_secret(authentication_key_id: object::Id, password: Secret) -> Result<Self, Error> {
+        let key = authentication::Key::derive_from_password(password.load()?.as_bytes());
+        let mut role = Role::new(Credentials::new(authentication_key_id, key));
+        role.authentication_key_secret = Some(password);
+        Ok(role)
+    }
+}
+
+/// Serialized form of a `Role`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RoleSpec {
+    authentication_key_id: object::Id,
+    #[serde(default)]
+    authentication_key_label: String,
+    #[serde(with = "capabilities")]
+    capabilities: Capability,
+    #[serde(default, with = "capabilities")]
+    delegated_capabilities: Capability,
+    #[serde(with = "domains")]
+    domains: Domain,
+    authentication_key: Secret,
+}
+
+impl RoleSpec {
+    /// Describe a role
+    fn new(role: &Role) -> Result<Self, Error> {
+        let authentication_key = role.authentication_key_secret.clone().ok_or_else(|| {
+            format_err!(
+                ErrorKind::ProfileInvalid,
+                "role {} has an inline authentication key, which can't be serialized",
+                role.credentials.authentication_key_id
+            )
+        })?;
+
+        Ok(Self {
+            authentication_key_id: role.credentials.authentication_key_id,
+            authentication_key_label: role.authentication_key_label.to_string(),
+            authentication_key,
+            capabilities: role.capabilities,
+            delegated_capabilities: role.delegated_capabilities,
+            domains: role.domains,
+        })
+    }
+
+    /// Load the described role's authentication key
+    fn resolve(&self) -> Result<Role, Error> {
+        let label = self
+            .authentication_key_label
+            .parse::<object::Label>()
+            .map_err(|e| format_err!(ErrorKind::LabelInvalid, "{}", e))?;
+
+        let role = Role::from_secret(self.authentication_key_id, self.authentication_key.clone())?;
+
+        Ok(role
+            .authentication_key_label(label)
+            .capabilities(self.capabilities)
+            .delegated_capabilities(self.delegated_capabilities)
+            .domains(self.domains))
+    }
+}
+
+/// Serialized form of a wrap key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct WrapKeySpec {
+    id: object::Id,
+    #[serde(default)]
+    label: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "wrap_algorithm"
+    )]
+    algorithm: Option<wrap::Algorithm>,
+    #[serde(with = "capabilities")]
+    capabilities: Capability,
+    #[serde(default, with = "capabilities")]
+    delegated_capabilities: Capability,
+    #[serde(with = "domains")]
+    domains: Domain,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<Secret>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    split: Option<SplitSpec>,
+}
+
+/// Serialized M-of-N split of a wrap key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct SplitSpec {
+    threshold: u8,
+    shares: u8,
+}
+
+impl WrapKeySpec {
+    /// Describe a wrap key loaded from the given secret (or generated, if
+    /// there isn't one)
+    fn new(key: &wrap::Key, secret: Option<Secret>, split: Option<SplitSpec>) -> Self {
+        let params = &key.import_params;
+
+        Self {
+            id: params.id,
+            label: params.label.to_string(),
+            key: secret,
+            algorithm: params.algorithm.wrap(),
+            split,
+            capabilities: params.capabilities,
+            delegated_capabilities: key.delegated_capabilities,
+            domains: params.domains,
+        }
+    }
+
+    /// Load or generate the described wrap key
+    fn resolve(&self) -> Result<wrap::Key, Error> {
+        let key = match (&self.key, self.algorithm) {
+            (Some(secret), algorithm) => {
+                let key = wrap::Key::from_bytes(self.id, &load_hex(secret)?)
+                    .map_err(|e| format_err!(ErrorKind::ProfileInvalid, "{}", e))?;
+
+                if let Some(algorithm) = algorithm {
+                    ensure!(
+                        algorithm.key_len() == key.key_len(),
+                        ErrorKind::ProfileInvalid,
+                        "wrap key 0x{:04x} is {} bytes, but {:?} needs {}",
+                        self.id,
+                        key.key_len(),
+                        algorithm,
+                        algorithm.key_len()
+                    );
+                }
+
+                key
+            }
+            (None, Some(algorithm)) if self.split.is_some() => {
+                wrap::Key::generate_random(self.id, algorithm)
+            }
+            (None, _) => fail!(
+                ErrorKind::ProfileInvalid,
+                "wrap key 0x{:04x} needs a `key` (or an `algorithm` and `split` to generate one)",
+                self.id
+            ),
+        };
+
+        let label = self
+            .label
+            .parse::<object::Label>()
+            .map_err(|e| format_err!(ErrorKind::LabelInvalid, "{}", e))?;
+
+        Ok(key
+            .label(label)
+            .capabilities(self.capabilities)
+            .delegated_capabilities(self.delegated_capabilities)
+            .domains(self.domains))
+    }
+}
+
+/// Serialized form of an `Object`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectSpec {
+    #[serde(rename = "type", with = "object_type")]
+    object_type: object::Type,
+    id: object::Id,
+    #[serde(default)]
+    label: String,
+    #[serde(with = "object_algorithm")]
+    algorithm: Algorithm,
+    #[serde(default, skip_serializing_if = "is_false")]
+    generate: bool,
+    #[serde(with = "capabilities")]
+    capabilities: Capability,
+    #[serde(with = "domains")]
+    domains: Domain,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<Secret>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<Secret>,
+}
+
+impl ObjectSpec {
+    /// Describe an object
+    fn new(object: &Object) -> Result<Self, Error> {
+        let handle = object.handle();
+        let generate = object.is_generated();
+
+        ensure!(
+            generate || object.source.is_some(),
+            ErrorKind::ProfileInvalid,
+            "{} 0x{:04x} has inline contents, which can't be serialized",
+            handle.object_type,
+            handle.object_id
+        );
+
+        let is_key = matches!(
+            handle.object_type,
+            object::Type::AsymmetricKey | object::Type::HmacKey
+        );
+
+        Ok(Self {
+            object_type: handle.object_type,
+            id: handle.object_id,
+            label: object.label.to_string(),
+            algorithm: object.algorithm(),
+            generate,
+            capabilities: object.capabilities,
+            domains: object.domains,
+            key: object.source.clone().filter(|_| is_key),
+            data: object.source.clone().filter(|_| !is_key),
+        })
+    }
+
+    /// Load the described object
+    fn resolve(&self) -> Result<Object, Error> {
+        let object = match (self.object_type, self.algorithm) {
+            (object::Type::AsymmetricKey, Algorithm::Asymmetric(algorithm)) => {
+                match self.key_source()? {
+                    Some(key) => Object::put_asymmetric_key(self.id, algorithm, load_hex(key)?)?,
+                    None => Object::generate_asymmetric_key(self.id, algorithm),
+                }
+            }
+            (object::Type::HmacKey, Algorithm::Hmac(algorithm)) => match self.key_source()? {
+                Some(key) => Object::put_hmac_key(self.id, algorithm, load_hex(key)?)?,
+                None => Object::generate_hmac_key(self.id, algorithm),
+            },
+            (object::Type::Opaque, Algorithm::Opaque(algorithm)) => Object::opaque(
+                self.id,
+                algorithm,
+                self.data_source()?.load_bytes()?.to_vec(),
+            ),
+            (object::Type::Template, Algorithm::Template(template::Algorithm::Ssh)) => {
+                Object::ssh_template(
+                    self.id,
+                    ssh::Template::from_bytes(self.data_source()?.load_bytes()?.to_vec()),
+                )
+            }
+            (object_type, algorithm) => fail!(
+                ErrorKind::ProfileInvalid,
+                "unsupported {} algorithm: {:?}",
+                object_type,
+                algorithm
+            ),
+        };
+
+        let label = self
+            .label
+            .parse::<object::Label>()
+            .map_err(|e| format_err!(ErrorKind::LabelInvalid, "{}", e))?;
+
+        let mut object = object
+            .label(label)
+            .capabilities(self.capabilities)
+            .domains(self.domains);
+
+        object.source = self.key.clone().or_else(|| self.data.clone());
+        Ok(object)
+    }
+
+    /// Find the key to import (or `None` if the key is generated)
+    fn key_source(&self) -> Result<Option<&Secret>, Error> {
+        ensure!(
+            self.data.is_none(),
+            ErrorKind::ProfileInvalid,
+            "keys are imported from `key` rather than `data`"
+        );
+
+        match (&self.key, self.generate) {
+            (Some(key), false) => Ok(Some(key)),
+            (None, true) => Ok(None),
+            _ => fail!(
+                ErrorKind::ProfileInvalid,
+                "{} 0x{:04x} needs either `generate = true` or a `key`",
+                self.object_type,
+                self.id
+            ),
+        }
+    }
+
+    /// Find the data to store
+    fn data_source(&self) -> Result<&Secret, Error> {
+        ensure!(
+            self.key.is_none() && !self.generate,
+            ErrorKind::ProfileInvalid,
+            "{} objects can't have a `key` or be generated",
+            self.object_type
+        );
+
+        self.data.as_ref().ok_or_else(|| {
+            format_err!(
+                ErrorKind::ProfileInvalid,
+                "{} 0x{:04x} needs `data`",
+                self.object_type,
+                self.id
+            )
+            .into()
+        })
+    }
+}
+
+/// Command written by its name (e.g. `sign-ecdsa`)
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct CommandName(command::Code);
+
+impl<'de> Deserialize<'de> for CommandName {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map(CommandName)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for CommandName {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+/// Audit option as `off`, `on` or `fix`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct AuditSetting(#[serde(with = "audit_option")] AuditOption);
+
+/// Is this `false`? (for skipping default fields when serializing)
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Name the entry of a config (e.g. `roles[0]`) an error occurred in
+fn in_entry(error: Error, entry: fmt::Arguments<'_>) -> Error {
+    match std::error::Error::source(&error) {
+        Some(source) => format_err!(*error.kind(), "{}: {}", entry, source).into(),
+        None => format_err!(*error.kind(), "{}", entry).into(),
+    }
+}
+
+/// Load and decode a hex-encoded secret
+fn load_hex(secret: &Secret) -> Result<Zeroizing<Vec<u8>>, Error> {
+    decode_hex(&secret.load()?)
+}
+
+/// Decode a hex-encoded secret
+fn decode_hex(hex: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    ensure!(
+        hex.len() % 2 == 0 && hex.is_ascii(),
+        ErrorKind::ProfileInvalid,
+        "malformed hex-encoded key"
+    );
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map(Zeroizing::new)
+        .map_err(|_| format_err!(ErrorKind::ProfileInvalid, "malformed hex-encoded key").into())
+}
+
+/// Capabilities as comma-separated `yubihsm-shell` names
+mod capabilities {
+    use super::*;
+
+    pub fn serialize<S: ser::Serializer>(
+        capabilities: &Capability,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if *capabilities == Capability::all() {
+            return serializer.serialize_str("all");
+        }
+
+        if capabilities.is_empty() {
+            return serializer.serialize_str("none");
+        }
+
+        let mut names = String::new();
+
+        for capability in capabilities.iter() {
+            if !names.is_empty() {
+                names.push(',');
+            }
+
+            write!(names, "{capability}").map_err(|_| {
+                S::Error::custom(format!(
+                    "capability has no name: 0x{:016x}",
+                    capability.bits()
+                ))
+            })?;
+        }
+
+        serializer.serialize_str(&names)
+    }
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Capability, D::Error> {
+        let names = String::deserialize(deserializer)?;
+
+        match names.trim() {
+            "all" => return Ok(Capability::all()),
+            "" | "none" => return Ok(Capability::empty()),
+            _ => (),
+        }
+
+        names.split(',').try_fold(Capability::empty(), |acc, name| {
+            name.trim()
+                .parse::<Capability>()
+                .map(|capability| acc | capability)
+                .map_err(|_| de::Error::custom(format!("unknown capability: {:?}", name.trim())))
+        })
+    }
+}
+
+/// Domains as comma-separated numbers
+mod domains {
+    use super::*;
+    use crate::domain::DOMAINS;
+
+    pub fn serialize<S: ser::Serializer>(
+        domains: &Domain,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if *domains == Domain::all() {
+            return serializer.serialize_str("all");
+        }
+
+        if domains.is_empty() {
+            return serializer.serialize_str("none");
+        }
+
+        let numbers = (1..=DOMAINS.len())
+            .filter(|&i| domains.contains(DOMAINS[i - 1]))
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+
+        serializer.serialize_str(&numbers.join(","))
+    }
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(deserializer: D) -> Result<Domain, D::Error> {
+        let numbers = String::deserialize(deserializer)?;
+
+        match numbers.trim() {
+            "all" => return Ok(Domain::all()),
+            "" | "none" => return Ok(Domain::empty()),
+            _ => (),
+        }
+
+        numbers.split(',').try_fold(Domain::empty(), |acc, number| {
+            number
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| de::Error::custom(format!("invalid domain: {:?}", number.trim())))
+                .and_then(|i| Domain::at(i).map_err(de::Error::custom))
+                .map(|domain| acc | domain)
+        })
+    }
+}
+
+/// Audit options as `off`, `on` or `fix`
+mod audit_option {
+    use super::*;
+
+    pub fn serialize<S: ser::Serializer>(
+        option: &AuditOption,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match option {
+            AuditOption::Off => "off",
+            AuditOption::On => "on",
+            AuditOption::Fix => "fix",
+        })
+    }
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<AuditOption, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "off" => Ok(AuditOption::Off),
+            "on" => Ok(AuditOption::On),
+            "fix" => Ok(AuditOption::Fix),
+            other => Err(de::Error::custom(format!(
+                "unknown audit option: {other:?} (expected off, on or fix)"
+            ))),
+        }
+    }
+}
+
+/// Object types as `yubihsm-shell` names (e.g. `asymmetric-key`)
+mod object_type {
+    use super::*;
+
+    pub fn serialize<S: ser::Serializer>(
+        object_type: &object::Type,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&object_type.to_string())
+    }
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<object::Type, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        name.parse()
+            .map_err(|_| D::Error::custom(format!("unknown object type: {name:?}")))
+    }
+}
+
+/// Object algorithms as `yubihsm-shell` names
+mod object_algorithm {
+    use super::*;
+
+    /// Names of the algorithms objects in profiles can have
+    const NAMES: &[(&str, Algorithm)] = &[
+        (
+            "rsa2048",
+            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa2048),
+        ),
+        (
+            "rsa3072",
+            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa3072),
+        ),
+        (
+            "rsa4096",
+            Algorithm::Asymmetric(asymmetric::Algorithm::Rsa4096),
+        ),
+        (
+            "ecp224",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP224),
+        ),
+        (
+            "ecp256",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP256),
+        ),
+        (
+            "ecp384",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP384),
+        ),
+        (
+            "ecp521",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP521),
+        ),
+        (
+            "eck256",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcK256),
+        ),
+        (
+            "ecbp256",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp256),
+        ),
+        (
+            "ecbp384",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp384),
+        ),
+        (
+            "ecbp512",
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcBp512),
+        ),
+        (
+            "ed25519",
+            Algorithm::Asymmetric(asymmetric::Algorithm::Ed25519),
+        ),
+        ("hmac-sha1", Algorithm::Hmac(hmac::Algorithm::Sha1)),
+        ("hmac-sha256", Algorithm::Hmac(hmac::Algorithm::Sha256)),
+        ("hmac-sha384", Algorithm::Hmac(hmac::Algorithm::Sha384)),
+        ("hmac-sha512", Algorithm::Hmac(hmac::Algorithm::Sha512)),
+        ("opaque-data", Algorithm::Opaque(opaque::Algorithm::Data)),
+        (
+            "opaque-x509-certificate",
+            Algorithm::Opaque(opaque::Algorithm::X509Certificate),
+        ),
+        (
+            "template-ssh",
+            Algorithm::Template(template::Algorithm::Ssh),
+        ),
+    ];
+
+    pub fn serialize<S: ser::Serializer>(
+        algorithm: &Algorithm,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match NAMES.iter().find(|(_, alg)| alg == algorithm) {
+            Some((name, _)) => serializer.serialize_str(name),
+            None => Err(S::Error::custom(format!(
+                "unsupported algorithm: {algorithm:?}"
+            ))),
+        }
+    }
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Algorithm, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, algorithm)| *algorithm)
+            .ok_or_else(|| D::Error::custom(format!("unknown algorithm: {name:?}")))
+    }
+}
+
+/// Wrap algorithms as `yubihsm-shell` names
+mod wrap_algorithm {
+    use super::*;
+
+    pub fn serialize<S: ser::Serializer>(
+        algorithm: &Option<wrap::Algorithm>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match algorithm {
+            Some(wrap::Algorithm::Aes128Ccm) => serializer.serialize_str("aes128-ccm-wrap"),
+            Some(wrap::Algorithm::Aes192Ccm) => serializer.serialize_str("aes192-ccm-wrap"),
+            Some(wrap::Algorithm::Aes256Ccm) => serializer.serialize_str("aes256-ccm-wrap"),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<wrap::Algorithm>, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "aes128-ccm-wrap" => Ok(Some(wrap::Algorithm::Aes128Ccm)),
+            "aes192-ccm-wrap" => Ok(Some(wrap::Algorithm::Aes192Ccm)),
+            "aes256-ccm-wrap" => Ok(Some(wrap::Algorithm::Aes256Ccm)),
+            other => Err(de::Error::custom(format!(
+                "unknown wrap algorithm: {other:?}"
+            ))),
+        }
+    }
+}
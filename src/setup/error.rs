@@ -13,10 +13,18 @@ pub enum ErrorKind {
     #[error("invalid label")]
     LabelInvalid,
 
+    /// Malformed profile, or a secret it references couldn't be loaded
+    #[error("invalid profile")]
+    ProfileInvalid,
+
     /// Errors involving setup report generation
     #[error("report failed")]
     ReportFailed,
 
+    /// Setup report signature or contents don't match the device
+    #[error("report invalid")]
+    ReportInvalid,
+
     /// Error performing setup
     #[error("setup failed")]
     SetupFailed,
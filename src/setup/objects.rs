@@ -0,0 +1,324 @@
+//! Objects (asymmetric keys, HMAC keys, opaque objects and templates) to
+//! create when provisioning a device
+
+use super::{report::CreatedObject, Error, ErrorKind, Secret};
+use crate::{
+    asymmetric, hmac, object, opaque, ssh, template, Algorithm, Capability, Client, Domain,
+};
+use std::fmt::{self, Debug};
+use zeroize::Zeroize;
+
+/// Object to create when provisioning a device: either generated within
+/// the HSM or imported into it
+#[derive(Clone, Debug)]
+pub struct Object {
+    /// ID of the object
+    pub(super) id: object::Id,
+
+    /// Label for the object
+    pub(super) label: object::Label,
+
+    /// Domains the object is accessible from
+    pub(super) domains: Domain,
+
+    /// Capabilities of the object
+    pub(super) capabilities: Capability,
+
+    /// What to generate or import
+    pub(super) contents: Contents,
+
+    /// Reference to the file or secret imported contents were loaded from
+    /// (if the object was loaded from a profile)
+    pub(super) source: Option<Secret>,
+}
+
+impl Object {
+    /// Generate an asymmetric key within the HSM
+    pub fn generate_asymmetric_key(id: object::Id, algorithm: asymmetric::Algorithm) -> Self {
+        Self::new(id, Contents::GenerateAsymmetricKey(algorithm))
+    }
+
+    /// Import an existing asymmetric key
+    pub fn put_asymmetric_key(
+        id: object::Id,
+        algorithm: asymmetric::Algorithm,
+        key_bytes: impl Into<Vec<u8>>,
+    ) -> Result<Self, Error> {
+        let key_bytes = key_bytes.into();
+
+        ensure!(
+            key_bytes.len() == algorithm.key_len(),
+            ErrorKind::SetupFailed,
+            "invalid key length for {:?}: {} (expected {})",
+            algorithm,
+            key_bytes.len(),
+            algorithm.key_len()
+        );
+
+        Ok(Self::new(id, Contents::AsymmetricKey(algorithm, key_bytes)))
+    }
+
+    /// Generate an HMAC key within the HSM
+    pub fn generate_hmac_key(id: object::Id, algorithm: hmac::Algorithm) -> Self {
+        Self::new(id, Contents::GenerateHmacKey(algorithm))
+    }
+
+    /// Import an existing HMAC key
+    pub fn put_hmac_key(
+        id: object::Id,
+        algorithm: hmac::Algorithm,
+        key_bytes: impl Into<Vec<u8>>,
+    ) -> Result<Self, Error> {
+        let key_bytes = key_bytes.into();
+
+        ensure!(
+            !key_bytes.is_empty() && key_bytes.len() <= algorithm.max_key_len(),
+            ErrorKind::SetupFailed,
+            "invalid key length for {:?}: {} (max {})",
+            algorithm,
+            key_bytes.len(),
+            algorithm.max_key_len()
+        );
+
+        Ok(Self::new(id, Contents::HmacKey(algorithm, key_bytes)))
+    }
+
+    /// Store an opaque object (e.g. an X.509 certificate)
+    pub fn opaque(id: object::Id, algorithm: opaque::Algorithm, data: impl Into<Vec<u8>>) -> Self {
+        Self::new(id, Contents::Opaque(algorithm, data.into()))
+    }
+
+    /// Store an SSH certificate template
+    pub fn ssh_template(id: object::Id, template: ssh::Template) -> Self {
+        Self::new(id, Contents::SshTemplate(template.as_ref().to_vec()))
+    }
+
+    /// Set the label for this object
+    pub fn label<L>(mut self, label: L) -> Self
+    where
+        L: Into<object::Label>,
+    {
+        self.label = label.into();
+        self
+    }
+
+    /// Set the domains this object is accessible from
+    pub fn domains(mut self, domains: Domain) -> Self {
+        self.domains = domains;
+        self
+    }
+
+    /// Set the capabilities of this object
+    pub fn capabilities(mut self, capabilities: Capability) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Handle of this object
+    pub fn handle(&self) -> object::Handle {
+        object::Handle::new(self.id, self.contents.object_type())
+    }
+
+    /// Algorithm of this object
+    pub fn algorithm(&self) -> Algorithm {
+        self.contents.algorithm()
+    }
+
+    /// Is this object generated within the HSM (rather than imported)?
+    pub fn is_generated(&self) -> bool {
+        matches!(
+            self.contents,
+            Contents::GenerateAsymmetricKey(_) | Contents::GenerateHmacKey(_)
+        )
+    }
+
+    /// Create this object within the HSM
+    pub fn create(&self, client: &Client) -> Result<CreatedObject, Error> {
+        let label = self.label.clone();
+
+        match &self.contents {
+            Contents::GenerateAsymmetricKey(algorithm) => client.generate_asymmetric_key(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *algorithm,
+            ),
+            Contents::AsymmetricKey(algorithm, key_bytes) => client.put_asymmetric_key(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *algorithm,
+                key_bytes.clone(),
+            ),
+            Contents::GenerateHmacKey(algorithm) => client.generate_hmac_key(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *algorithm,
+            ),
+            Contents::HmacKey(algorithm, key_bytes) => client.put_hmac_key(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *algorithm,
+                key_bytes.clone(),
+            ),
+            Contents::Opaque(algorithm, data) => client.put_opaque(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *algorithm,
+                data.clone(),
+            ),
+            Contents::SshTemplate(data) => client.put_template(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                ssh::Template::from_bytes(data.clone()),
+            ),
+        }
+        .map_err(|e| {
+            format_err!(
+                ErrorKind::SetupFailed,
+                "error creating {} 0x{:04x}: {}",
+                self.contents.object_type(),
+                self.id,
+                e
+            )
+        })?;
+
+        let public_key = match self.contents {
+            Contents::GenerateAsymmetricKey(_) | Contents::AsymmetricKey(..) => {
+                Some(client.get_public_key(self.id)?)
+            }
+            _ => None,
+        };
+
+        // Only keys generated within the HSM can be attested. Attestation is
+        // best-effort, so a missing or unusable attestation key doesn't abort
+        // provisioning after the key has been created.
+        let attestation_certificate = match self.contents {
+            Contents::GenerateAsymmetricKey(_) => client
+                .sign_attestation_certificate(self.id, None)
+                .map_err(|e| {
+                    warn!(
+                        "couldn't attest {} 0x{:04x}: {}",
+                        self.contents.object_type(),
+                        self.id,
+                        e
+                    )
+                })
+                .ok(),
+            _ => None,
+        };
+
+        Ok(CreatedObject {
+            handle: self.handle(),
+            label: self.label.to_string(),
+            algorithm: self.algorithm(),
+            generated: self.is_generated(),
+            capabilities: self.capabilities,
+            delegated_capabilities: Capability::empty(),
+            domains: self.domains,
+            public_key,
+            attestation_certificate,
+        })
+    }
+
+    /// Create an object with default attributes
+    fn new(id: object::Id, contents: Contents) -> Self {
+        Self {
+            id,
+            label: Default::default(),
+            domains: Domain::empty(),
+            capabilities: Capability::empty(),
+            contents,
+            source: None,
+        }
+    }
+}
+
+/// What to generate or import
+#[derive(Clone)]
+pub(super) enum Contents {
+    /// Generate an asymmetric key
+    GenerateAsymmetricKey(asymmetric::Algorithm),
+
+    /// Import an asymmetric key
+    AsymmetricKey(asymmetric::Algorithm, Vec<u8>),
+
+    /// Generate an HMAC key
+    GenerateHmacKey(hmac::Algorithm),
+
+    /// Import an HMAC key
+    HmacKey(hmac::Algorithm, Vec<u8>),
+
+    /// Store opaque data
+    Opaque(opaque::Algorithm, Vec<u8>),
+
+    /// Store an SSH certificate template
+    SshTemplate(Vec<u8>),
+}
+
+impl Contents {
+    /// Type of the object
+    pub(super) fn object_type(&self) -> object::Type {
+        match self {
+            Contents::GenerateAsymmetricKey(_) | Contents::AsymmetricKey(..) => {
+                object::Type::AsymmetricKey
+            }
+            Contents::GenerateHmacKey(_) | Contents::HmacKey(..) => object::Type::HmacKey,
+            Contents::Opaque(..) => object::Type::Opaque,
+            Contents::SshTemplate(_) => object::Type::Template,
+        }
+    }
+
+    /// Algorithm of the object
+    pub(super) fn algorithm(&self) -> Algorithm {
+        match self {
+            Contents::GenerateAsymmetricKey(algorithm) | Contents::AsymmetricKey(algorithm, _) => {
+                (*algorithm).into()
+            }
+            Contents::GenerateHmacKey(algorithm) | Contents::HmacKey(algorithm, _) => {
+                (*algorithm).into()
+            }
+            Contents::Opaque(algorithm, _) => (*algorithm).into(),
+            Contents::SshTemplate(_) => template::Algorithm::Ssh.into(),
+        }
+    }
+}
+
+impl Debug for Contents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Avoid leaking secrets in debug messages
+        match self {
+            Contents::GenerateAsymmetricKey(algorithm) => {
+                write!(f, "GenerateAsymmetricKey({algorithm:?})")
+            }
+            Contents::AsymmetricKey(algorithm, _) => write!(f, "AsymmetricKey({algorithm:?}, ...)"),
+            Contents::GenerateHmacKey(algorithm) => write!(f, "GenerateHmacKey({algorithm:?})"),
+            Contents::HmacKey(algorithm, _) => write!(f, "HmacKey({algorithm:?}, ...)"),
+            Contents::Opaque(algorithm, data) => {
+                write!(f, "Opaque({algorithm:?}, {} bytes)", data.len())
+            }
+            Contents::SshTemplate(data) => write!(f, "SshTemplate({} bytes)", data.len()),
+        }
+    }
+}
+
+impl Drop for Contents {
+    fn drop(&mut self) {
+        match self {
+            Contents::AsymmetricKey(_, key_bytes) | Contents::HmacKey(_, key_bytes) => {
+                key_bytes.zeroize()
+            }
+            _ => (),
+        }
+    }
+}
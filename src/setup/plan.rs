@@ -0,0 +1,639 @@
+//! Non-destructive setup: converge an already-provisioned HSM toward a
+//! profile without resetting it.
+//!
+//! [`plan`] compares the authentication keys, wrap keys, other objects and
+//! audit options in the HSM against a [`Profile`] and returns the steps needed to reconcile
+//! them, which [`apply`] then executes one at a time. If a step fails, the
+//! steps completed so far are rolled back where possible, and the
+//! [`ApplyError`] reports what was and wasn't rolled back, along with what was
+//! applied (e.g. the shares of wrap keys which are still in the HSM).
+//!
+//! Objects in the HSM which aren't in the profile are left as they are.
+//! The device attestation key and its certificate aren't listed as such.
+
+use super::{
+    is_device_attestation, report::CreatedObject, role::Role, Error, ErrorKind, Object, Profile,
+};
+use crate::{command, device, object, wrap, AuditOption, Capability, Client, Domain};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+/// Steps needed to reconcile an HSM with a profile
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    /// Changes to make, in order
+    pub steps: Vec<Step>,
+
+    /// Objects in the profile which already match the HSM
+    pub unchanged: Vec<object::Handle>,
+
+    /// Objects in the HSM which aren't in the profile (and are left as is)
+    pub unmanaged: Vec<object::Info>,
+}
+
+impl Plan {
+    /// Is the HSM already in line with the profile?
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.steps.is_empty() {
+            writeln!(f, "no changes needed")?;
+        }
+
+        for step in &self.steps {
+            writeln!(f, "{step}")?;
+        }
+
+        if !self.unchanged.is_empty() {
+            writeln!(f, "= {} object(s) unchanged", self.unchanged.len())?;
+        }
+
+        for info in &self.unmanaged {
+            writeln!(
+                f,
+                "? {} 0x{:04x} \"{}\" is not in the profile (left as is)",
+                info.object_type, info.object_id, info.label
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Change to make to the HSM
+#[derive(Clone, Debug)]
+pub enum Step {
+    /// Put an authentication key for a role, replacing an existing key
+    /// with different attributes (if any)
+    PutAuthenticationKey {
+        /// Role to create
+        role: Role,
+
+        /// Existing key which will be deleted first
+        replaces: Option<object::Info>,
+    },
+
+    /// Put a wrap key, replacing an existing key with different attributes
+    /// (if any)
+    PutWrapKey {
+        /// Wrap key to create
+        key: wrap::Key,
+
+        /// Threshold and number of shares to split the key into
+        shares: Option<(u8, u8)>,
+
+        /// Existing key which will be deleted first
+        replaces: Option<object::Info>,
+    },
+
+    /// Create an asymmetric key, HMAC key, opaque object or template,
+    /// replacing an existing object with different attributes (if any)
+    PutObject {
+        /// Object to create
+        object: Object,
+
+        /// Existing object which will be deleted first
+        replaces: Option<object::Info>,
+    },
+
+    /// Change the auditing option for a command
+    SetCommandAudit {
+        /// Command to change the option for
+        command: command::Code,
+
+        /// Current option
+        from: AuditOption,
+
+        /// New option
+        to: AuditOption,
+    },
+
+    /// Change the force audit option
+    SetForceAudit {
+        /// Current option
+        from: AuditOption,
+
+        /// New option
+        to: AuditOption,
+    },
+}
+
+impl Step {
+    /// Handle of the object this step creates (if any)
+    pub fn handle(&self) -> Option<object::Handle> {
+        match self {
+            Step::PutAuthenticationKey { role, .. } => Some(object::Handle::new(
+                role.credentials.authentication_key_id,
+                object::Type::AuthenticationKey,
+            )),
+            Step::PutWrapKey { key, .. } => Some(object::Handle::new(
+                key.import_params.id,
+                object::Type::WrapKey,
+            )),
+            Step::PutObject { object, .. } => Some(object.handle()),
+            Step::SetCommandAudit { .. } | Step::SetForceAudit { .. } => None,
+        }
+    }
+
+    /// Execute this step, recording what it created.
+    ///
+    /// Everything which can be checked up front is checked before the object
+    /// being replaced (if any) is deleted, and `deleted` is set once it has
+    /// been, so a failure afterwards can be reported.
+    fn execute(
+        &self,
+        client: &Client,
+        applied: &mut Applied,
+        deleted: &mut bool,
+    ) -> Result<(), Error> {
+        let shares = match self {
+            Step::PutWrapKey { key, shares, .. } => shares
+                .map(|(threshold, count)| key.split(threshold, count))
+                .transpose()
+                .map_err(|e| format_err!(ErrorKind::SetupFailed, "{}", e))?,
+            _ => None,
+        };
+
+        if let (Some(handle), Some(_)) = (self.handle(), self.replaces()) {
+            if let Step::PutAuthenticationKey { role, .. } = self {
+                let session_key_id = client.session()?.authentication_key_id();
+
+                ensure!(
+                    role.credentials.authentication_key_id != session_key_id,
+                    ErrorKind::SetupFailed,
+                    "can't replace authentication key 0x{:04x}: the current session uses it",
+                    session_key_id
+                );
+            }
+
+            client.delete_object(handle.object_id, handle.object_type)?;
+            *deleted = true;
+        }
+
+        match self {
+            Step::PutAuthenticationKey { role, .. } => {
+                role.create(client)?;
+                applied.objects.push(CreatedObject::from(role));
+            }
+            Step::PutWrapKey { key, .. } => {
+                key.create(client)?;
+                applied.objects.push(CreatedObject::from(key));
+
+                if let Some(shares) = shares {
+                    applied.wrap_key_shares.insert(key.import_params.id, shares);
+                }
+            }
+            Step::PutObject { object, .. } => applied.objects.push(object.create(client)?),
+            Step::SetCommandAudit { command, to, .. } => {
+                client.set_command_audit_option(*command, *to)?
+            }
+            Step::SetForceAudit { to, .. } => client.set_force_audit_option(*to)?,
+        }
+
+        Ok(())
+    }
+
+    /// Undo this step (if possible)
+    fn roll_back(&self, client: &Client) -> Result<(), Error> {
+        match self {
+            Step::SetCommandAudit { command, from, to } => {
+                ensure!(
+                    *to != AuditOption::Fix,
+                    ErrorKind::SetupFailed,
+                    "command auditing can't be changed once fixed"
+                );
+
+                client.set_command_audit_option(*command, *from)?;
+            }
+            Step::SetForceAudit { from, to } => {
+                ensure!(
+                    *to != AuditOption::Fix,
+                    ErrorKind::SetupFailed,
+                    "force audit can't be changed once fixed"
+                );
+
+                client.set_force_audit_option(*from)?;
+            }
+            _ => {
+                ensure!(
+                    self.replaces().is_none(),
+                    ErrorKind::SetupFailed,
+                    "the replaced object can't be restored"
+                );
+
+                let handle = self.handle().unwrap();
+                client.delete_object(handle.object_id, handle.object_type)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Existing object this step replaces
+    fn replaces(&self) -> Option<&object::Info> {
+        match self {
+            Step::PutAuthenticationKey { replaces, .. }
+            | Step::PutWrapKey { replaces, .. }
+            | Step::PutObject { replaces, .. } => replaces.as_ref(),
+            Step::SetCommandAudit { .. } | Step::SetForceAudit { .. } => None,
+        }
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::PutAuthenticationKey { role, replaces } => write_put(
+                f,
+                "authentication key",
+                role.credentials.authentication_key_id,
+                &role.authentication_key_label,
+                replaces,
+            ),
+            Step::PutWrapKey {
+                key,
+                shares,
+                replaces,
+            } => {
+                write_put(
+                    f,
+                    "wrap key",
+                    key.import_params.id,
+                    &key.import_params.label,
+                    replaces,
+                )?;
+
+                if let Some((threshold, count)) = shares {
+                    write!(f, ", split into {threshold}-of-{count} shares")?;
+                }
+
+                Ok(())
+            }
+            Step::PutObject { object, replaces } => {
+                let handle = object.handle();
+
+                write_put(
+                    f,
+                    &handle.object_type.to_string(),
+                    handle.object_id,
+                    &object.label,
+                    replaces,
+                )?;
+
+                if object.is_generated() {
+                    write!(f, ", generated")?;
+                }
+
+                Ok(())
+            }
+            Step::SetCommandAudit { command, from, to } => {
+                write!(f, "~ set audit for {command:?}: {from:?} -> {to:?}")
+            }
+            Step::SetForceAudit { from, to } => {
+                write!(f, "~ set force audit: {from:?} -> {to:?}")
+            }
+        }
+    }
+}
+
+/// Describe creating or replacing an object
+fn write_put(
+    f: &mut fmt::Formatter<'_>,
+    kind: &str,
+    id: object::Id,
+    label: &object::Label,
+    replaces: &Option<object::Info>,
+) -> fmt::Result {
+    match replaces {
+        Some(existing) => write!(
+            f,
+            "~ replace {kind} 0x{id:04x} \"{label}\" (was \"{}\", capabilities 0x{:x}, delegated 0x{:x}, domains 0x{:x})",
+            existing.label,
+            existing.capabilities.bits(),
+            existing.delegated_capabilities.bits(),
+            existing.domains.bits()
+        ),
+        None => write!(f, "+ create {kind} 0x{id:04x} \"{label}\""),
+    }
+}
+
+/// Result of applying a plan
+#[derive(Clone, Debug, Default)]
+pub struct Applied {
+    /// Steps which were executed
+    pub steps: Vec<Step>,
+
+    /// Objects which were created
+    pub objects: Vec<CreatedObject>,
+
+    /// Shares of split wrap keys which were created, by key ID
+    pub wrap_key_shares: BTreeMap<object::Id, Vec<wrap::Share>>,
+}
+
+/// Compare the HSM against a profile and plan the changes needed to
+/// reconcile them.
+///
+/// The secrets of existing keys can't be read back, so objects whose
+/// attributes (label, capabilities, delegated capabilities, domains and, for
+/// objects other than roles and wrap keys, algorithm) match the profile are
+/// assumed to be unchanged.
+///
+/// FIPS mode can only be changed on a freshly reset device, so planning
+/// fails if it doesn't already match the profile.
+pub fn plan(client: &Client, profile: &Profile) -> Result<Plan, Error> {
+    if let Some(enabled) = profile.fips_mode {
+        let fips_option = client.get_fips_option()?;
+
+        ensure!(
+            (fips_option != AuditOption::Off) == enabled,
+            ErrorKind::SetupFailed,
+            "FIPS mode is {:?} and can only be changed after resetting the device",
+            fips_option
+        );
+    }
+
+    let mut plan = Plan::default();
+    let mut managed = vec![];
+
+    for role in &profile.roles {
+        let handle = object::Handle::new(
+            role.credentials.authentication_key_id,
+            object::Type::AuthenticationKey,
+        );
+
+        let existing = get_object_info(client, &handle)?;
+
+        if matches(
+            existing.as_ref(),
+            &role.authentication_key_label,
+            role.capabilities,
+            role.delegated_capabilities,
+            role.domains,
+        ) {
+            plan.unchanged.push(handle.clone());
+        } else {
+            plan.steps.push(Step::PutAuthenticationKey {
+                role: role.clone(),
+                replaces: existing,
+            });
+        }
+
+        managed.push(handle);
+    }
+
+    let wrap_keys = profile.wrap_keys.iter().map(|key| (key, None)).chain(
+        profile
+            .split_wrap_keys
+            .iter()
+            .map(|split| (&split.key, Some((split.threshold, split.count)))),
+    );
+
+    for (key, shares) in wrap_keys {
+        let handle = object::Handle::new(key.import_params.id, object::Type::WrapKey);
+        let existing = get_object_info(client, &handle)?;
+
+        if matches(
+            existing.as_ref(),
+            &key.import_params.label,
+            key.import_params.capabilities,
+            key.delegated_capabilities,
+            key.import_params.domains,
+        ) {
+            plan.unchanged.push(handle.clone());
+        } else {
+            plan.steps.push(Step::PutWrapKey {
+                key: key.clone(),
+                shares,
+                replaces: existing,
+            });
+        }
+
+        managed.push(handle);
+    }
+
+    for object in &profile.objects {
+        let handle = object.handle();
+        let existing = get_object_info(client, &handle)?;
+
+        if matches(
+            existing.as_ref(),
+            &object.label,
+            object.capabilities,
+            Capability::empty(),
+            object.domains,
+        ) && existing.as_ref().map(|info| info.algorithm) == Some(object.algorithm())
+        {
+            plan.unchanged.push(handle.clone());
+        } else {
+            plan.steps.push(Step::PutObject {
+                object: object.clone(),
+                replaces: existing,
+            });
+        }
+
+        managed.push(handle);
+    }
+
+    for (command, option) in &profile.command_audit_options {
+        let current = client.get_command_audit_option(*command)?;
+
+        if current != *option {
+            ensure!(
+                current != AuditOption::Fix,
+                ErrorKind::SetupFailed,
+                "auditing for {:?} is fixed on and can't be changed to {:?}",
+                command,
+                option
+            );
+
+            plan.steps.push(Step::SetCommandAudit {
+                command: *command,
+                from: current,
+                to: *option,
+            });
+        }
+    }
+
+    let audit_option = client.get_force_audit_option()?;
+
+    if audit_option != profile.audit_option {
+        ensure!(
+            audit_option != AuditOption::Fix,
+            ErrorKind::SetupFailed,
+            "force audit is fixed on and can't be changed to {:?}",
+            profile.audit_option
+        );
+
+        plan.steps.push(Step::SetForceAudit {
+            from: audit_option,
+            to: profile.audit_option,
+        });
+    }
+
+    for entry in client.list_objects(&[])? {
+        let handle = object::Handle::new(entry.object_id, entry.object_type);
+
+        if managed.contains(&handle) {
+            continue;
+        }
+
+        let info = client.get_object_info(entry.object_id, entry.object_type)?;
+
+        if !is_device_attestation(&info) {
+            plan.unmanaged.push(info);
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Execute a plan step by step.
+///
+/// If a step fails, the steps completed before it are rolled back in reverse
+/// order where possible (replaced keys and fixed audit options can't be
+/// restored), and the returned [`ApplyError`] holds what was applied, the
+/// outcome of each rollback, and the object the failed step deleted (if it
+/// got that far).
+///
+/// Steps which would replace the authentication key of the client's current
+/// session fail without deleting it.
+pub fn apply(client: &Client, plan: &Plan) -> Result<Applied, ApplyError> {
+    let mut applied = Applied::default();
+
+    for step in &plan.steps {
+        info!("applying: {}", step);
+
+        let mut deleted = false;
+
+        if let Err(error) = step.execute(client, &mut applied, &mut deleted) {
+            let rollback = applied
+                .steps
+                .iter()
+                .rev()
+                .map(|completed| {
+                    let result = completed.roll_back(client);
+
+                    if let Err(rollback_error) = &result {
+                        warn!("couldn't roll back: {}: {}", completed, rollback_error);
+                    }
+
+                    (completed.clone(), result)
+                })
+                .collect();
+
+            return Err(ApplyError {
+                step_number: applied.steps.len() + 1,
+                step_count: plan.steps.len(),
+                step: step.clone(),
+                error,
+                deleted: step.replaces().filter(|_| deleted).cloned(),
+                applied,
+                rollback,
+            });
+        }
+
+        applied.steps.push(step.clone());
+    }
+
+    Ok(applied)
+}
+
+/// Failure to apply a plan
+#[derive(Debug)]
+pub struct ApplyError {
+    /// Number of the step which failed (starting at 1)
+    pub step_number: usize,
+
+    /// Number of steps in the plan
+    pub step_count: usize,
+
+    /// Step which failed
+    pub step: Step,
+
+    /// Error the step failed with
+    pub error: Error,
+
+    /// Object the failed step deleted before it failed, which is gone
+    pub deleted: Option<object::Info>,
+
+    /// Steps completed before the failure, and the objects and wrap key
+    /// shares they created. Steps which couldn't be rolled back are still in
+    /// effect, and these shares are the only copy of their wrap keys.
+    pub applied: Applied,
+
+    /// Outcome of rolling back each completed step, most recent first
+    pub rollback: Vec<(Step, Result<(), Error>)>,
+}
+
+impl Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "error applying step {} of {} ({}): {}",
+            self.step_number, self.step_count, self.step, self.error
+        )?;
+
+        if let Some(replaced) = &self.deleted {
+            write!(
+                f,
+                "\nNOT restored: {} 0x{:04x} \"{}\" (deleted before the step failed)",
+                replaced.object_type, replaced.object_id, replaced.label
+            )?;
+        }
+
+        for (step, result) in &self.rollback {
+            match result {
+                Ok(()) => write!(f, "\nrolled back: {step}")?,
+                Err(e) => write!(f, "\nNOT rolled back: {step} ({e})")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApplyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ApplyError> for Error {
+    fn from(apply_error: ApplyError) -> Error {
+        format_err!(ErrorKind::SetupFailed, "{}", apply_error).into()
+    }
+}
+
+/// Get information about an object, if it exists
+fn get_object_info(
+    client: &Client,
+    handle: &object::Handle,
+) -> Result<Option<object::Info>, Error> {
+    match client.get_object_info(handle.object_id, handle.object_type) {
+        Ok(info) => Ok(Some(info)),
+        Err(e) if e.device_error() == Some(device::ErrorKind::ObjectNotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Does an existing object have the given attributes?
+fn matches(
+    existing: Option<&object::Info>,
+    label: &object::Label,
+    capabilities: Capability,
+    delegated_capabilities: Capability,
+    domains: Domain,
+) -> bool {
+    existing.is_some_and(|info| {
+        info.label == *label
+            && info.capabilities == capabilities
+            && info.delegated_capabilities == delegated_capabilities
+            && info.domains == domains
+    })
+}
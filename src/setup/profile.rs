@@ -1,8 +1,12 @@
 //! Device provisioning profiles: all attributes required to initialize a device
 
-use super::{role::Role, Error, Report};
-use crate::{object, wrap, AuditOption, Client};
-use std::time::Duration;
+use super::{
+    report::{AuditSettings, CreatedObject},
+    role::Role,
+    Error, ErrorKind, Object, Report, Secret,
+};
+use crate::{command, object, wrap, AuditOption, Client};
+use std::{collections::BTreeMap, time::Duration};
 
 /// Temporary account key to use for device provisioning.
 /// Uses key ID #65534 as 65535 is reserved for internal use.
@@ -34,10 +38,32 @@ pub struct Profile {
     /// imported into other devices.
     pub(super) wrap_keys: Vec<wrap::Key>,
 
+    /// Wrap keys which are split into M-of-N shares when provisioning, to be
+    /// distributed to key custodians
+    pub(super) split_wrap_keys: Vec<SplitWrapKey>,
+
+    /// References to the secrets wrap keys were loaded from, by key ID, so
+    /// the profile can be serialized without including the keys themselves
+    pub(super) wrap_key_secrets: BTreeMap<object::Id, Secret>,
+
+    /// Asymmetric keys, HMAC keys, opaque objects and templates to generate
+    /// or import
+    pub(super) objects: Vec<Object>,
+
+    /// Per-command auditing options
+    pub(super) command_audit_options: BTreeMap<command::Code, AuditOption>,
+
+    /// Enable (or disable) FIPS mode. Must be set on a freshly reset device,
+    /// before any keys are created.
+    pub(super) fips_mode: Option<bool>,
+
     /// Store a JSON copy of the provisioning report in the given opaque
     /// object slot
     pub(super) report_object_id: Option<object::Id>,
 
+    /// Sign the provisioning report with the given ECDSA key
+    pub(super) report_signing_key_id: Option<object::Id>,
+
     /// How long to wait for the device to reset before giving up
     pub(super) reset_device_timeout: Duration,
 }
@@ -50,7 +76,13 @@ impl Default for Profile {
             audit_option: AuditOption::Off,
             roles: Vec::new(),
             wrap_keys: Vec::new(),
+            split_wrap_keys: Vec::new(),
+            wrap_key_secrets: BTreeMap::new(),
+            objects: Vec::new(),
+            command_audit_options: BTreeMap::new(),
+            fips_mode: None,
             report_object_id: Some(DEFAULT_REPORT_OBJECT_ID),
+            report_signing_key_id: None,
             reset_device_timeout: Duration::from_secs(10),
         }
     }
@@ -94,16 +126,128 @@ impl Profile {
         self
     }
 
+    /// Set the asymmetric keys, HMAC keys, opaque objects and templates to
+    /// generate or import
+    pub fn objects<I>(mut self, objects: I) -> Self
+    where
+        I: IntoIterator<Item = Object>,
+    {
+        self.objects = objects.into_iter().collect();
+        self
+    }
+
+    /// Configure the auditing option for a particular command
+    pub fn command_audit_option(mut self, command: command::Code, option: AuditOption) -> Self {
+        self.command_audit_options.insert(command, option);
+        self
+    }
+
+    /// Enable or disable FIPS mode
+    pub fn fips_mode(mut self, enabled: bool) -> Self {
+        self.fips_mode = Some(enabled);
+        self
+    }
+
+    /// Sign the provisioning report with the given ECDSA (NIST P-256 or
+    /// P-384) key, which can be one of the objects created by this profile.
+    /// The key needs the `sign-ecdsa` capability.
+    pub fn report_signing_key(mut self, key_id: object::Id) -> Self {
+        self.report_signing_key_id = Some(key_id);
+        self
+    }
+
+    /// Provision the given wrap key and split it into `count` shares, any
+    /// `threshold` of which can later recover it. The shares are returned in
+    /// the provisioning report (but never stored in the HSM).
+    pub fn split_wrap_key(mut self, key: wrap::Key, threshold: u8, count: u8) -> Self {
+        self.split_wrap_keys.push(SplitWrapKey {
+            key,
+            threshold,
+            count,
+            generated: false,
+        });
+        self
+    }
+
+    /// Recombine a wrap key from shares made during an earlier provisioning,
+    /// and add it to the wrap keys to provision
+    pub fn wrap_key_shares(mut self, shares: &[wrap::Share]) -> Result<Self, Error> {
+        let key = wrap::Key::combine(shares).map_err(|e| {
+            format_err!(
+                ErrorKind::SetupFailed,
+                "error recombining wrap key shares: {}",
+                e
+            )
+        })?;
+
+        self.wrap_keys.push(key);
+        Ok(self)
+    }
+
     /// Use this profile to provision the YubiHSM 2 with the given client
     pub fn provision(&self, client: &Client) -> Result<Report, Error> {
+        // Split keys before installing anything, so invalid thresholds are
+        // caught up front
+        let mut wrap_key_shares = BTreeMap::new();
+
+        for split in &self.split_wrap_keys {
+            let shares = split.key.split(split.threshold, split.count).map_err(|e| {
+                format_err!(
+                    ErrorKind::SetupFailed,
+                    "error splitting wrap key 0x{:04x}: {}",
+                    split.key.import_params.id,
+                    e
+                )
+            })?;
+
+            wrap_key_shares.insert(split.key.import_params.id, shares);
+        }
+
+        // FIPS mode has to be set before any keys are created
+        if let Some(enabled) = self.fips_mode {
+            info!("setting FIPS mode to: {}", enabled);
+            client.set_fips_option(if enabled {
+                AuditOption::On
+            } else {
+                AuditOption::Off
+            })?;
+        }
+
+        let mut created = vec![];
+
         for role in &self.roles {
             info!("installing role: {}", role.authentication_key_label);
             role.create(client)?;
+            created.push(CreatedObject::from(role));
         }
 
         for wrap_key in &self.wrap_keys {
             info!("installing wrap key: {}", &wrap_key.import_params.label);
             wrap_key.create(client)?;
+            created.push(CreatedObject::from(wrap_key));
+        }
+
+        for split in &self.split_wrap_keys {
+            info!(
+                "installing {}-of-{} split wrap key: {}",
+                split.threshold, split.count, &split.key.import_params.label
+            );
+            split.key.create(client)?;
+            created.push(CreatedObject::from(&split.key));
+        }
+
+        for object in &self.objects {
+            let handle = object.handle();
+            info!(
+                "creating {} 0x{:04x}: {}",
+                handle.object_type, handle.object_id, object.label
+            );
+            created.push(object.create(client)?);
+        }
+
+        for (command, option) in &self.command_audit_options {
+            info!("setting audit option for {:?} to: {:?}", command, option);
+            client.set_command_audit_option(*command, *option)?;
         }
 
         if self.audit_option != AuditOption::Off {
@@ -111,7 +255,14 @@ impl Profile {
             client.set_force_audit_option(self.audit_option)?;
         }
 
-        let report = Report::new(client.device_info()?.serial_number);
+        let mut report = Report::new(client.device_info()?.serial_number);
+        report.objects = created;
+        report.audit = Some(AuditSettings::read(client, self.fips_mode.is_some())?);
+
+        if let Some(key_id) = self.report_signing_key_id {
+            info!("signing provisioning report with key 0x{:x}", key_id);
+            report.sign(client, key_id)?;
+        }
 
         if let Some(report_object_id) = self.report_object_id {
             info!(
@@ -121,6 +272,23 @@ impl Profile {
             report.store(client, report_object_id)?;
         }
 
+        report.wrap_key_shares = wrap_key_shares;
         Ok(report)
     }
 }
+
+/// Wrap key to be split into shares when provisioning
+#[derive(Clone, Debug)]
+pub(super) struct SplitWrapKey {
+    /// Key to split
+    pub(super) key: wrap::Key,
+
+    /// Number of shares needed to recover the key
+    pub(super) threshold: u8,
+
+    /// Total number of shares
+    pub(super) count: u8,
+
+    /// Was the key generated from a profile (rather than supplied)?
+    pub(super) generated: bool,
+}
@@ -1,16 +1,28 @@
 //! YubiHSM 2 provisioning reports which record the server where the HSM was
 //! provisioned, the username which performed the provisioning operation,
 //! and the date provisioning occurred.
+//!
+//! Reports list the objects which were created (along with the public keys
+//! and attestation certificates of asymmetric keys) and the resulting audit
+//! settings. They can be signed by an ECDSA key within the HSM, in which case
+//! [`Report::verify`] checks the signature and cross-checks the report
+//! against the device.
 
-use super::{Error, ErrorKind};
+use super::{Error, ErrorKind, Role};
 use crate::{
+    asymmetric, attestation,
+    audit::{AuditCommand, AuditOption},
+    authentication,
     device::SerialNumber,
+    ecdsa::{NistP256, NistP384},
     object, opaque,
     uuid::{self, Uuid},
-    Capability, Client, Domain,
+    wrap, Algorithm, Capability, Client, Domain,
 };
 use serde::{Deserialize, Serialize};
-use std::{env, str::FromStr};
+use sha2::{Digest, Sha256, Sha384};
+use signature::Verifier;
+use std::{collections::BTreeMap, env, str::FromStr};
 use time::OffsetDateTime as DateTime;
 
 /// Label string for the provisioning report object
@@ -49,6 +61,23 @@ pub struct Report {
 
     /// Software that performed the provisioning
     pub software: String,
+
+    /// Objects which were created
+    #[serde(default)]
+    pub objects: Vec<CreatedObject>,
+
+    /// Audit settings of the device after provisioning
+    #[serde(default)]
+    pub audit: Option<AuditSettings>,
+
+    /// Signature over the rest of the report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+
+    /// Shares of split wrap keys, by key ID, to be distributed to key
+    /// custodians. These are never serialized or stored in the HSM.
+    #[serde(skip)]
+    pub wrap_key_shares: BTreeMap<object::Id, Vec<wrap::Share>>,
 }
 
 impl Report {
@@ -56,16 +85,31 @@ impl Report {
     pub fn new(serial_number: SerialNumber) -> Self {
         // TODO: handle these better on operating systems other than *IX
         Report {
-            version: Version(1),
+            version: Version(3),
             uuid: uuid::new_v4(),
             device_serial_number: serial_number.to_string(),
             username: env::var("LOGNAME").ok(),
             hostname: env::var("HOSTNAME").ok(),
             date: DateTime::now_utc(),
             software: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            objects: vec![],
+            audit: None,
+            signature: None,
+            wrap_key_shares: BTreeMap::new(),
         }
     }
 
+    /// Load a report previously stored in the YubiHSM at the given object ID
+    pub fn load(client: &Client, report_object_id: object::Id) -> Result<Self, Error> {
+        let json = client
+            .get_opaque(report_object_id)
+            .map_err(|e| format_err!(ErrorKind::ReportFailed, "{}", e))?;
+
+        String::from_utf8(json)
+            .map_err(|e| format_err!(ErrorKind::ReportFailed, "report isn't UTF-8: {}", e))?
+            .parse()
+    }
+
     /// Serialize a report as JSON
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap()
@@ -86,6 +130,325 @@ impl Report {
 
         Ok(())
     }
+
+    /// Sign this report with the given ECDSA (NIST P-256 or P-384) key
+    pub fn sign(&mut self, client: &Client, key_id: object::Id) -> Result<(), Error> {
+        self.signature = None;
+
+        let algorithm = client
+            .get_public_key(key_id)
+            .map_err(|e| format_err!(ErrorKind::ReportFailed, "{}", e))?
+            .algorithm;
+
+        let message = self.to_json();
+
+        let digest = match algorithm {
+            asymmetric::Algorithm::EcP256 => Sha256::digest(&message).to_vec(),
+            asymmetric::Algorithm::EcP384 => Sha384::digest(&message).to_vec(),
+            other => fail!(
+                ErrorKind::ReportFailed,
+                "unsupported report signing key algorithm: {:?}",
+                other
+            ),
+        };
+
+        let signature = client
+            .sign_ecdsa_prehash_raw(key_id, digest)
+            .map_err(|e| format_err!(ErrorKind::ReportFailed, "error signing report: {}", e))?;
+
+        self.signature = Some(Signature {
+            key_id,
+            algorithm,
+            signature,
+        });
+
+        Ok(())
+    }
+
+    /// Verify the signature on this report with the given public key
+    pub fn verify_signature(&self, public_key: &asymmetric::PublicKey) -> Result<(), Error> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| format_err!(ErrorKind::ReportInvalid, "report isn't signed"))?;
+
+        ensure!(
+            public_key.algorithm == signature.algorithm,
+            ErrorKind::ReportInvalid,
+            "report was signed with a {:?} key, not {:?}",
+            signature.algorithm,
+            public_key.algorithm
+        );
+
+        let mut unsigned = self.clone();
+        unsigned.signature = None;
+        let message = unsigned.to_json();
+
+        let valid = match signature.algorithm {
+            asymmetric::Algorithm::EcP256 => public_key
+                .ecdsa::<NistP256>()
+                .and_then(|point| p256::ecdsa::VerifyingKey::from_encoded_point(&point).ok())
+                .zip(p256::ecdsa::Signature::from_der(&signature.signature).ok())
+                .is_some_and(|(key, sig)| key.verify(message.as_bytes(), &sig).is_ok()),
+            asymmetric::Algorithm::EcP384 => public_key
+                .ecdsa::<NistP384>()
+                .and_then(|point| p384::ecdsa::VerifyingKey::from_encoded_point(&point).ok())
+                .zip(p384::ecdsa::Signature::from_der(&signature.signature).ok())
+                .is_some_and(|(key, sig)| key.verify(message.as_bytes(), &sig).is_ok()),
+            _ => false,
+        };
+
+        ensure!(valid, ErrorKind::ReportInvalid, "invalid report signature");
+        Ok(())
+    }
+
+    /// Verify the signature on this report with the given signing key in the
+    /// HSM, then check the objects and audit settings it lists against the
+    /// device.
+    ///
+    /// Reports signed by any other key are rejected: the key named in the
+    /// report can't be trusted just because the report names it.
+    ///
+    /// Objects created after provisioning are ignored, but any object in the
+    /// report which is missing or has different attributes is an error.
+    pub fn verify(&self, client: &Client, signing_key_id: object::Id) -> Result<(), Error> {
+        let serial_number = client.device_info()?.serial_number.to_string();
+
+        ensure!(
+            self.device_serial_number == serial_number,
+            ErrorKind::ReportInvalid,
+            "report is for device {}, not {}",
+            self.device_serial_number,
+            serial_number
+        );
+
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| format_err!(ErrorKind::ReportInvalid, "report isn't signed"))?;
+
+        ensure!(
+            signature.key_id == signing_key_id,
+            ErrorKind::ReportInvalid,
+            "report is signed by key 0x{:04x}, not 0x{:04x}",
+            signature.key_id,
+            signing_key_id
+        );
+
+        let signing_key = client.get_public_key(signature.key_id).map_err(|e| {
+            format_err!(
+                ErrorKind::ReportInvalid,
+                "error getting report signing key 0x{:04x}: {}",
+                signature.key_id,
+                e
+            )
+        })?;
+
+        self.verify_signature(&signing_key)?;
+
+        for object in &self.objects {
+            object.verify(client)?;
+        }
+
+        if let Some(audit) = &self.audit {
+            audit.verify(client)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Signature over a report by a key within the HSM
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Signature {
+    /// ID of the key which signed the report
+    pub key_id: object::Id,
+
+    /// Algorithm of the key which signed the report
+    pub algorithm: asymmetric::Algorithm,
+
+    /// ASN.1 DER-encoded ECDSA signature over the report's JSON serialization
+    /// (without the signature)
+    pub signature: Vec<u8>,
+}
+
+/// Audit settings of a device
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AuditSettings {
+    /// Force audit option
+    pub force: AuditOption,
+
+    /// FIPS mode (only read from devices provisioned with a FIPS mode, as
+    /// devices which aren't FIPS capable don't support the option)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fips: Option<AuditOption>,
+
+    /// Audit options for each command
+    pub commands: Vec<AuditCommand>,
+}
+
+impl AuditSettings {
+    /// Read the audit settings of a device, including FIPS mode if `fips`
+    /// is set
+    pub fn read(client: &Client, fips: bool) -> Result<Self, Error> {
+        Ok(Self {
+            force: client.get_force_audit_option()?,
+            fips: if fips {
+                Some(client.get_fips_option()?)
+            } else {
+                None
+            },
+            commands: client.get_commands_audit_options()?,
+        })
+    }
+
+    /// Check these settings against the device
+    fn verify(&self, client: &Client) -> Result<(), Error> {
+        let current = Self::read(client, self.fips.is_some())?;
+
+        ensure!(
+            current.force == self.force && current.fips == self.fips,
+            ErrorKind::ReportInvalid,
+            "audit settings changed: force audit {:?} -> {:?}, FIPS {:?} -> {:?}",
+            self.force,
+            current.force,
+            self.fips,
+            current.fips
+        );
+
+        for command in &self.commands {
+            let option = current
+                .commands
+                .iter()
+                .find(|c| c.command_type() == command.command_type())
+                .map(AuditCommand::audit_option);
+
+            ensure!(
+                option == Some(command.audit_option()),
+                ErrorKind::ReportInvalid,
+                "audit option for {:?} changed: {:?} -> {:?}",
+                command.command_type(),
+                command.audit_option(),
+                option
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Object created when provisioning a device
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreatedObject {
+    /// Handle of the object
+    pub handle: object::Handle,
+
+    /// Label of the object
+    pub label: String,
+
+    /// Algorithm of the object
+    pub algorithm: Algorithm,
+
+    /// Was the object generated within the HSM (rather than imported)?
+    pub generated: bool,
+
+    /// Capabilities of the object
+    pub capabilities: Capability,
+
+    /// Delegated capabilities of the object
+    pub delegated_capabilities: Capability,
+
+    /// Domains the object is accessible from
+    pub domains: Domain,
+
+    /// Public key (for asymmetric keys)
+    pub public_key: Option<asymmetric::PublicKey>,
+
+    /// Attestation certificate (for asymmetric keys generated within the HSM,
+    /// if the device could attest them)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_certificate: Option<attestation::Certificate>,
+}
+
+impl CreatedObject {
+    /// Check this object against the device
+    fn verify(&self, client: &Client) -> Result<(), Error> {
+        let handle = &self.handle;
+
+        let info = client
+            .get_object_info(handle.object_id, handle.object_type)
+            .map_err(|e| {
+                format_err!(
+                    ErrorKind::ReportInvalid,
+                    "{} 0x{:04x} \"{}\": {}",
+                    handle.object_type,
+                    handle.object_id,
+                    self.label,
+                    e
+                )
+            })?;
+
+        ensure!(
+            info.label.to_string() == self.label
+                && info.algorithm == self.algorithm
+                && info.capabilities == self.capabilities
+                && info.delegated_capabilities == self.delegated_capabilities
+                && info.domains == self.domains,
+            ErrorKind::ReportInvalid,
+            "{} 0x{:04x} \"{}\" doesn't match the report",
+            handle.object_type,
+            handle.object_id,
+            self.label
+        );
+
+        if let Some(public_key) = &self.public_key {
+            ensure!(
+                client.get_public_key(handle.object_id)? == *public_key,
+                ErrorKind::ReportInvalid,
+                "public key of {} 0x{:04x} \"{}\" doesn't match the report",
+                handle.object_type,
+                handle.object_id,
+                self.label
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&Role> for CreatedObject {
+    fn from(role: &Role) -> Self {
+        Self {
+            handle: object::Handle::new(
+                role.credentials.authentication_key_id,
+                object::Type::AuthenticationKey,
+            ),
+            label: role.authentication_key_label.to_string(),
+            algorithm: authentication::Algorithm::default().into(),
+            generated: false,
+            capabilities: role.capabilities,
+            delegated_capabilities: role.delegated_capabilities,
+            domains: role.domains,
+            public_key: None,
+            attestation_certificate: None,
+        }
+    }
+}
+
+impl From<&wrap::Key> for CreatedObject {
+    fn from(key: &wrap::Key) -> Self {
+        Self {
+            handle: object::Handle::new(key.import_params.id, object::Type::WrapKey),
+            label: key.import_params.label.to_string(),
+            algorithm: key.import_params.algorithm,
+            generated: false,
+            capabilities: key.import_params.capabilities,
+            delegated_capabilities: key.delegated_capabilities,
+            domains: key.import_params.domains,
+            public_key: None,
+            attestation_certificate: None,
+        }
+    }
 }
 
 impl FromStr for Report {
@@ -1,6 +1,6 @@
 //! Roles for interacting with the YubiHSM 2
 
-use super::{Error, ErrorKind};
+use super::{Error, ErrorKind, Secret};
 use crate::Client;
 pub use crate::{object, Capability, Credentials, Domain};
 
@@ -13,6 +13,10 @@ pub struct Role {
     /// Credentials (auth key and ID) used to authenticate with this role
     pub(super) credentials: Credentials,
 
+    /// Reference to the password the authentication key was derived from
+    /// (if it was loaded from a profile)
+    pub(super) authentication_key_secret: Option<Secret>,
+
     /// Permissions for this role
     pub(super) capabilities: Capability,
 
@@ -29,6 +33,7 @@ impl Role {
         Self {
             authentication_key_label: Default::default(),
             credentials,
+            authentication_key_secret: None,
             capabilities: Capability::empty(),
             delegated_capabilities: Capability::empty(),
             domains: Domain::empty(),
@@ -6,7 +6,7 @@
 //! You will need to enable the `untested` cargo feature to use it.
 
 mod certificate;
-#[cfg(feature = "untested")]
+#[cfg(any(feature = "untested", feature = "mockhsm"))]
 pub(crate) mod commands;
 mod template;
 
@@ -12,10 +12,16 @@ use crate::{
     response::Response,
     ssh,
 };
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, Deserializer},
+    Deserialize, Serialize,
+};
+
+/// Size of the RSA-2048 signature over the timestamp and request
+pub(crate) const TIMESTAMP_SIGNATURE_SIZE: usize = 256;
 
 /// Request parameters for `command::sign_ssh_certificate`
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Debug)]
 pub(crate) struct SignSshCertificateCommand {
     /// Object ID of the asymmetric key to perform the signature with
     pub key_id: object::Id,
@@ -29,8 +35,9 @@ pub(crate) struct SignSshCertificateCommand {
     /// Timestamp
     pub timestamp: u32,
 
-    /// Signature over the request and timestamp
-    pub signature: [u8; 32],
+    /// Signature over the timestamp and request, made with the timestamp key
+    /// of the template
+    pub signature: Vec<u8>,
 
     /// Data to be signed
     pub request: Vec<u8>,
@@ -42,7 +49,7 @@ impl Command for SignSshCertificateCommand {
 
 /// Signed SSH certificates
 #[derive(Serialize, Deserialize, Debug)]
-pub struct SignSshCertificateResponse(ssh::Certificate);
+pub struct SignSshCertificateResponse(pub(crate) ssh::Certificate);
 
 impl Response for SignSshCertificateResponse {
     const COMMAND_CODE: command::Code = command::Code::SignSshCertificate;
@@ -53,3 +60,48 @@ impl From<SignSshCertificateResponse> for ssh::Certificate {
         response.0
     }
 }
+
+impl<'de> Deserialize<'de> for SignSshCertificateCommand {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct SignSshCertificateCommand {
+            /// Object ID of the asymmetric key to perform the signature with
+            key_id: object::Id,
+
+            /// Object ID of the SSH certificate template
+            template_id: object::Id,
+
+            /// Algorithm
+            algorithm: Algorithm,
+
+            /// Timestamp
+            timestamp: u32,
+
+            /// Signature followed by the data to be signed
+            data: Vec<u8>,
+        }
+
+        let mut value = SignSshCertificateCommand::deserialize(deserializer)?;
+
+        if value.data.len() < TIMESTAMP_SIGNATURE_SIZE {
+            return Err(de::Error::invalid_length(
+                value.data.len(),
+                &"timestamp signature followed by request",
+            ));
+        }
+
+        let request = value.data.split_off(TIMESTAMP_SIGNATURE_SIZE);
+
+        Ok(Self {
+            key_id: value.key_id,
+            template_id: value.template_id,
+            algorithm: value.algorithm,
+            timestamp: value.timestamp,
+            signature: value.data,
+            request,
+        })
+    }
+}
@@ -8,6 +8,9 @@ mod info;
 mod key;
 mod message;
 mod nonce;
+mod pem;
+pub mod rewrap;
+mod share;
 
 pub use self::{
     algorithm::Algorithm,
@@ -16,4 +19,8 @@ pub use self::{
     key::Key,
     message::{Message, Plaintext},
     nonce::Nonce,
+    share::Share,
 };
+
+#[cfg(feature = "mockhsm")]
+pub(crate) use self::message::{extend_padded, DELEGATED_CAPABILITIES_SIZE};
@@ -28,6 +28,38 @@ pub enum ErrorKind {
     /// Wrapping key algorithm mismatch
     #[error("Wrap key algorithm mismatch")]
     AlgorithmMismatch,
+
+    /// Wrap message failed to authenticate (wrong key or corrupted data)
+    #[error("wrap message authentication failed")]
+    AuthenticationFailed,
+
+    /// Unknown wrap or object algorithm
+    #[error("invalid algorithm")]
+    AlgorithmInvalid,
+
+    /// Malformed metadata of a wrapped object
+    #[error("invalid object info")]
+    ObjectInfoInvalid,
+
+    /// Wrapped object data doesn't match the size its metadata calls for
+    #[error("object size mismatch")]
+    SizeMismatch,
+
+    /// Malformed base64 or PEM encoding, or an unsupported key format
+    #[error("invalid encoding")]
+    EncodingInvalid,
+
+    /// I/O error reading or writing a wrapped object file
+    #[error("I/O error")]
+    Io,
+
+    /// Error rewrapping an object using the HSM
+    #[error("rewrap failed")]
+    RewrapFailed,
+
+    /// Malformed wrap key share, or shares which can't be combined
+    #[error("invalid key share")]
+    ShareInvalid,
 }
 
 impl ErrorKind {
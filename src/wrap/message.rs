@@ -3,13 +3,14 @@
 use super::nonce::{self, Nonce};
 use super::{Algorithm, Error, ErrorKind};
 use crate::{
-    algorithm, asymmetric,
+    algorithm, asymmetric, authentication,
     ecdsa::algorithm::CurveAlgorithm,
-    object,
+    hmac, object, opaque, otp,
     serialization::{deserialize, serialize},
-    wrap, Capability, Domain,
+    template, wrap, Capability, Domain,
 };
 use aes::cipher::typenum::Unsigned;
+use base64ct::{Base64, Encoding};
 use ccm::aead::Aead;
 use ecdsa::{
     elliptic_curve::{
@@ -24,6 +25,7 @@ use rsa::{
     BigUint, RsaPrivateKey,
 };
 use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
 
 /// Wrap wessage (encrypted HSM object or arbitrary data) encrypted under a wrap key
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -68,8 +70,64 @@ impl Message {
     pub fn into_vec(self) -> Vec<u8> {
         self.into()
     }
+
+    /// Load a `Message` from the base64 encoding of its nonce and ciphertext,
+    /// as written by `yubihsm-shell` and `yubihsm-wrap`. Whitespace (e.g.
+    /// line breaks) is ignored.
+    pub fn from_base64(encoded: &str) -> Result<Self, Error> {
+        let encoded: String = encoded
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect();
+
+        let bytes = Base64::decode_vec(&encoded)
+            .map_err(|e| format_err!(ErrorKind::EncodingInvalid, "malformed base64: {}", e))?;
+
+        Self::from_vec(bytes)
+    }
+
+    /// Encode this message as base64 wrapped at 64 columns, the format used
+    /// by `yubihsm-shell` and `yubihsm-wrap`
+    pub fn to_base64(&self) -> String {
+        let mut bytes = Vec::with_capacity(nonce::SIZE + self.ciphertext.len());
+        bytes.extend_from_slice(self.nonce.as_ref());
+        bytes.extend_from_slice(&self.ciphertext);
+
+        let encoded = Base64::encode_string(&bytes);
+        let mut wrapped =
+            String::with_capacity(encoded.len() + encoded.len() / BASE64_LINE_WIDTH + 1);
+
+        for line in encoded.as_bytes().chunks(BASE64_LINE_WIDTH) {
+            // Base64 is ASCII, so any chunk of it is valid UTF-8
+            wrapped.push_str(std::str::from_utf8(line).unwrap());
+            wrapped.push('\n');
+        }
+
+        wrapped
+    }
+
+    /// Read a `Message` from a file in the `yubihsm-shell` format
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let encoded = fs::read_to_string(path)
+            .map_err(|e| format_err!(ErrorKind::Io, "couldn't read {}: {}", path.display(), e))?;
+
+        Self::from_base64(&encoded)
+    }
+
+    /// Write this message to a file in the `yubihsm-shell` format
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        fs::write(path, self.to_base64())
+            .map_err(|e| format_err!(ErrorKind::Io, "couldn't write {}: {}", path.display(), e))?;
+
+        Ok(())
+    }
 }
 
+/// Line width of base64-encoded messages
+const BASE64_LINE_WIDTH: usize = 64;
+
 impl Into<Vec<u8>> for Message {
     fn into(self) -> Vec<u8> {
         let Message {
@@ -85,18 +143,65 @@ impl Into<Vec<u8>> for Message {
 }
 
 impl Message {
-    /// Decrypt the [`Message`] with the provided [`wrap::Key`]
+    /// Decrypt the [`Message`] with the provided [`wrap::Key`].
+    ///
+    /// Fails if the message doesn't authenticate under the given key (i.e.
+    /// the key is wrong or the message was corrupted), or if the decrypted
+    /// object is malformed or doesn't match its [`wrap::Info`].
     pub fn decrypt(&self, key: &wrap::Key) -> Result<Plaintext, Error> {
+        ensure!(
+            self.ciphertext.len() >= MAC_SIZE,
+            ErrorKind::LengthInvalid,
+            "ciphertext must be at least {}-bytes (got {})",
+            MAC_SIZE,
+            self.ciphertext.len()
+        );
+
         let cipher: super::key::AesCcm = key.into();
         let plaintext = cipher
             .decrypt(&self.nonce.to_nonce(), &*self.ciphertext)
-            .unwrap();
-
-        let plaintext = deserialize(&plaintext).unwrap();
+            .map_err(|_| {
+                format_err!(
+                    ErrorKind::AuthenticationFailed,
+                    "couldn't decrypt message with wrap key 0x{:04x} (wrong key or corrupted data)",
+                    key.import_params.id
+                )
+            })?;
+
+        let plaintext = Plaintext::from_bytes(&plaintext)?;
+
+        ensure!(
+            plaintext.algorithm.key_len() == key.key_len(),
+            ErrorKind::AlgorithmMismatch,
+            "message was wrapped using {:?} but key is {}-bytes",
+            plaintext.algorithm,
+            key.key_len()
+        );
+
+        plaintext.validate()?;
         Ok(plaintext)
     }
+
+    /// Verify the integrity of this [`Message`] under the provided
+    /// [`wrap::Key`] without importing it, returning the metadata of the
+    /// wrapped object.
+    pub fn verify(&self, key: &wrap::Key) -> Result<wrap::Info, Error> {
+        self.decrypt(key).map(|plaintext| plaintext.object_info)
+    }
 }
 
+/// Size of the AES-CCM MAC appended to the ciphertext
+const MAC_SIZE: usize = 16;
+
+/// Size of a serialized [`wrap::Info`]
+const INFO_SIZE: usize = 18 + object::LABEL_SIZE;
+
+/// Offset of the object type within a serialized [`wrap::Info`]
+const OBJECT_TYPE_OFFSET: usize = 14;
+
+/// Offset of the object algorithm within a serialized [`wrap::Info`]
+const OBJECT_ALGORITHM_OFFSET: usize = 15;
+
 /// Plaintext message to be encrypted under a wrap key
 #[derive(Serialize, Deserialize)]
 pub struct Plaintext {
@@ -120,14 +225,131 @@ impl Plaintext {
             );
         }
 
+        self.validate()?;
+
         let cipher: super::key::AesCcm = key.into();
         let nonce = Nonce::generate();
-        let wire = serialize(&self).unwrap();
-        let ciphertext = cipher.encrypt(&nonce.to_nonce(), wire.as_slice()).unwrap();
+        let wire = serialize(&self).map_err(|e| {
+            format_err!(
+                ErrorKind::ObjectInfoInvalid,
+                "error serializing object: {}",
+                e
+            )
+        })?;
+        let ciphertext = cipher
+            .encrypt(&nonce.to_nonce(), wire.as_slice())
+            .map_err(|_| format_err!(ErrorKind::LengthInvalid, "message too long to encrypt"))?;
 
         Ok(Message { nonce, ciphertext })
     }
 
+    /// Check that the data of this [`Plaintext`] is the size its
+    /// [`wrap::Info`] calls for, both for its algorithm and its length.
+    pub fn validate(&self) -> Result<(), Error> {
+        let info = &self.object_info;
+        let len = self.data.len();
+
+        let (min_len, max_len) = match (info.object_type, info.algorithm) {
+            (object::Type::AsymmetricKey, algorithm::Algorithm::Asymmetric(alg))
+                if alg.is_rsa() =>
+            {
+                // p, q, dp, dq and qinv followed by the modulus
+                let expected_len = alg.key_len() / 2 * 5 + alg.key_len();
+                (expected_len, expected_len)
+            }
+            (object::Type::AsymmetricKey, algorithm::Algorithm::Asymmetric(alg)) => {
+                (alg.key_len(), alg.key_len())
+            }
+            (object::Type::HmacKey, algorithm::Algorithm::Hmac(alg)) => (1, alg.max_key_len()),
+            (object::Type::Opaque, algorithm::Algorithm::Opaque(_))
+            | (object::Type::Template, algorithm::Algorithm::Template(_)) => (1, usize::MAX),
+            (object::Type::OtpAeadKey, algorithm::Algorithm::YubicoOtp(alg)) => {
+                (alg.key_len(), alg.key_len())
+            }
+            (object::Type::AuthenticationKey, algorithm::Algorithm::Authentication(_)) => {
+                let expected_len = DELEGATED_CAPABILITIES_SIZE + authentication::key::SIZE;
+                (expected_len, expected_len)
+            }
+            (object::Type::WrapKey, algorithm::Algorithm::Wrap(alg)) => {
+                let expected_len = DELEGATED_CAPABILITIES_SIZE + alg.key_len();
+                (expected_len, expected_len)
+            }
+            (object_type, alg) => fail!(
+                ErrorKind::AlgorithmInvalid,
+                "{:?} objects can't use {:?}",
+                object_type,
+                alg
+            ),
+        };
+
+        ensure!(
+            min_len <= len && len <= max_len,
+            ErrorKind::SizeMismatch,
+            "{:?} object 0x{:04x} ({:?}) has {} bytes of data (expected {})",
+            info.object_type,
+            info.object_id,
+            info.algorithm,
+            len,
+            if min_len == max_len {
+                min_len.to_string()
+            } else {
+                format!("at least {min_len}")
+            }
+        );
+
+        // The length in the object info doesn't count delegated capabilities
+        let key_len = match info.object_type {
+            object::Type::AuthenticationKey | object::Type::WrapKey => {
+                len - DELEGATED_CAPABILITIES_SIZE
+            }
+            _ => len,
+        };
+
+        ensure!(
+            usize::from(info.length) == key_len,
+            ErrorKind::SizeMismatch,
+            "{:?} object 0x{:04x} has {} bytes of key data but its info says {}",
+            info.object_type,
+            info.object_id,
+            key_len,
+            info.length
+        );
+
+        Ok(())
+    }
+
+    /// Parse a decrypted [`Plaintext`], reporting truncated data and unknown
+    /// algorithms or object types
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        ensure!(
+            bytes.len() >= 1 + INFO_SIZE,
+            ErrorKind::LengthInvalid,
+            "truncated wrapped object: expected at least {} bytes (got {})",
+            1 + INFO_SIZE,
+            bytes.len()
+        );
+
+        let algorithm = Algorithm::from_u8(bytes[0])
+            .map_err(|e| format_err!(ErrorKind::AlgorithmInvalid, "{}", e))?;
+
+        let (info, data) = bytes[1..].split_at(INFO_SIZE);
+
+        object::Type::from_u8(info[OBJECT_TYPE_OFFSET])
+            .map_err(|e| format_err!(ErrorKind::ObjectInfoInvalid, "{}", e))?;
+
+        algorithm::Algorithm::from_u8(info[OBJECT_ALGORITHM_OFFSET])
+            .map_err(|e| format_err!(ErrorKind::AlgorithmInvalid, "{}", e))?;
+
+        let object_info =
+            deserialize(info).map_err(|e| format_err!(ErrorKind::ObjectInfoInvalid, "{}", e))?;
+
+        Ok(Self {
+            algorithm,
+            object_info,
+            data: data.to_vec(),
+        })
+    }
+
     /// Return the ecdsa key of this [`Plaintext`] if it was an EC key.
     pub fn ecdsa<C>(&self) -> Option<SecretKey<C>>
     where
@@ -162,10 +384,12 @@ impl Plaintext {
     {
         let asym_algorithm = C::asymmetric_algorithm();
 
+        let data = key.to_bytes().as_slice().to_vec();
+
         let object_info = wrap::Info {
             capabilities,
             object_id,
-            length: 0,
+            length: data.len() as u16,
             domains,
             object_type: object::Type::AsymmetricKey,
             algorithm: algorithm::Algorithm::Asymmetric(asym_algorithm),
@@ -174,8 +398,6 @@ impl Plaintext {
             label,
         };
 
-        let data = key.to_bytes().as_slice().to_vec();
-
         Ok(Self {
             algorithm,
             object_info,
@@ -252,15 +474,23 @@ impl Plaintext {
         let p = &primes[0];
         let q = &primes[1];
 
+        // Components are zero-padded to a fixed width
+        let modulus_size = key.size();
+        let component_size = modulus_size / 2;
+
         let mut data = Vec::new();
-        data.extend_from_slice(&p.to_bytes_be());
-        data.extend_from_slice(&q.to_bytes_be());
+        extend_padded(&mut data, &p.to_bytes_be(), component_size);
+        extend_padded(&mut data, &q.to_bytes_be(), component_size);
         // Unwrap here is okay, we have ownership of the key and we already precomputed the values.
-        data.extend_from_slice(&key.dp().unwrap().to_bytes_be());
-        data.extend_from_slice(&key.dq().unwrap().to_bytes_be());
+        extend_padded(&mut data, &key.dp().unwrap().to_bytes_be(), component_size);
+        extend_padded(&mut data, &key.dq().unwrap().to_bytes_be(), component_size);
         // TODO: the second unwrap for int -> uint conversion is unfortunate.
-        data.extend_from_slice(&key.qinv().unwrap().to_biguint().unwrap().to_bytes_be());
-        data.extend_from_slice(&key.n().to_bytes_be());
+        extend_padded(
+            &mut data,
+            &key.qinv().unwrap().to_biguint().unwrap().to_bytes_be(),
+            component_size,
+        );
+        extend_padded(&mut data, &key.n().to_bytes_be(), modulus_size);
 
         object_info.length = data.len() as u16;
 
@@ -272,6 +502,374 @@ impl Plaintext {
     }
 }
 
+/// Size of an Ed25519 private key (i.e. seed)
+const ED25519_KEY_SIZE: usize = 32;
+
+/// Size of the delegated capabilities prefixing wrapped authentication and
+/// wrap keys
+pub(crate) const DELEGATED_CAPABILITIES_SIZE: usize = 8;
+
+impl Plaintext {
+    /// Return the Ed25519 private key (i.e. seed) of this [`Plaintext`] if it
+    /// was an Ed25519 key.
+    pub fn ed25519(&self) -> Option<[u8; ED25519_KEY_SIZE]> {
+        match self.object_info.algorithm {
+            algorithm::Algorithm::Asymmetric(asymmetric::Algorithm::Ed25519) => {
+                self.key_data(object::Type::AsymmetricKey)?.try_into().ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Build a [`Plaintext`] from an Ed25519 private key (i.e. seed).
+    pub fn from_ed25519(
+        algorithm: Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        key: &[u8; ED25519_KEY_SIZE],
+    ) -> Result<Self, Error> {
+        Ok(Self::from_parts(
+            algorithm,
+            object::Type::AsymmetricKey,
+            asymmetric::Algorithm::Ed25519.into(),
+            object_id,
+            capabilities,
+            domains,
+            label,
+            None,
+            key,
+        ))
+    }
+
+    /// Return the HMAC key of this [`Plaintext`] if it was an HMAC key.
+    pub fn hmac(&self) -> Option<Vec<u8>> {
+        let key = self.key_data(object::Type::HmacKey)?;
+
+        match self.object_info.algorithm {
+            algorithm::Algorithm::Hmac(alg) if valid_hmac_key_len(alg, key.len()) => {
+                Some(key.to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    /// Build a [`Plaintext`] from an HMAC key.
+    pub fn from_hmac(
+        algorithm: Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        hmac_algorithm: hmac::Algorithm,
+        key: &[u8],
+    ) -> Result<Self, Error> {
+        ensure!(
+            valid_hmac_key_len(hmac_algorithm, key.len()),
+            ErrorKind::LengthInvalid,
+            "invalid {:?} key length: {} (max {})",
+            hmac_algorithm,
+            key.len(),
+            hmac_algorithm.max_key_len()
+        );
+
+        Ok(Self::from_parts(
+            algorithm,
+            object::Type::HmacKey,
+            hmac_algorithm.into(),
+            object_id,
+            capabilities,
+            domains,
+            label,
+            None,
+            key,
+        ))
+    }
+
+    /// Return the data of this [`Plaintext`] if it was an opaque object.
+    pub fn opaque(&self) -> Option<Vec<u8>> {
+        match self.object_info.algorithm {
+            algorithm::Algorithm::Opaque(_) => Some(self.key_data(object::Type::Opaque)?.to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Build a [`Plaintext`] from opaque data.
+    pub fn from_opaque(
+        algorithm: Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        opaque_algorithm: opaque::Algorithm,
+        data: &[u8],
+    ) -> Result<Self, Error> {
+        ensure!(
+            !data.is_empty(),
+            ErrorKind::LengthInvalid,
+            "opaque data is empty"
+        );
+
+        Ok(Self::from_parts(
+            algorithm,
+            object::Type::Opaque,
+            opaque_algorithm.into(),
+            object_id,
+            capabilities,
+            domains,
+            label,
+            None,
+            data,
+        ))
+    }
+
+    /// Return the template of this [`Plaintext`] if it was a template object.
+    pub fn template(&self) -> Option<Vec<u8>> {
+        match self.object_info.algorithm {
+            algorithm::Algorithm::Template(_) => {
+                Some(self.key_data(object::Type::Template)?.to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    /// Build a [`Plaintext`] from a template (e.g. for SSH certificates).
+    pub fn from_template(
+        algorithm: Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        template_algorithm: template::Algorithm,
+        data: &[u8],
+    ) -> Result<Self, Error> {
+        ensure!(
+            !data.is_empty(),
+            ErrorKind::LengthInvalid,
+            "template is empty"
+        );
+
+        Ok(Self::from_parts(
+            algorithm,
+            object::Type::Template,
+            template_algorithm.into(),
+            object_id,
+            capabilities,
+            domains,
+            label,
+            None,
+            data,
+        ))
+    }
+
+    /// Return the Yubico OTP AEAD key of this [`Plaintext`] if it was an OTP
+    /// AEAD key.
+    pub fn otp_aead_key(&self) -> Option<Vec<u8>> {
+        let key = self.key_data(object::Type::OtpAeadKey)?;
+
+        match self.object_info.algorithm {
+            algorithm::Algorithm::YubicoOtp(alg) if alg.key_len() == key.len() => {
+                Some(key.to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    /// Build a [`Plaintext`] from a Yubico OTP AEAD key.
+    pub fn from_otp_aead_key(
+        algorithm: Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        otp_algorithm: otp::Algorithm,
+        key: &[u8],
+    ) -> Result<Self, Error> {
+        ensure!(
+            key.len() == otp_algorithm.key_len(),
+            ErrorKind::LengthInvalid,
+            "invalid {:?} key length: {} (expected {})",
+            otp_algorithm,
+            key.len(),
+            otp_algorithm.key_len()
+        );
+
+        Ok(Self::from_parts(
+            algorithm,
+            object::Type::OtpAeadKey,
+            otp_algorithm.into(),
+            object_id,
+            capabilities,
+            domains,
+            label,
+            None,
+            key,
+        ))
+    }
+
+    /// Return the authentication key of this [`Plaintext`] if it was an
+    /// authentication key.
+    pub fn authentication_key(&self) -> Option<authentication::Key> {
+        match self.object_info.algorithm {
+            algorithm::Algorithm::Authentication(_) => {
+                authentication::Key::from_slice(self.key_data(object::Type::AuthenticationKey)?)
+                    .ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Build a [`Plaintext`] from an authentication key.
+    pub fn from_authentication_key(
+        algorithm: Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        key: &authentication::Key,
+    ) -> Result<Self, Error> {
+        Ok(Self::from_parts(
+            algorithm,
+            object::Type::AuthenticationKey,
+            authentication::Algorithm::YubicoAes.into(),
+            object_id,
+            capabilities,
+            domains,
+            label,
+            Some(delegated_capabilities),
+            key.as_secret_slice(),
+        ))
+    }
+
+    /// Return the wrap key of this [`Plaintext`] if it was a wrap key.
+    pub fn wrap_key(&self) -> Option<wrap::Key> {
+        let key_alg = match self.object_info.algorithm {
+            algorithm::Algorithm::Wrap(alg) => alg,
+            _ => return None,
+        };
+
+        let key = self.key_data(object::Type::WrapKey)?;
+
+        if key.len() != key_alg.key_len() {
+            return None;
+        }
+
+        Some(
+            wrap::Key::from_bytes(self.object_info.object_id, key)
+                .ok()?
+                .label(self.object_info.label.clone())
+                .domains(self.object_info.domains)
+                .capabilities(self.object_info.capabilities)
+                .delegated_capabilities(self.delegated_capabilities()?),
+        )
+    }
+
+    /// Build a [`Plaintext`] from a wrap key, using the object ID, label,
+    /// domains and capabilities of the key.
+    pub fn from_wrap_key(algorithm: Algorithm, key: &wrap::Key) -> Result<Self, Error> {
+        let key_algorithm = key.import_params.algorithm;
+
+        ensure!(
+            key_algorithm.wrap().is_some(),
+            ErrorKind::AlgorithmMismatch,
+            "not a wrap key algorithm: {:?}",
+            key_algorithm
+        );
+
+        Ok(Self::from_parts(
+            algorithm,
+            object::Type::WrapKey,
+            key_algorithm,
+            key.import_params.id,
+            key.import_params.capabilities,
+            key.import_params.domains,
+            key.import_params.label.clone(),
+            Some(key.delegated_capabilities),
+            &key.data,
+        ))
+    }
+
+    /// Return the delegated capabilities of this [`Plaintext`] if it was an
+    /// authentication or wrap key.
+    pub fn delegated_capabilities(&self) -> Option<Capability> {
+        match self.object_info.object_type {
+            object::Type::AuthenticationKey | object::Type::WrapKey => self
+                .data
+                .get(..DELEGATED_CAPABILITIES_SIZE)
+                .and_then(|bytes| deserialize(bytes).ok()),
+            _ => None,
+        }
+    }
+
+    /// Build a [`Plaintext`] for the given object, prefixing the key material
+    /// with its delegated capabilities (if any)
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        algorithm: Algorithm,
+        object_type: object::Type,
+        object_algorithm: algorithm::Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        delegated_capabilities: Option<Capability>,
+        key: &[u8],
+    ) -> Self {
+        let object_info = wrap::Info {
+            capabilities,
+            object_id,
+            length: key.len() as u16,
+            domains,
+            object_type,
+            algorithm: object_algorithm,
+            sequence: 0,
+            origin: object::Origin::Imported,
+            label,
+        };
+
+        let mut data = match delegated_capabilities {
+            Some(capabilities) => serialize(&capabilities).unwrap(),
+            None => vec![],
+        };
+
+        data.extend_from_slice(key);
+
+        Self {
+            algorithm,
+            object_info,
+            data,
+        }
+    }
+
+    /// Get the key material of this [`Plaintext`] if it's an object of the
+    /// given type, stripping the delegated capabilities (if any)
+    fn key_data(&self, object_type: object::Type) -> Option<&[u8]> {
+        if self.object_info.object_type != object_type {
+            return None;
+        }
+
+        match object_type {
+            object::Type::AuthenticationKey | object::Type::WrapKey => {
+                self.data.get(DELEGATED_CAPABILITIES_SIZE..)
+            }
+            _ => Some(&self.data),
+        }
+    }
+}
+
+/// Append a big-endian integer to `data`, left-padded with zeroes to `size`
+pub(crate) fn extend_padded(data: &mut Vec<u8>, bytes: &[u8], size: usize) {
+    data.resize(data.len() + size.saturating_sub(bytes.len()), 0);
+    data.extend_from_slice(bytes);
+}
+
+/// Is the given key length acceptable for the given HMAC algorithm?
+fn valid_hmac_key_len(algorithm: hmac::Algorithm, len: usize) -> bool {
+    len > 0 && len <= algorithm.max_key_len()
+}
+
 /// Support structure to read from a slice like a reader
 struct SliceReader<'a>(&'a [u8]);
 
@@ -13,8 +13,13 @@ pub struct Nonce(pub [u8; SIZE]);
 impl Nonce {
     /// Generate a random `wrap::Nonce`
     pub fn generate() -> Self {
+        Self::from_rng(&mut OsRng)
+    }
+
+    /// Generate a `wrap::Nonce` using the given RNG
+    pub(crate) fn from_rng(rng: &mut impl RngCore) -> Self {
         let mut bytes = [0u8; SIZE];
-        OsRng.fill_bytes(&mut bytes);
+        rng.fill_bytes(&mut bytes);
         Nonce(bytes)
     }
 
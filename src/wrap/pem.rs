@@ -0,0 +1,197 @@
+//! PEM-encoded private keys, for wrapping existing keys offline the same way
+//! as `yubihsm-wrap`
+
+use super::{Algorithm, Error, ErrorKind, Plaintext};
+use crate::{object, Capability, Domain};
+use pkcs8::{der::pem, AssociatedOid, DecodePrivateKey, ObjectIdentifier, PrivateKeyInfoRef};
+use rsa::{pkcs1::DecodeRsaPrivateKey, RsaPrivateKey};
+use std::fmt::Display;
+use zeroize::Zeroizing;
+
+/// OID of RSA keys (`rsaEncryption`)
+const RSA_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+
+/// OID of elliptic curve keys (`id-ecPublicKey`)
+const EC_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+
+/// OID of Ed25519 keys
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+/// DER header of the octet string containing an Ed25519 seed
+const ED25519_SEED_HEADER: [u8; 2] = [0x04, 0x20];
+
+impl Plaintext {
+    /// Build a [`Plaintext`] from a PEM-encoded private key, as accepted by
+    /// `yubihsm-wrap`.
+    ///
+    /// Supports RSA, ECDSA and Ed25519 keys in PKCS#8 (`PRIVATE KEY`) format,
+    /// as well as RSA keys in PKCS#1 (`RSA PRIVATE KEY`) format and ECDSA keys
+    /// in SEC1 (`EC PRIVATE KEY`) format. Encrypted keys are not supported.
+    pub fn from_pem(
+        algorithm: Algorithm,
+        object_id: object::Id,
+        capabilities: Capability,
+        domains: Domain,
+        label: object::Label,
+        pem: &str,
+    ) -> Result<Self, Error> {
+        match PrivateKey::from_pem(pem)? {
+            PrivateKey::Rsa(key) => {
+                Self::from_rsa(algorithm, object_id, capabilities, domains, label, key)
+            }
+            PrivateKey::NistP256(key) => {
+                Self::from_ecdsa(algorithm, object_id, capabilities, domains, label, key)
+            }
+            PrivateKey::NistP384(key) => {
+                Self::from_ecdsa(algorithm, object_id, capabilities, domains, label, key)
+            }
+            PrivateKey::NistP521(key) => {
+                Self::from_ecdsa(algorithm, object_id, capabilities, domains, label, key)
+            }
+            #[cfg(feature = "secp256k1")]
+            PrivateKey::Secp256k1(key) => {
+                Self::from_ecdsa(algorithm, object_id, capabilities, domains, label, key)
+            }
+            PrivateKey::Ed25519(seed) => {
+                Self::from_ed25519(algorithm, object_id, capabilities, domains, label, &seed)
+            }
+        }
+    }
+}
+
+/// Private keys which can be parsed from PEM
+enum PrivateKey {
+    /// RSA private key
+    Rsa(RsaPrivateKey),
+
+    /// NIST P-256 private key
+    NistP256(p256::SecretKey),
+
+    /// NIST P-384 private key
+    NistP384(p384::SecretKey),
+
+    /// NIST P-521 private key
+    NistP521(p521::SecretKey),
+
+    /// secp256k1 private key
+    #[cfg(feature = "secp256k1")]
+    Secp256k1(k256::SecretKey),
+
+    /// Ed25519 private key (i.e. seed)
+    Ed25519(Zeroizing<[u8; 32]>),
+}
+
+impl PrivateKey {
+    /// Parse a PEM-encoded private key
+    fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) = pem::decode_vec(pem.as_bytes())
+            .map_err(|e| format_err!(ErrorKind::EncodingInvalid, "malformed PEM: {}", e))?;
+
+        let der = Zeroizing::new(der);
+
+        match label {
+            "PRIVATE KEY" => Self::from_pkcs8_der(&der),
+            "RSA PRIVATE KEY" => RsaPrivateKey::from_pkcs1_der(&der)
+                .map(PrivateKey::Rsa)
+                .map_err(invalid_key),
+            "EC PRIVATE KEY" => Self::from_sec1_der(&der),
+            other => fail!(
+                ErrorKind::EncodingInvalid,
+                "unsupported PEM type: {}",
+                other
+            ),
+        }
+    }
+
+    /// Parse a PKCS#8 private key
+    fn from_pkcs8_der(der: &[u8]) -> Result<Self, Error> {
+        let info = PrivateKeyInfoRef::try_from(der).map_err(invalid_key)?;
+        let oid = info.algorithm.oid;
+
+        if oid == RSA_OID {
+            return RsaPrivateKey::from_pkcs8_der(der)
+                .map(PrivateKey::Rsa)
+                .map_err(invalid_key);
+        }
+
+        if oid == ED25519_OID {
+            let seed = info
+                .private_key
+                .as_bytes()
+                .strip_prefix(&ED25519_SEED_HEADER)
+                .and_then(|seed| <[u8; 32]>::try_from(seed).ok())
+                .ok_or_else(|| {
+                    format_err!(ErrorKind::EncodingInvalid, "malformed Ed25519 private key")
+                })?;
+
+            return Ok(PrivateKey::Ed25519(Zeroizing::new(seed)));
+        }
+
+        ensure!(
+            oid == EC_OID,
+            ErrorKind::EncodingInvalid,
+            "unsupported key algorithm: {}",
+            oid
+        );
+
+        let curve = info.algorithm.parameters_oid().map_err(invalid_key)?;
+
+        if curve == p256::NistP256::OID {
+            p256::SecretKey::from_pkcs8_der(der)
+                .map(PrivateKey::NistP256)
+                .map_err(invalid_key)
+        } else if curve == p384::NistP384::OID {
+            p384::SecretKey::from_pkcs8_der(der)
+                .map(PrivateKey::NistP384)
+                .map_err(invalid_key)
+        } else if curve == p521::NistP521::OID {
+            p521::SecretKey::from_pkcs8_der(der)
+                .map(PrivateKey::NistP521)
+                .map_err(invalid_key)
+        } else {
+            #[cfg(feature = "secp256k1")]
+            if curve == k256::Secp256k1::OID {
+                return k256::SecretKey::from_pkcs8_der(der)
+                    .map(PrivateKey::Secp256k1)
+                    .map_err(invalid_key);
+            }
+
+            fail!(
+                ErrorKind::EncodingInvalid,
+                "unsupported elliptic curve: {}",
+                curve
+            )
+        }
+    }
+
+    /// Parse a SEC1 elliptic curve private key
+    fn from_sec1_der(der: &[u8]) -> Result<Self, Error> {
+        // SEC1 keys name their curve, which is checked when parsing
+        if let Ok(key) = p256::SecretKey::from_sec1_der(der) {
+            return Ok(PrivateKey::NistP256(key));
+        }
+
+        if let Ok(key) = p384::SecretKey::from_sec1_der(der) {
+            return Ok(PrivateKey::NistP384(key));
+        }
+
+        if let Ok(key) = p521::SecretKey::from_sec1_der(der) {
+            return Ok(PrivateKey::NistP521(key));
+        }
+
+        #[cfg(feature = "secp256k1")]
+        if let Ok(key) = k256::SecretKey::from_sec1_der(der) {
+            return Ok(PrivateKey::Secp256k1(key));
+        }
+
+        fail!(
+            ErrorKind::EncodingInvalid,
+            "malformed EC private key or unsupported curve"
+        )
+    }
+}
+
+/// Create an error for a private key which couldn't be parsed
+fn invalid_key(err: impl Display) -> Error {
+    format_err!(ErrorKind::EncodingInvalid, "invalid private key: {}", err).into()
+}
@@ -0,0 +1,146 @@
+//! Rewrapping: migrating wrapped objects from one wrap key to another, either
+//! offline (given both keys) or with the help of an HSM holding both keys.
+//!
+//! In both cases the [`wrap::Info`] metadata of each object is preserved.
+
+use super::{Error, ErrorKind, Message, Plaintext};
+use crate::{object, wrap, Client};
+
+/// Progress of a rewrap operation, reported after each message is rewrapped
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// Number of messages rewrapped so far
+    pub completed: usize,
+
+    /// Total number of messages to rewrap
+    pub total: usize,
+
+    /// Handle of the object which was just rewrapped
+    pub handle: object::Handle,
+}
+
+impl Message {
+    /// Rewrap this message from `old_key` to `new_key` offline, preserving
+    /// the metadata of the wrapped object
+    pub fn rewrap(&self, old_key: &wrap::Key, new_key: &wrap::Key) -> Result<Message, Error> {
+        encrypt_under(self.decrypt(old_key)?, new_key)
+    }
+}
+
+/// Rewrap messages from `old_key` to `new_key` offline, calling `progress`
+/// after each one.
+pub fn offline<'a, I, F>(
+    messages: I,
+    old_key: &wrap::Key,
+    new_key: &wrap::Key,
+    mut progress: F,
+) -> Result<Vec<Message>, Error>
+where
+    I: IntoIterator<Item = &'a Message>,
+    I::IntoIter: ExactSizeIterator,
+    F: FnMut(&Progress),
+{
+    let messages = messages.into_iter();
+    let total = messages.len();
+    let mut rewrapped = Vec::with_capacity(total);
+
+    for (index, message) in messages.enumerate() {
+        let plaintext = message
+            .decrypt(old_key)
+            .map_err(|e| format_err!(*e.kind(), "error decrypting message {}: {}", index, e))?;
+
+        let handle = object::Handle::new(
+            plaintext.object_info.object_id,
+            plaintext.object_info.object_type,
+        );
+
+        rewrapped.push(encrypt_under(plaintext, new_key)?);
+
+        progress(&Progress {
+            completed: rewrapped.len(),
+            total,
+            handle,
+        });
+    }
+
+    Ok(rewrapped)
+}
+
+/// Rewrap messages from one wrap key to another using an HSM which holds
+/// both keys, calling `progress` after each one.
+///
+/// Each object is imported under `old_key_id`, exported under `new_key_id`,
+/// and then deleted from the HSM again (including when exporting it fails).
+/// Objects must be exportable under wrap and must not already exist in the
+/// HSM.
+pub fn with_hsm<'a, I, F>(
+    client: &Client,
+    messages: I,
+    old_key_id: object::Id,
+    new_key_id: object::Id,
+    mut progress: F,
+) -> Result<Vec<Message>, Error>
+where
+    I: IntoIterator<Item = &'a Message>,
+    I::IntoIter: ExactSizeIterator,
+    F: FnMut(&Progress),
+{
+    let messages = messages.into_iter();
+    let total = messages.len();
+    let mut rewrapped = Vec::with_capacity(total);
+
+    for (index, message) in messages.enumerate() {
+        let handle = client
+            .import_wrapped(old_key_id, message.clone())
+            .map_err(|e| {
+                format_err!(
+                    ErrorKind::RewrapFailed,
+                    "error importing message {} under key 0x{:04x}: {}",
+                    index,
+                    old_key_id,
+                    e
+                )
+            })?;
+
+        let exported = client.export_wrapped(new_key_id, handle.object_type, handle.object_id);
+
+        if let Err(e) = client.delete_object(handle.object_id, handle.object_type) {
+            warn!(
+                "error deleting rewrapped {:?} 0x{:04x}: {}",
+                handle.object_type, handle.object_id, e
+            );
+        }
+
+        rewrapped.push(exported.map_err(|e| {
+            format_err!(
+                ErrorKind::RewrapFailed,
+                "error exporting {:?} 0x{:04x} under key 0x{:04x}: {}",
+                handle.object_type,
+                handle.object_id,
+                new_key_id,
+                e
+            )
+        })?);
+
+        progress(&Progress {
+            completed: rewrapped.len(),
+            total,
+            handle,
+        });
+    }
+
+    Ok(rewrapped)
+}
+
+/// Encrypt a decrypted message under a new wrap key
+fn encrypt_under(mut plaintext: Plaintext, key: &wrap::Key) -> Result<Message, Error> {
+    plaintext.algorithm = key.import_params.algorithm.wrap().ok_or_else(|| {
+        format_err!(
+            ErrorKind::AlgorithmMismatch,
+            "not a wrap key algorithm: {:?}",
+            key.import_params.algorithm
+        )
+    })?;
+
+    plaintext.encrypt(key)
+}
@@ -0,0 +1,395 @@
+//! M-of-N wrap key shares (Shamir's Secret Sharing over GF(2^8)), for key
+//! ceremonies where no single custodian holds a whole wrap key.
+//!
+//! Each share encodes the wrap key's object ID, domains, capabilities,
+//! delegated capabilities and label along with the key itself, so combining
+//! shares yields a `wrap::Key` which is ready to be put into an HSM.
+//!
+//! Shares beyond the threshold are checked against the key recovered from
+//! the others, so a share which doesn't belong is caught instead of ignored.
+//!
+//! Shares are printed as `<threshold>-<index>-<hex>`, where the hex-encoded
+//! data ends with a checksum which catches transcription errors.
+
+use super::{Error, ErrorKind, Key};
+use crate::{
+    object::{Label, LABEL_SIZE},
+    Capability, Domain,
+};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{self, Debug, Display},
+    str::FromStr,
+};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Size of the checksum at the end of each encoded share
+const CHECKSUM_SIZE: usize = 4;
+
+/// Size of the object parameters at the start of a shared secret: object ID
+/// (2 bytes), domains (2 bytes), capabilities (8 bytes) and delegated
+/// capabilities (8 bytes), followed by the length of the label (1 byte) and
+/// the label itself
+const PARAMS_SIZE: usize = 21;
+
+/// Size of the digest at the end of a shared secret, which detects shares of
+/// different keys being combined
+const SECRET_DIGEST_SIZE: usize = 4;
+
+/// Share of a wrap key
+#[derive(Clone)]
+pub struct Share {
+    /// Number of shares needed to recover the key
+    threshold: u8,
+
+    /// Index of this share (the x-coordinate of its point)
+    index: u8,
+
+    /// Share data (the y-coordinates of its points)
+    data: Vec<u8>,
+}
+
+impl Share {
+    /// Number of shares needed to recover the key
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Index of this share (starting at 1)
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Compute the checksum of this share
+    fn checksum(&self) -> [u8; CHECKSUM_SIZE] {
+        let digest = Sha256::new()
+            .chain_update([self.threshold, self.index])
+            .chain_update(&self.data)
+            .finalize();
+
+        digest[..CHECKSUM_SIZE].try_into().unwrap()
+    }
+}
+
+impl Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-", self.threshold, self.index)?;
+
+        for byte in self.data.iter().chain(self.checksum().iter()) {
+            write!(f, "{byte:02X}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Avoid leaking secrets in debug messages
+        write!(
+            f,
+            "yubihsm::wrap::Share {{ threshold: {}, index: {}, data: ... }}",
+            self.threshold, self.index
+        )
+    }
+}
+
+impl FromStr for Share {
+    type Err = Error;
+
+    /// Parse a share, ignoring whitespace
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s: Zeroizing<String> =
+            Zeroizing::new(s.chars().filter(|c| !c.is_ascii_whitespace()).collect());
+
+        let mut parts = s.splitn(3, '-');
+
+        let (threshold, index, hex) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(threshold), Some(index), Some(hex)) => (threshold, index, hex),
+            _ => fail!(
+                ErrorKind::ShareInvalid,
+                "expected <threshold>-<index>-<data>"
+            ),
+        };
+
+        let threshold = threshold.parse().map_err(|_| {
+            format_err!(ErrorKind::ShareInvalid, "invalid threshold: {}", threshold)
+        })?;
+
+        let index = index
+            .parse()
+            .map_err(|_| format_err!(ErrorKind::ShareInvalid, "invalid index: {}", index))?;
+
+        ensure!(
+            hex.len() % 2 == 0 && hex.is_ascii(),
+            ErrorKind::ShareInvalid,
+            "malformed share data"
+        );
+
+        let mut data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format_err!(ErrorKind::ShareInvalid, "malformed share data"))?;
+
+        ensure!(
+            data.len() > CHECKSUM_SIZE,
+            ErrorKind::ShareInvalid,
+            "share data too short"
+        );
+
+        let checksum = data.split_off(data.len() - CHECKSUM_SIZE);
+
+        let share = Share {
+            threshold,
+            index,
+            data,
+        };
+
+        ensure!(
+            share.checksum()[..] == checksum[..],
+            ErrorKind::ShareInvalid,
+            "checksum mismatch for share {} (typo?)",
+            index
+        );
+
+        ensure!(
+            share.index != 0 && share.threshold != 0,
+            ErrorKind::ShareInvalid,
+            "threshold and index must be non-zero"
+        );
+
+        Ok(share)
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl Key {
+    /// Split this key into `count` shares, any `threshold` of which can be
+    /// combined to recover it.
+    pub fn split(&self, threshold: u8, count: u8) -> Result<Vec<Share>, Error> {
+        ensure!(
+            threshold >= 1 && threshold <= count,
+            ErrorKind::ShareInvalid,
+            "invalid threshold: {} of {} shares",
+            threshold,
+            count
+        );
+
+        // Random coefficients of the polynomial for each byte of the secret
+        let secret = self.share_secret();
+        let terms = threshold as usize - 1;
+        let mut coefficients = Zeroizing::new(vec![0u8; secret.len() * terms]);
+        OsRng.fill_bytes(&mut coefficients);
+
+        let mut shares = Vec::with_capacity(count as usize);
+
+        for index in 1..=count {
+            let mut data = Vec::with_capacity(secret.len());
+
+            for (i, &byte) in secret.iter().enumerate() {
+                // Evaluate the polynomial for this byte of the secret at
+                // `index` using Horner's method
+                let mut y = 0;
+
+                for &coefficient in coefficients[i * terms..(i + 1) * terms].iter().rev() {
+                    y = gf_mul(y, index) ^ coefficient;
+                }
+
+                data.push(gf_mul(y, index) ^ byte);
+            }
+
+            shares.push(Share {
+                threshold,
+                index,
+                data,
+            });
+        }
+
+        Ok(shares)
+    }
+
+    /// Recover a key from at least as many shares as its threshold. Any
+    /// shares beyond the threshold must be consistent with the others.
+    pub fn combine(shares: &[Share]) -> Result<Self, Error> {
+        let threshold = match shares.first() {
+            Some(share) => share.threshold,
+            None => fail!(ErrorKind::ShareInvalid, "no shares given"),
+        };
+
+        ensure!(
+            shares.len() >= threshold as usize,
+            ErrorKind::ShareInvalid,
+            "need {} shares to recover key (got {})",
+            threshold,
+            shares.len()
+        );
+
+        for (i, share) in shares.iter().enumerate() {
+            ensure!(
+                share.threshold == threshold && share.data.len() == shares[0].data.len(),
+                ErrorKind::ShareInvalid,
+                "share {} doesn't belong with share {}",
+                share.index,
+                shares[0].index
+            );
+
+            ensure!(
+                shares[..i].iter().all(|other| other.index != share.index),
+                ErrorKind::ShareInvalid,
+                "duplicate share: {}",
+                share.index
+            );
+        }
+
+        let (shares, extra_shares) = shares.split_at(threshold as usize);
+
+        for extra_share in extra_shares {
+            ensure!(
+                interpolate(shares, extra_share.index)[..] == extra_share.data[..],
+                ErrorKind::ShareInvalid,
+                "share {} doesn't belong with the others",
+                extra_share.index
+            );
+        }
+
+        Self::from_share_secret(&interpolate(shares, 0))
+    }
+
+    /// Serialize the parameters and data of this key into the secret which
+    /// is shared
+    fn share_secret(&self) -> Zeroizing<Vec<u8>> {
+        let mut secret = Zeroizing::new(Vec::with_capacity(
+            PARAMS_SIZE + LABEL_SIZE + self.data.len() + SECRET_DIGEST_SIZE,
+        ));
+
+        secret.extend_from_slice(&self.import_params.id.to_be_bytes());
+        secret.extend_from_slice(&self.import_params.domains.bits().to_be_bytes());
+        secret.extend_from_slice(&self.import_params.capabilities.bits().to_be_bytes());
+        secret.extend_from_slice(&self.delegated_capabilities.bits().to_be_bytes());
+
+        // Labels are NUL padded, which doesn't need sharing
+        let label = self.import_params.label.as_ref();
+        let label_len = label.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+        secret.push(label_len as u8);
+        secret.extend_from_slice(&label[..label_len]);
+        secret.extend_from_slice(&self.data);
+
+        let digest = Sha256::digest(&secret[..]);
+        secret.extend_from_slice(&digest[..SECRET_DIGEST_SIZE]);
+        secret
+    }
+
+    /// Parse a key from a recovered secret
+    fn from_share_secret(secret: &[u8]) -> Result<Self, Error> {
+        ensure!(
+            secret.len() > PARAMS_SIZE + SECRET_DIGEST_SIZE,
+            ErrorKind::ShareInvalid,
+            "recovered secret too short"
+        );
+
+        let (secret, digest) = secret.split_at(secret.len() - SECRET_DIGEST_SIZE);
+
+        ensure!(
+            Sha256::digest(secret)[..SECRET_DIGEST_SIZE] == *digest,
+            ErrorKind::ShareInvalid,
+            "shares don't belong to the same key"
+        );
+
+        let (params, rest) = secret.split_at(PARAMS_SIZE);
+        let label_len = params[20] as usize;
+
+        ensure!(
+            label_len <= LABEL_SIZE && rest.len() > label_len,
+            ErrorKind::ShareInvalid,
+            "invalid label length: {}",
+            label_len
+        );
+
+        let (label, key) = rest.split_at(label_len);
+        let key_id = u16::from_be_bytes(params[..2].try_into().unwrap());
+
+        let domains = Domain::from_bits(u16::from_be_bytes(params[2..4].try_into().unwrap()))
+            .ok_or_else(|| format_err!(ErrorKind::ShareInvalid, "invalid domains"))?;
+
+        let capabilities =
+            Capability::from_bits(u64::from_be_bytes(params[4..12].try_into().unwrap()))
+                .ok_or_else(|| format_err!(ErrorKind::ShareInvalid, "invalid capabilities"))?;
+
+        let delegated_capabilities =
+            Capability::from_bits(u64::from_be_bytes(params[12..20].try_into().unwrap()))
+                .ok_or_else(|| {
+                    format_err!(ErrorKind::ShareInvalid, "invalid delegated capabilities")
+                })?;
+
+        let key = Key::from_bytes(key_id, key)
+            .map_err(|e| format_err!(ErrorKind::ShareInvalid, "{}", e))?;
+
+        Ok(key
+            .label(Label::from_bytes(label).unwrap())
+            .domains(domains)
+            .capabilities(capabilities)
+            .delegated_capabilities(delegated_capabilities))
+    }
+}
+
+/// Evaluate the polynomials through the given shares at `x` (Lagrange
+/// interpolation), yielding the secret at `x = 0`
+fn interpolate(shares: &[Share], x: u8) -> Zeroizing<Vec<u8>> {
+    let mut result = Zeroizing::new(vec![0u8; shares[0].data.len()]);
+
+    for share in shares {
+        let basis = shares
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |acc, other| {
+                gf_mul(
+                    acc,
+                    gf_mul(x ^ other.index, gf_inv(other.index ^ share.index)),
+                )
+            });
+
+        for (byte, &y) in result.iter_mut().zip(&share.data) {
+            *byte ^= gf_mul(basis, y);
+        }
+    }
+
+    result
+}
+
+/// Multiply two elements of GF(2^8) (modulo the AES polynomial)
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(a >> 7));
+        b >>= 1;
+    }
+
+    product
+}
+
+/// Invert a non-zero element of GF(2^8) (i.e. raise it to the power of 254)
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254u8;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+
+    result
+}
@@ -0,0 +1,221 @@
+//! Audit log tests: collect and verify the `MockHsm`'s hash-chained log
+
+#![cfg(feature = "mockhsm")]
+
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use yubihsm::{audit, command, device, mockhsm::MockHsm};
+
+/// Path to a fresh log file for the given test
+fn log_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("yubihsm-rs-{name}.log"));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// Number of entries in a log file
+fn line_count(path: &Path) -> usize {
+    fs::read_to_string(path).unwrap().lines().count()
+}
+
+/// Entries are stored, acknowledged and resumed from the log file
+#[test]
+fn collector_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+    let path = log_path("collector-test");
+
+    let mut collector = audit::Collector::open(client.clone(), &path).unwrap();
+    let collected = collector.collect().unwrap();
+    assert!(!collected.entries.is_empty());
+    assert!(!collected.has_gaps());
+
+    let last = collected.entries.last().unwrap().clone();
+    assert_eq!(collector.last_entry(), Some(&last));
+    assert!(hsm.log_entries().iter().all(|entry| entry.item > last.item));
+
+    client.get_storage_info().unwrap();
+    let collected = collector.collect().unwrap();
+    assert_eq!(collected.entries[0].item, last.item + 1);
+    assert!(collected
+        .entries
+        .iter()
+        .any(|entry| entry.cmd == command::Code::GetStorageInfo));
+
+    // Collection resumes from the last entry in the file
+    let last = collector.last_entry().unwrap().clone();
+    drop(collector);
+
+    let mut collector = audit::Collector::open(client.clone(), &path).unwrap();
+    assert_eq!(collector.last_entry(), Some(&last));
+
+    client.get_storage_info().unwrap();
+    collector.collect().unwrap();
+    let stored = line_count(&path);
+
+    // A partially written entry is discarded
+    drop(collector);
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"0001ffff")
+        .unwrap();
+
+    let collector = audit::Collector::open(client.clone(), &path).unwrap();
+    assert_eq!(line_count(&path), stored);
+    drop(collector);
+
+    // Tampering with a stored entry breaks the chain
+    let contents = fs::read_to_string(&path).unwrap();
+    let mut lines: Vec<String> = contents.lines().map(ToOwned::to_owned).collect();
+    let tampered = lines[2].replacen("00", "01", 1);
+    assert_ne!(tampered, lines[2]);
+    lines[2] = tampered;
+    fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+    let err = audit::Collector::open(client, &path).err().unwrap();
+    assert_eq!(*err.kind(), audit::ErrorKind::ChainInvalid);
+
+    fs::remove_file(path).unwrap();
+}
+
+/// Entries overwritten before they're collected are detected, and nothing
+/// further is stored or acknowledged
+#[test]
+fn collector_gap_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+    let path = log_path("collector-gap-test");
+
+    let mut collector = audit::Collector::open(client.clone(), &path).unwrap();
+    collector.collect().unwrap();
+    let stored = line_count(&path);
+
+    for _ in 0..100 {
+        client.get_storage_info().unwrap();
+    }
+
+    let err = collector.collect().unwrap_err();
+    assert_eq!(*err.kind(), audit::ErrorKind::ChainInvalid);
+    assert_eq!(line_count(&path), stored);
+
+    fs::remove_file(path).unwrap();
+}
+
+/// Entries read back from a log file verify offline from a trusted digest
+#[test]
+fn verify_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+    let path = log_path("verify-test");
+
+    let mut collector = audit::Collector::open(client.clone(), &path).unwrap();
+    client.get_storage_info().unwrap();
+    collector.collect().unwrap();
+    drop(collector);
+
+    let entries = audit::read_log_file(&path).unwrap();
+    let (first, rest) = entries.split_first().unwrap();
+    audit::verify(&first.digest, rest).unwrap();
+
+    // The chain doesn't start from another digest
+    let err = audit::verify(&rest[0].digest, rest).unwrap_err();
+    assert_eq!(*err.kind(), audit::ErrorKind::ChainInvalid);
+
+    // Missing entries are detected
+    let mut missing = rest.to_vec();
+    missing.remove(1);
+    let err = audit::verify(&first.digest, &missing).unwrap_err();
+    assert_eq!(*err.kind(), audit::ErrorKind::ChainInvalid);
+
+    // Tampered entries are detected
+    let mut tampered = rest.to_vec();
+    tampered[1].tick += 1;
+    let err = audit::verify(&first.digest, &tampered).unwrap_err();
+    assert_eq!(*err.kind(), audit::ErrorKind::ChainInvalid);
+
+    fs::remove_file(path).unwrap();
+}
+
+/// Entries are decoded and exported with labels resolved from the objects
+#[test]
+fn export_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+    client.get_storage_info().unwrap();
+
+    let entries = hsm.log_entries();
+    let objects = hsm.objects();
+
+    let entry = entries
+        .iter()
+        .find(|entry| entry.cmd == command::Code::GetStorageInfo)
+        .unwrap();
+
+    let record = audit::export::Record::decode(entry, &objects);
+    assert_eq!(record.command, "get-storage-info");
+    assert!(record.success);
+    assert_eq!(record.result, "success");
+    assert_eq!(record.target_key, None);
+
+    let session_key = record.session_key.as_ref().unwrap();
+    assert_eq!(session_key.id, 1);
+    assert_eq!(
+        session_key.label.as_deref(),
+        Some("DEFAULT AUTHKEY CHANGE THIS ASAP")
+    );
+
+    let mut csv = vec![];
+    audit::export::export(&entries, &objects, &audit::export::Format::Csv, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some(audit::export::CSV_HEADER));
+    assert_eq!(lines.count(), entries.len());
+
+    let syslog = record.to_syslog(&Default::default());
+    assert!(syslog.starts_with("<110>1 - - yubihsm - get-storage-info [yubihsm@32473 item="));
+    assert!(syslog.contains(" session_key_label=\"DEFAULT AUTHKEY CHANGE THIS ASAP\""));
+    assert!(syslog.ends_with("] get-storage-info: success"));
+}
+
+/// With forced auditing, a full log is drained into the log sink and the
+/// refused command retried
+#[test]
+fn log_sink_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+    client
+        .set_force_audit_option(audit::AuditOption::On)
+        .unwrap();
+
+    // Without a log sink, commands are refused once the log is full
+    let err = loop {
+        if let Err(err) = client.get_storage_info() {
+            break err;
+        }
+    };
+
+    assert_eq!(err.device_error(), Some(device::ErrorKind::LogFull));
+
+    let drained = Arc::new(Mutex::new(vec![]));
+    let mut client = crate::open_mockhsm_client(&hsm);
+    let sink = drained.clone();
+    client.set_log_sink(move |log| {
+        sink.lock().unwrap().extend(log.entries.iter().cloned());
+        Ok(())
+    });
+
+    client.get_storage_info().unwrap();
+
+    let drained = drained.lock().unwrap();
+    let last = drained.last().unwrap();
+    assert!(drained
+        .windows(2)
+        .all(|pair| pair[1].verify_digest(&pair[0].digest)));
+    assert!(hsm.log_entries().iter().all(|entry| entry.item > last.item));
+}
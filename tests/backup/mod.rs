@@ -0,0 +1,192 @@
+//! Backup tests: back up one `MockHsm` and restore it into another
+
+#![cfg(all(feature = "backup", feature = "mockhsm"))]
+
+use yubihsm::{
+    asymmetric,
+    backup::{self, restore},
+    device, hmac,
+    mockhsm::MockHsm,
+    object, opaque, wrap, Capability, Domain,
+};
+
+/// ID of the wrap key objects are backed up under
+const WRAP_KEY_ID: object::Id = 0x100;
+
+/// Wrap key shared by the source and target HSMs
+const WRAP_KEY: [u8; 32] = [0x42; 32];
+
+/// Create a `MockHsm` holding the backup wrap key
+fn mockhsm_with_wrap_key() -> MockHsm {
+    MockHsm::builder()
+        .wrap_key(
+            WRAP_KEY_ID,
+            "backup key".into(),
+            Domain::all(),
+            Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED,
+            Capability::all(),
+            wrap::Algorithm::Aes256Ccm,
+            WRAP_KEY,
+        )
+        .build()
+}
+
+/// Create a backup of an HSM with two exportable objects and one which
+/// isn't exportable
+fn create_backup() -> backup::Archive {
+    let client = crate::open_mockhsm_client(&mockhsm_with_wrap_key());
+
+    client
+        .put_opaque(
+            0x200,
+            "exportable data".into(),
+            Domain::DOM1,
+            Capability::EXPORTABLE_UNDER_WRAP,
+            opaque::Algorithm::Data,
+            b"backed up".as_ref(),
+        )
+        .unwrap();
+
+    client
+        .generate_asymmetric_key(
+            0x201,
+            "exportable key".into(),
+            Domain::DOM1,
+            Capability::SIGN_EDDSA | Capability::EXPORTABLE_UNDER_WRAP,
+            asymmetric::Algorithm::Ed25519,
+        )
+        .unwrap();
+
+    client
+        .generate_hmac_key(
+            0x202,
+            "non-exportable key".into(),
+            Domain::DOM1,
+            Capability::SIGN_HMAC,
+            hmac::Algorithm::Sha256,
+        )
+        .unwrap();
+
+    backup::create(&client, WRAP_KEY_ID).unwrap()
+}
+
+#[test]
+fn backup_and_restore_test() {
+    let archive = create_backup();
+
+    assert_eq!(archive.version, backup::VERSION);
+    assert_eq!(archive.manifest.wrap_key_id, WRAP_KEY_ID);
+    assert_eq!(
+        archive
+            .objects
+            .iter()
+            .map(backup::Entry::handle)
+            .collect::<Vec<_>>(),
+        [
+            object::Handle::new(0x200, object::Type::Opaque),
+            object::Handle::new(0x201, object::Type::AsymmetricKey),
+        ]
+    );
+    assert_eq!(archive.objects[0].label, "exportable data");
+
+    let archive: backup::Archive = archive.to_json().parse().unwrap();
+    archive.verify().unwrap();
+
+    let target = crate::open_mockhsm_client(&mockhsm_with_wrap_key());
+
+    // A dry run imports nothing
+    let report = restore(
+        &target,
+        &archive,
+        &restore::Options::default().dry_run(true),
+    )
+    .unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.restored.len(), 2);
+    assert_eq!(
+        target
+            .get_object_info(0x200, object::Type::Opaque)
+            .unwrap_err()
+            .device_error(),
+        Some(device::ErrorKind::ObjectNotFound)
+    );
+
+    let report = restore(&target, &archive, &Default::default()).unwrap();
+    assert_eq!(report.restored.len(), 2);
+    assert!(report.conflicts.is_empty());
+    assert_eq!(target.get_opaque(0x200).unwrap(), b"backed up");
+
+    let info = target
+        .get_object_info(0x201, object::Type::AsymmetricKey)
+        .unwrap();
+    assert_eq!(info.origin, object::Origin::WrappedGenerated);
+}
+
+#[test]
+fn restore_conflict_test() {
+    let archive = create_backup();
+    let target = crate::open_mockhsm_client(&mockhsm_with_wrap_key());
+
+    target
+        .put_opaque(
+            0x200,
+            "existing data".into(),
+            Domain::DOM1,
+            Capability::empty(),
+            opaque::Algorithm::Data,
+            b"existing".as_ref(),
+        )
+        .unwrap();
+
+    let err = restore(&target, &archive, &Default::default()).unwrap_err();
+    assert_eq!(*err.kind(), backup::ErrorKind::Conflict);
+
+    // Nothing is imported when the restore is aborted
+    assert!(target
+        .get_object_info(0x201, object::Type::AsymmetricKey)
+        .is_err());
+
+    let report = restore(
+        &target,
+        &archive,
+        &restore::Options::default().skip_existing(true),
+    )
+    .unwrap();
+
+    assert_eq!(
+        report.restored,
+        [object::Handle::new(0x201, object::Type::AsymmetricKey)]
+    );
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(
+        report.conflicts[0].existing.label,
+        object::Label::from("existing data")
+    );
+    assert_eq!(target.get_opaque(0x200).unwrap(), b"existing");
+}
+
+#[test]
+fn restore_tampered_archive_test() {
+    let mut archive = create_backup();
+    archive.objects[0].digest = "00".repeat(32);
+
+    let target = crate::open_mockhsm_client(&mockhsm_with_wrap_key());
+    let err = restore(&target, &archive, &Default::default()).unwrap_err();
+    assert_eq!(*err.kind(), backup::ErrorKind::DigestMismatch);
+
+    let mut archive = create_backup();
+    archive.version += 1;
+    assert_eq!(
+        *archive.verify().unwrap_err().kind(),
+        backup::ErrorKind::ArchiveInvalid
+    );
+}
+
+#[test]
+fn restore_missing_wrap_key_test() {
+    let archive = create_backup();
+    let target = crate::open_mockhsm_client(&MockHsm::new());
+
+    let err = restore(&target, &archive, &Default::default()).unwrap_err();
+    assert_eq!(*err.kind(), backup::ErrorKind::ImportFailed);
+}
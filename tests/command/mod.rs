@@ -17,12 +17,15 @@ pub mod list_objects;
 pub mod put_asymmetric_key;
 pub mod put_authentication_key;
 pub mod put_opaque;
+pub mod put_template;
 #[cfg(feature = "mockhsm")]
 pub mod reset_device;
 pub mod set_option;
-#[cfg(not(feature = "mockhsm"))]
 pub mod sign_attestation_certificate;
 #[cfg(not(feature = "mockhsm"))]
 pub mod sign_ecdsa;
 pub mod sign_eddsa;
+#[cfg(all(feature = "mockhsm", feature = "untested"))]
+pub mod sign_ssh_certificate;
 pub mod verify_hmac;
+pub mod wrap_data;
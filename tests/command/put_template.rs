@@ -0,0 +1,32 @@
+use yubihsm::{object, ssh, Capability};
+
+use crate::{clear_test_key_slot, TEST_DOMAINS, TEST_KEY_ID, TEST_KEY_LABEL};
+
+/// Example SSH template: allowlist containing the test key as the only CA key
+const TEST_SSH_TEMPLATE: &[u8] = &[0x03, 0x00, 0x02, 0x00, 0x64];
+
+/// Put an SSH certificate template and read it back
+#[test]
+fn put_template_test() {
+    let client = crate::get_hsm_client();
+
+    clear_test_key_slot(&client, object::Type::Template);
+
+    let object_id = client
+        .put_template(
+            TEST_KEY_ID,
+            TEST_KEY_LABEL.into(),
+            TEST_DOMAINS,
+            Capability::default(),
+            ssh::Template::from_bytes(TEST_SSH_TEMPLATE),
+        )
+        .unwrap_or_else(|err| panic!("error putting template: {err}"));
+
+    assert_eq!(object_id, TEST_KEY_ID);
+
+    let template = client
+        .get_template(TEST_KEY_ID)
+        .unwrap_or_else(|err| panic!("error getting template: {err}"));
+
+    assert_eq!(template, TEST_SSH_TEMPLATE);
+}
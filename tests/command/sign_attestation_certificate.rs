@@ -1,4 +1,5 @@
 use crate::{generate_asymmetric_key, EC_P256_PUBLIC_KEY_SIZE, TEST_KEY_ID};
+use x509_cert::{der::Decode, Certificate};
 use yubihsm::{asymmetric, Capability};
 
 /// Generate an attestation about a key in the HSM
@@ -16,6 +17,20 @@ fn attest_asymmetric_test() {
         .sign_attestation_certificate(TEST_KEY_ID, None)
         .unwrap_or_else(|err| panic!("error getting attestation certificate: {}", err));
 
-    // TODO: more tests, e.g. test that the certificate validates
     assert!(certificate.len() > EC_P256_PUBLIC_KEY_SIZE);
+
+    // Certificate must attest to the public key of the key in the HSM
+    let certificate = Certificate::from_der(certificate.as_slice())
+        .unwrap_or_else(|err| panic!("error parsing attestation certificate: {}", err));
+
+    let public_key = client.get_public_key(TEST_KEY_ID).unwrap();
+
+    let subject_public_key = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .raw_bytes();
+
+    // Uncompressed SEC1 point: 0x04 tag followed by the coordinates
+    assert_eq!(&subject_public_key[1..], public_key.as_slice());
 }
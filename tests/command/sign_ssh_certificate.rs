@@ -0,0 +1,212 @@
+use ::ecdsa::signature::Verifier;
+use ::rsa::{
+    pkcs1v15,
+    pkcs8::DecodePrivateKey,
+    signature::{SignatureEncoding, Signer},
+    traits::PublicKeyParts,
+    RsaPrivateKey,
+};
+use yubihsm::{asymmetric, device, ecdsa, object, ssh, Capability, Client};
+
+use crate::{
+    clear_test_key_slot, generate_asymmetric_key, TEST_DOMAINS, TEST_KEY_ID, TEST_KEY_LABEL,
+};
+
+/// RSA-2048 timestamp key, PKCS#8 encoded as ASN.1 DER
+const TIMESTAMP_KEY_DER: &[u8] = include_bytes!("../rsa/rsa2048-priv.der");
+
+/// Timestamp of the test requests
+const TEST_TIMESTAMP: u32 = 1_700_000_000;
+
+/// Seconds before the timestamp certificates may become valid
+const TEST_NOT_BEFORE: u32 = 60;
+
+/// Seconds after the timestamp certificates may stay valid
+const TEST_NOT_AFTER: u32 = 3600;
+
+/// Sign an SSH certificate request using a template
+#[test]
+fn sign_ssh_certificate_test() {
+    let client = crate::get_hsm_client();
+    let timestamp_key = RsaPrivateKey::from_pkcs8_der(TIMESTAMP_KEY_DER).unwrap();
+    put_ca_key_and_template(&client, &timestamp_key, true);
+
+    let request = certificate_request(TEST_TIMESTAMP - 30, TEST_TIMESTAMP + 600);
+
+    let certificate = client
+        .sign_ssh_certificate(
+            TEST_KEY_ID,
+            TEST_KEY_ID,
+            ecdsa::Algorithm::Sha256,
+            TEST_TIMESTAMP,
+            timestamp_signature(&timestamp_key, TEST_TIMESTAMP, &request),
+            request.clone(),
+        )
+        .unwrap_or_else(|err| panic!("error signing SSH certificate: {err}"));
+
+    // Certificate is the request followed by an SSH signature
+    let (signed_request, signature) = certificate.as_slice().split_at(request.len());
+    assert_eq!(signed_request, request.as_slice());
+
+    let mut fields = ssh_strings(&signature[4..]);
+    assert_eq!(fields.next().unwrap(), b"ecdsa-sha2-nistp256");
+
+    let mut scalars = ssh_strings(fields.next().unwrap());
+    let mut signature_bytes = [0u8; 64];
+
+    for chunk in signature_bytes.chunks_mut(32) {
+        let scalar = scalars.next().unwrap();
+        let scalar = &scalar[scalar.len().saturating_sub(32)..];
+        chunk[32 - scalar.len()..].copy_from_slice(scalar);
+    }
+
+    let public_key = client
+        .get_public_key(TEST_KEY_ID)
+        .unwrap()
+        .ecdsa::<ecdsa::NistP256>()
+        .unwrap();
+
+    let verifying_key = p256::ecdsa::VerifyingKey::from_encoded_point(&public_key).unwrap();
+    let signature = p256::ecdsa::Signature::from_slice(&signature_bytes).unwrap();
+    assert!(verifying_key.verify(&request, &signature).is_ok());
+}
+
+/// Requests are rejected unless their timestamp signature verifies and the
+/// certificate is valid within the timestamp window of the template
+#[test]
+fn sign_ssh_certificate_rejected_test() {
+    let client = crate::get_hsm_client();
+    let timestamp_key = RsaPrivateKey::from_pkcs8_der(TIMESTAMP_KEY_DER).unwrap();
+    put_ca_key_and_template(&client, &timestamp_key, true);
+
+    let request = certificate_request(TEST_TIMESTAMP, TEST_TIMESTAMP + 600);
+
+    // Signature over a different timestamp
+    let signature = timestamp_signature(&timestamp_key, TEST_TIMESTAMP + 1, &request);
+    assert_rejected(&client, TEST_TIMESTAMP, signature, request);
+
+    // Valid from too long before the timestamp
+    let request = certificate_request(TEST_TIMESTAMP - TEST_NOT_BEFORE - 1, TEST_TIMESTAMP);
+    let signature = timestamp_signature(&timestamp_key, TEST_TIMESTAMP, &request);
+    assert_rejected(&client, TEST_TIMESTAMP, signature, request);
+
+    // Valid until too long after the timestamp
+    let request = certificate_request(TEST_TIMESTAMP, TEST_TIMESTAMP + TEST_NOT_AFTER + 1);
+    let signature = timestamp_signature(&timestamp_key, TEST_TIMESTAMP, &request);
+    assert_rejected(&client, TEST_TIMESTAMP, signature, request);
+
+    // Template without a CA key allowlist
+    put_ca_key_and_template(&client, &timestamp_key, false);
+    let request = certificate_request(TEST_TIMESTAMP, TEST_TIMESTAMP + 600);
+    let signature = timestamp_signature(&timestamp_key, TEST_TIMESTAMP, &request);
+    assert_rejected(&client, TEST_TIMESTAMP, signature, request);
+}
+
+/// Generate the test CA key and put an SSH template for it, with or without
+/// the CA key allowlist
+fn put_ca_key_and_template(client: &Client, timestamp_key: &RsaPrivateKey, allowlist: bool) {
+    generate_asymmetric_key(
+        client,
+        asymmetric::Algorithm::EcP256,
+        Capability::SIGN_SSH_CERTIFICATE,
+    );
+
+    clear_test_key_slot(client, object::Type::Template);
+
+    let mut template = vec![];
+    put_template_entry(&mut template, 1, &[asymmetric::Algorithm::Rsa2048 as u8]);
+    put_template_entry(&mut template, 2, &timestamp_key.n().to_bytes_be());
+
+    if allowlist {
+        put_template_entry(&mut template, 3, &TEST_KEY_ID.to_be_bytes());
+    }
+
+    put_template_entry(&mut template, 4, &TEST_NOT_BEFORE.to_be_bytes());
+    put_template_entry(&mut template, 5, &TEST_NOT_AFTER.to_be_bytes());
+
+    client
+        .put_template(
+            TEST_KEY_ID,
+            TEST_KEY_LABEL.into(),
+            TEST_DOMAINS,
+            Capability::default(),
+            ssh::Template::from_bytes(template),
+        )
+        .unwrap_or_else(|err| panic!("error putting template: {err}"));
+}
+
+/// Ensure the device refuses to sign the given request
+fn assert_rejected(client: &Client, timestamp: u32, signature: [u8; 256], request: Vec<u8>) {
+    let err = client
+        .sign_ssh_certificate(
+            TEST_KEY_ID,
+            TEST_KEY_ID,
+            ecdsa::Algorithm::Sha256,
+            timestamp,
+            signature,
+            request,
+        )
+        .unwrap_err();
+
+    assert_eq!(err.device_error(), Some(device::ErrorKind::InvalidData));
+}
+
+/// Append a TLV entry to an SSH template
+fn put_template_entry(template: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    template.push(tag);
+    template.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    template.extend_from_slice(value);
+}
+
+/// Sign the timestamp and request with the timestamp key
+fn timestamp_signature(key: &RsaPrivateKey, timestamp: u32, request: &[u8]) -> [u8; 256] {
+    let mut message = timestamp.to_be_bytes().to_vec();
+    message.extend_from_slice(request);
+
+    pkcs1v15::SigningKey::<sha2::Sha256>::new(key.clone())
+        .sign(&message)
+        .to_vec()
+        .try_into()
+        .unwrap()
+}
+
+/// SSH certificate request for an ECDSA P-256 user key, valid for the given
+/// period (`PROTOCOL.certkeys` in OpenSSH)
+fn certificate_request(valid_after: u32, valid_before: u32) -> Vec<u8> {
+    let mut request = vec![];
+    put_ssh_string(&mut request, b"ecdsa-sha2-nistp256-cert-v01@openssh.com");
+    put_ssh_string(&mut request, &[0x42; 32]);
+    put_ssh_string(&mut request, b"nistp256");
+    put_ssh_string(&mut request, &[0x04; 65]);
+    request.extend_from_slice(&1u64.to_be_bytes());
+    request.extend_from_slice(&1u32.to_be_bytes());
+    put_ssh_string(&mut request, b"test user");
+    put_ssh_string(&mut request, b"");
+    request.extend_from_slice(&u64::from(valid_after).to_be_bytes());
+    request.extend_from_slice(&u64::from(valid_before).to_be_bytes());
+    put_ssh_string(&mut request, b"");
+    put_ssh_string(&mut request, b"");
+    put_ssh_string(&mut request, b"");
+    put_ssh_string(&mut request, b"");
+    request
+}
+
+/// Append an SSH `string`
+fn put_ssh_string(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+/// Iterate over a sequence of length-prefixed SSH `string`s
+fn ssh_strings(mut bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        if bytes.len() < 4 {
+            return None;
+        }
+
+        let len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+        let (string, rest) = bytes[4..].split_at(len);
+        bytes = rest;
+        Some(string)
+    })
+}
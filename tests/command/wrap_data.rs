@@ -0,0 +1,47 @@
+use crate::{
+    clear_test_key_slot, test_vectors::AESCCM_TEST_VECTORS, TEST_DOMAINS, TEST_KEY_ID,
+    TEST_KEY_LABEL, TEST_MESSAGE,
+};
+use yubihsm::{device, object, wrap, Capability};
+
+/// Encrypt data under a wrap key and decrypt it again
+#[test]
+fn wrap_data_test() {
+    let client = crate::get_hsm_client();
+
+    clear_test_key_slot(&client, object::Type::WrapKey);
+
+    client
+        .put_wrap_key(
+            TEST_KEY_ID,
+            TEST_KEY_LABEL.into(),
+            TEST_DOMAINS,
+            Capability::WRAP_DATA | Capability::UNWRAP_DATA,
+            Capability::default(),
+            wrap::Algorithm::Aes128Ccm,
+            AESCCM_TEST_VECTORS[0].key,
+        )
+        .unwrap_or_else(|err| panic!("error putting wrap key: {err}"));
+
+    let wrap_message = client
+        .wrap_data(TEST_KEY_ID, TEST_MESSAGE.to_vec())
+        .unwrap_or_else(|err| panic!("error wrapping data: {err}"));
+
+    assert_ne!(wrap_message.ciphertext.as_slice(), TEST_MESSAGE);
+
+    let plaintext = client
+        .unwrap_data(TEST_KEY_ID, wrap_message.clone())
+        .unwrap_or_else(|err| panic!("error unwrapping data: {err}"));
+
+    assert_eq!(plaintext, TEST_MESSAGE);
+
+    // Tampering with the ciphertext must cause decryption to fail
+    let mut tampered_message = wrap_message;
+    tampered_message.ciphertext[0] ^= 1;
+
+    let err = client
+        .unwrap_data(TEST_KEY_ID, tampered_message)
+        .expect_err("tampered ciphertext should be rejected");
+
+    assert_eq!(err.device_error(), Some(device::ErrorKind::InvalidData));
+}
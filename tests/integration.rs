@@ -4,6 +4,15 @@ use once_cell::sync::Lazy;
 use std::sync::{Mutex, MutexGuard};
 use yubihsm::{asymmetric, device, object, Capability, Client, Connector, Domain};
 
+#[cfg(feature = "mockhsm")]
+use yubihsm::mockhsm::MockHsm;
+
+/// Audit log collection and verification tests
+mod audit;
+
+/// Full-device backup and restore tests
+mod backup;
+
 /// Integration tests for individual YubiHSM 2 commands
 mod command;
 
@@ -13,12 +22,30 @@ mod ecdsa;
 /// Ed25519 tests
 mod ed25519;
 
+/// Object inventory tests
+mod inventory;
+
+/// MockHsm device emulation tests
+mod mockhsm;
+
+/// Object query tests
+mod query;
+
+/// Key rotation tests
+mod rotation;
+
 /// Rsa tests
 mod rsa;
 
+/// Declarative provisioning tests
+mod setup;
+
 /// Cryptographic test vectors taken from standards documents
 mod test_vectors;
 
+/// Typed object handle tests
+mod typed;
+
 /// Key ID to use for testing keygen/signing
 const TEST_KEY_ID: object::Id = 100;
 
@@ -103,6 +130,13 @@ pub fn create_mockhsm_connector() -> Connector {
     Connector::mockhsm()
 }
 
+/// Open a client (and therefore a new session) to the given `MockHsm`, for
+/// tests which need a device of their own
+#[cfg(feature = "mockhsm")]
+pub fn open_mockhsm_client(hsm: &MockHsm) -> Client {
+    Client::open(Connector::from(hsm.clone()), Default::default(), false).unwrap()
+}
+
 /// Delete the key in the test key slot (if it exists, otherwise do nothing)
 pub fn clear_test_key_slot(client: &Client, object_type: object::Type) {
     println!("clearing test key slot: {object_type:?} {TEST_KEY_ID}");
@@ -0,0 +1,85 @@
+//! Inventory tests: snapshot the objects in a `MockHsm` and diff snapshots
+
+#![cfg(feature = "mockhsm")]
+
+use yubihsm::{asymmetric, mockhsm::MockHsm, object, opaque, Capability, Domain};
+
+/// Objects are snapshotted with their public keys, and changes between
+/// snapshots are found
+#[test]
+fn inventory_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+
+    client
+        .generate_asymmetric_key(
+            0x100,
+            "signing key".into(),
+            Domain::DOM1,
+            Capability::SIGN_EDDSA,
+            asymmetric::Algorithm::Ed25519,
+        )
+        .unwrap();
+
+    client
+        .put_opaque(
+            0x101,
+            "data".into(),
+            Domain::DOM1,
+            Capability::empty(),
+            opaque::Algorithm::Data,
+            b"before".as_ref(),
+        )
+        .unwrap();
+
+    // The default authentication key, the device attestation key and its
+    // certificate, and the two objects above
+    let before = client.inventory(true).unwrap();
+    assert_eq!(before.objects.len(), 5);
+    assert_eq!(before.storage, client.get_storage_info().unwrap());
+
+    let key = before.get(0x100, object::Type::AsymmetricKey).unwrap();
+    assert_eq!(key.label, "signing key");
+    assert_eq!(key.public_key, Some(client.get_public_key(0x100).unwrap()));
+
+    let data = before.get(0x101, object::Type::Opaque).unwrap();
+    assert_eq!(data.public_key, None);
+
+    let without_keys = client.inventory(false).unwrap();
+    let key = without_keys
+        .get(0x100, object::Type::AsymmetricKey)
+        .unwrap();
+    assert_eq!(key.public_key, None);
+
+    // Remove the key, replace the opaque object with a shorter one and add
+    // a new one
+    client
+        .delete_object(0x100, object::Type::AsymmetricKey)
+        .unwrap();
+    client.delete_object(0x101, object::Type::Opaque).unwrap();
+
+    for (id, label) in [(0x101, "data"), (0x102, "more data")] {
+        client
+            .put_opaque(
+                id,
+                label.into(),
+                Domain::DOM1,
+                Capability::empty(),
+                opaque::Algorithm::Data,
+                b"after".as_ref(),
+            )
+            .unwrap();
+    }
+
+    let after = client.inventory(true).unwrap();
+    assert!(before.diff(&before).is_empty());
+
+    let diff = before.diff(&after);
+    assert_eq!(diff.removed.len(), 1);
+    assert_eq!(diff.removed[0].object_id, 0x100);
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.added[0].object_id, 0x102);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].before.length, 6);
+    assert_eq!(diff.changed[0].after.length, 5);
+}
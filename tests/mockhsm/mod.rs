@@ -0,0 +1,431 @@
+//! MockHsm tests: device limits which can't be exercised against the
+//! shared test client
+
+#![cfg(feature = "mockhsm")]
+
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use yubihsm::{
+    asymmetric,
+    authentication::{self, Credentials},
+    command, device, hmac,
+    mockhsm::MockHsm,
+    object, opaque, wrap, Algorithm, AuditOption, Capability, Client, Connector, Domain,
+};
+
+/// Sessions beyond the 16 supported by the device are rejected
+#[test]
+fn sessions_full_test() {
+    let hsm = MockHsm::new();
+
+    let _clients = (0..16)
+        .map(|_| crate::open_mockhsm_client(&hsm))
+        .collect::<Vec<_>>();
+
+    let err = Client::open(Connector::from(hsm.clone()), Default::default(), false)
+        .err()
+        .unwrap();
+    assert_eq!(err.device_error(), Some(device::ErrorKind::SessionsFull));
+}
+
+/// Sessions are closed after 30 seconds of inactivity
+#[test]
+fn session_timeout_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+
+    hsm.advance_clock(Duration::from_secs(29));
+    client.echo(b"still alive").unwrap();
+
+    hsm.advance_clock(Duration::from_secs(30));
+    assert!(client.echo(b"timed out").is_err());
+
+    // Timed out sessions no longer count against the session limit
+    let _clients = (0..16)
+        .map(|_| crate::open_mockhsm_client(&hsm))
+        .collect::<Vec<_>>();
+
+    hsm.advance_clock(Duration::from_secs(30));
+    crate::open_mockhsm_client(&hsm);
+}
+
+/// Objects consume storage records and pages until storage is exhausted
+#[test]
+fn storage_accounting_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+
+    let initial = client.get_storage_info().unwrap();
+
+    client
+        .put_opaque(
+            1,
+            "storage test".into(),
+            Domain::DOM1,
+            Capability::default(),
+            opaque::Algorithm::Data,
+            vec![0u8; 1000],
+        )
+        .unwrap();
+
+    let info = client.get_storage_info().unwrap();
+    assert_eq!(info.free_records, initial.free_records - 1);
+    assert_eq!(info.free_pages, initial.free_pages - 8);
+
+    let mut object_id: object::Id = 2;
+
+    let err = loop {
+        match client.put_opaque(
+            object_id,
+            "storage test".into(),
+            Domain::DOM1,
+            Capability::default(),
+            opaque::Algorithm::Data,
+            vec![0u8; 1800],
+        ) {
+            Ok(_) => object_id += 1,
+            Err(err) => break err,
+        }
+    };
+
+    assert_eq!(err.device_error(), Some(device::ErrorKind::StorageFailed));
+    assert!(client.get_storage_info().unwrap().free_pages < 15);
+
+    // Deleting an object frees its storage
+    client.delete_object(1, object::Type::Opaque).unwrap();
+
+    let info = client.get_storage_info().unwrap();
+    assert!(info.free_pages >= 8);
+}
+
+/// Invalid requests produce device errors rather than bringing down the MockHsm
+#[test]
+fn device_errors_test() {
+    let hsm = MockHsm::new();
+
+    let bad_credentials = Credentials::new(999, authentication::Key::random());
+    assert!(Client::open(Connector::from(hsm.clone()), bad_credentials, false).is_err());
+
+    let client = crate::open_mockhsm_client(&hsm);
+
+    let err = client
+        .put_asymmetric_key(
+            1,
+            "invalid key".into(),
+            Domain::DOM1,
+            Capability::SIGN_ECDSA,
+            asymmetric::Algorithm::EcP256,
+            [0u8; 32],
+        )
+        .unwrap_err();
+
+    assert_eq!(err.device_error(), Some(device::ErrorKind::InvalidData));
+
+    // Valid primes which are too short for the algorithm
+    let mut primes = [0u8; 256];
+    primes[120..128].copy_from_slice(&((1u64 << 61) - 1).to_be_bytes());
+    primes[248..].copy_from_slice(&((1u64 << 31) - 1).to_be_bytes());
+
+    let err = client
+        .put_asymmetric_key(
+            2,
+            "short rsa key".into(),
+            Domain::DOM1,
+            Capability::SIGN_PKCS,
+            asymmetric::Algorithm::Rsa2048,
+            primes,
+        )
+        .unwrap_err();
+
+    assert_eq!(err.device_error(), Some(device::ErrorKind::InvalidData));
+
+    for expected in [None, Some(device::ErrorKind::ObjectExists)] {
+        let result = client.put_opaque(
+            1,
+            "duplicate".into(),
+            Domain::DOM1,
+            Capability::default(),
+            opaque::Algorithm::Data,
+            b"hello".to_vec(),
+        );
+
+        assert_eq!(result.err().and_then(|err| err.device_error()), expected);
+    }
+
+    let err = client.sign_hmac(1, b"no such key".to_vec()).unwrap_err();
+    assert_eq!(err.device_error(), Some(device::ErrorKind::ObjectNotFound));
+
+    client.echo(b"still alive").unwrap();
+}
+
+/// Device info reflects the serial number and firmware version the
+/// `MockHsm` was built with
+#[test]
+fn device_identity_test() {
+    for (serial, version) in [("0000000001", (2, 0, 0)), ("0000000002", (2, 2, 0))] {
+        let hsm = MockHsm::builder()
+            .serial_number(serial.parse().unwrap())
+            .firmware_version(version.0, version.1, version.2)
+            .build();
+
+        let info = crate::open_mockhsm_client(&hsm).device_info().unwrap();
+        assert_eq!(info.serial_number.to_string(), serial);
+        assert_eq!(
+            (info.major_version, info.minor_version, info.build_version),
+            version
+        );
+    }
+}
+
+/// Commands and options the emulated firmware doesn't support are rejected
+#[test]
+fn firmware_version_test() {
+    let new_key = authentication::Key::derive_from_password(b"new password");
+
+    let hsm = MockHsm::builder().firmware_version(2, 0, 0).build();
+    let client = crate::open_mockhsm_client(&hsm);
+
+    let err = client.get_fips_option().unwrap_err();
+    assert_eq!(err.device_error(), Some(device::ErrorKind::InvalidData));
+    client.get_force_audit_option().unwrap();
+
+    let err = client
+        .change_authentication_key(1, new_key.clone())
+        .unwrap_err();
+    assert_eq!(err.device_error(), Some(device::ErrorKind::InvalidCommand));
+
+    for minor in [2, 4] {
+        let hsm = MockHsm::builder().firmware_version(2, minor, 0).build();
+        let client = crate::open_mockhsm_client(&hsm);
+        client.get_fips_option().unwrap();
+        client
+            .change_authentication_key(1, new_key.clone())
+            .unwrap();
+
+        // The changed key is used for new sessions
+        Client::open(
+            Connector::from(hsm.clone()),
+            Credentials::new(1, new_key.clone()),
+            false,
+        )
+        .unwrap();
+    }
+}
+
+/// Objects can't be created with algorithms the device doesn't support
+#[test]
+fn supported_algorithms_test() {
+    let hsm = MockHsm::builder()
+        .algorithms([
+            Algorithm::Authentication(authentication::Algorithm::YubicoAes),
+            Algorithm::Asymmetric(asymmetric::Algorithm::EcP256),
+        ])
+        .build();
+
+    let client = crate::open_mockhsm_client(&hsm);
+    assert_eq!(client.device_info().unwrap().algorithms.len(), 2);
+
+    client
+        .generate_asymmetric_key(
+            1,
+            "supported".into(),
+            Domain::DOM1,
+            Capability::SIGN_ECDSA,
+            asymmetric::Algorithm::EcP256,
+        )
+        .unwrap();
+
+    let err = client
+        .generate_asymmetric_key(
+            2,
+            "unsupported".into(),
+            Domain::DOM1,
+            Capability::SIGN_EDDSA,
+            asymmetric::Algorithm::Ed25519,
+        )
+        .unwrap_err();
+
+    assert_eq!(err.device_error(), Some(device::ErrorKind::InvalidData));
+}
+
+/// Objects preloaded by the builder are usable through the client, and the
+/// device's state can be inspected directly
+#[test]
+fn preloaded_objects_test() {
+    let authentication_key = authentication::Key::random();
+
+    let hsm = MockHsm::builder()
+        .authentication_key(
+            2,
+            "test auth key".into(),
+            Domain::DOM1,
+            Capability::all(),
+            Capability::all(),
+            authentication_key.clone(),
+        )
+        .hmac_key(
+            3,
+            "test hmac key".into(),
+            Domain::DOM1,
+            Capability::SIGN_HMAC,
+            hmac::Algorithm::Sha256,
+            [0x42u8; 32],
+        )
+        .opaque(
+            4,
+            "test opaque".into(),
+            Domain::DOM1,
+            Capability::default(),
+            opaque::Algorithm::Data,
+            b"hello".to_vec(),
+        )
+        .build();
+
+    let credentials = Credentials::new(2, authentication_key);
+    let client = Client::open(Connector::from(hsm.clone()), credentials, false).unwrap();
+
+    assert_eq!(client.get_opaque(4).unwrap(), b"hello");
+    client.sign_hmac(3, b"preloaded".to_vec()).unwrap();
+
+    let hmac_key = hsm.object(3, object::Type::HmacKey).unwrap();
+    assert_eq!(hmac_key.algorithm, Algorithm::Hmac(hmac::Algorithm::Sha256));
+    assert_eq!(hmac_key.origin, object::Origin::Imported);
+    assert_eq!(hmac_key.label, object::Label::from("test hmac key"));
+    assert!(hsm
+        .objects()
+        .iter()
+        .any(|info| info.object_id == 2 && info.object_type == object::Type::AuthenticationKey));
+
+    let sessions = hsm.sessions();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].authentication_key_id, 2);
+
+    client.set_force_audit_option(AuditOption::On).unwrap();
+    assert_eq!(hsm.force_audit_option(), AuditOption::On);
+    assert_eq!(
+        hsm.command_audit_option(command::Code::SignHmac),
+        AuditOption::On
+    );
+
+    let entry = hsm.log_entries().pop().unwrap();
+    assert_eq!(entry.cmd, command::Code::SetOption);
+    assert_eq!(entry.session_key, 2);
+}
+
+/// Resetting the device keeps only the device attestation key and its
+/// certificate, even if other objects use the same ID
+#[test]
+fn reset_device_test() {
+    let hsm = MockHsm::builder()
+        .hmac_key(
+            0,
+            "test hmac key".into(),
+            Domain::DOM1,
+            Capability::SIGN_HMAC,
+            hmac::Algorithm::Sha256,
+            [0x42u8; 32],
+        )
+        .build();
+
+    let client = crate::open_mockhsm_client(&hsm);
+    client.reset_device().unwrap();
+
+    let mut handles: Vec<_> = hsm
+        .objects()
+        .iter()
+        .map(|info| (info.object_id, info.object_type))
+        .collect();
+
+    handles.sort();
+
+    assert_eq!(
+        handles,
+        [
+            (0, object::Type::Opaque),
+            (0, object::Type::AsymmetricKey),
+            (1, object::Type::AuthenticationKey),
+        ]
+    );
+}
+
+/// The audit log is hash chained and can be consumed via `SetLogIndex`
+#[test]
+fn audit_log_test() {
+    let hsm = MockHsm::new();
+    let client = crate::open_mockhsm_client(&hsm);
+
+    client.get_storage_info().unwrap();
+    client
+        .get_object_info(99, object::Type::Opaque)
+        .unwrap_err();
+
+    let log = client.get_log_entries().unwrap();
+    assert_eq!(usize::from(log.num_entries), log.entries.len());
+
+    for pair in log.entries.windows(2) {
+        assert_eq!(pair[1].item, pair[0].item + 1);
+
+        let mut hasher = Sha256::new();
+        hasher.update(pair[1].digest_payload().unwrap());
+        hasher.update(pair[0].digest.as_ref());
+        assert_eq!(&hasher.finalize()[..16], pair[1].digest.as_ref());
+    }
+
+    let last = log.entries.last().unwrap();
+    assert_eq!(last.cmd, command::Code::GetObjectInfo);
+
+    client.set_log_index(last.item).unwrap();
+    assert!(hsm.log_entries().iter().all(|entry| entry.item > last.item));
+}
+
+/// MockHsms built with the same RNG seed produce the same keys, wrapped
+/// objects, attestation certificates, and random data
+#[test]
+fn deterministic_rng_test() {
+    fn outputs(seed: u64) -> Vec<Vec<u8>> {
+        let hsm = MockHsm::builder().rng_seed(seed).build();
+        let client = crate::open_mockhsm_client(&hsm);
+
+        client
+            .generate_asymmetric_key(
+                1,
+                "deterministic".into(),
+                Domain::DOM1,
+                Capability::SIGN_ECDSA | Capability::EXPORTABLE_UNDER_WRAP,
+                asymmetric::Algorithm::EcP256,
+            )
+            .unwrap();
+
+        client
+            .generate_wrap_key(
+                2,
+                "deterministic".into(),
+                Domain::DOM1,
+                Capability::EXPORT_WRAPPED,
+                Capability::all(),
+                wrap::Algorithm::Aes128Ccm,
+            )
+            .unwrap();
+
+        let wrapped = client
+            .export_wrapped(2, object::Type::AsymmetricKey, 1)
+            .unwrap();
+
+        let signature = client.sign_ecdsa_prehash_raw(1, vec![0x42; 32]).unwrap();
+
+        vec![
+            client.get_pseudo_random(32).unwrap(),
+            client.get_public_key(1).unwrap().as_slice().to_vec(),
+            wrapped.into_vec(),
+            signature,
+            client.get_opaque(0).unwrap(),
+            client
+                .sign_attestation_certificate(1, None)
+                .unwrap()
+                .into_vec(),
+        ]
+    }
+
+    assert_eq!(outputs(1), outputs(1));
+    assert_ne!(outputs(1)[1], outputs(2)[1]);
+}
@@ -0,0 +1,110 @@
+//! Object query tests: find objects in a `MockHsm` by label and attributes
+
+#![cfg(feature = "mockhsm")]
+
+use yubihsm::{
+    asymmetric, device, ed25519, mockhsm::MockHsm, object, opaque, Capability, Client, Domain,
+};
+
+/// Open a client to a `MockHsm` holding a few labeled objects
+fn client_with_objects() -> Client {
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    for (id, label) in [(0x100, "prod-signer-2025"), (0x101, "prod-signer-2026")] {
+        client
+            .generate_asymmetric_key(
+                id,
+                label.into(),
+                Domain::DOM1,
+                Capability::SIGN_EDDSA,
+                asymmetric::Algorithm::Ed25519,
+            )
+            .unwrap();
+    }
+
+    client
+        .put_opaque(
+            0x102,
+            "prod-signer-2026".into(),
+            Domain::DOM1,
+            Capability::empty(),
+            opaque::Algorithm::Data,
+            b"certificate".as_ref(),
+        )
+        .unwrap();
+
+    client
+}
+
+/// Objects are found by label and type
+#[test]
+fn find_object_test() {
+    let client = client_with_objects();
+
+    let info = client
+        .find_object("prod-signer-2026", object::Type::AsymmetricKey)
+        .unwrap();
+    assert_eq!(info.object_id, 0x101);
+
+    let info = client
+        .find_object("prod-signer-2026", object::Type::Opaque)
+        .unwrap();
+    assert_eq!(info.object_id, 0x102);
+
+    let err = client
+        .find_object("prod-signer-2027", object::Type::AsymmetricKey)
+        .unwrap_err();
+    assert_eq!(err.device_error(), Some(device::ErrorKind::ObjectNotFound));
+
+    let signer = ed25519::Signer::create_by_label(client.clone(), "prod-signer-2026").unwrap();
+    assert_eq!(
+        signer.public_key(),
+        &client.get_public_key(0x101).unwrap().ed25519().unwrap()
+    );
+}
+
+/// Queries combine device-side filters with client-side criteria
+#[test]
+fn find_objects_test() {
+    let client = client_with_objects();
+
+    let ids = |query: object::Query| -> Vec<object::Id> {
+        let mut ids: Vec<_> = client
+            .find_objects(&query)
+            .unwrap()
+            .iter()
+            .map(|info| info.object_id)
+            .collect();
+
+        ids.sort();
+        ids
+    };
+
+    let query = object::Query::new().label_prefix("prod-signer-");
+    assert_eq!(ids(query), [0x100, 0x101, 0x102]);
+
+    let query = object::Query::new()
+        .label_prefix("prod-signer-")
+        .capabilities(Capability::SIGN_EDDSA);
+    assert_eq!(ids(query), [0x100, 0x101]);
+
+    // Includes the device attestation certificate (opaque 0x0000)
+    let query = object::Query::new()
+        .object_type(object::Type::Opaque)
+        .object_type(object::Type::AuthenticationKey);
+    assert_eq!(ids(query), [0x0000, 0x0001, 0x102]);
+
+    let query = object::Query::new()
+        .label_prefix("prod-")
+        .origin(object::Origin::Imported);
+    assert_eq!(ids(query), [0x102]);
+
+    #[cfg(feature = "regex")]
+    {
+        let query = object::Query::new()
+            .label_regex(regex::Regex::new("^prod-signer-\\d+$").unwrap())
+            .object_type(object::Type::AsymmetricKey)
+            .algorithm(asymmetric::Algorithm::Ed25519.into());
+        assert_eq!(ids(query), [0x100, 0x101]);
+    }
+}
@@ -0,0 +1,96 @@
+//! Key rotation tests: rotate keys in a `MockHsm` through several versions
+
+#![cfg(feature = "mockhsm")]
+
+use std::time::Duration;
+use yubihsm::{asymmetric, hmac, mockhsm::MockHsm, object, rotation, Capability, Domain};
+
+/// Versions are generated, retired and pruned
+#[test]
+fn rotation_test() {
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let key = rotation::RotatingKey::new(
+        client.clone(),
+        "prod-signer",
+        asymmetric::Algorithm::Ed25519,
+        Domain::DOM1,
+        Capability::SIGN_EDDSA,
+    )
+    .unwrap();
+
+    let err = key.active().unwrap_err();
+    assert_eq!(*err.kind(), rotation::ErrorKind::NoVersions);
+
+    for number in 1..=3 {
+        let version = key.rotate().unwrap();
+        assert_eq!(version.number, number);
+        assert!(version.is_active());
+
+        let info = client
+            .get_object_info(version.object_id, object::Type::AsymmetricKey)
+            .unwrap();
+        assert!(info
+            .label
+            .to_string()
+            .starts_with(&format!("prod-signer.v{number}.")));
+    }
+
+    let versions = key.versions().unwrap();
+    assert_eq!(versions.len(), 3);
+    assert_eq!(key.active().unwrap(), versions[2]);
+    assert_eq!(versions[0].retired, Some(versions[1].created));
+    assert!(!versions[1].is_active());
+
+    // Retired versions are kept within the retention window
+    assert_eq!(key.public_keys().unwrap().len(), 3);
+    assert!(key.prune().unwrap().is_empty());
+
+    let key = key.retention(Duration::ZERO);
+    let public_keys = key.public_keys().unwrap();
+    assert_eq!(public_keys.len(), 1);
+    assert_eq!(public_keys[0].0, versions[2]);
+    assert_eq!(
+        public_keys[0].1,
+        client.get_public_key(versions[2].object_id).unwrap()
+    );
+
+    assert_eq!(key.prune().unwrap(), versions[..2]);
+    assert_eq!(key.versions().unwrap(), versions[2..]);
+}
+
+/// HMAC keys are rotated, but have no public keys
+#[test]
+fn hmac_rotation_test() {
+    let key = rotation::RotatingKey::new(
+        crate::open_mockhsm_client(&MockHsm::new()),
+        "webhook-mac",
+        hmac::Algorithm::Sha256,
+        Domain::DOM1,
+        Capability::SIGN_HMAC | Capability::VERIFY_HMAC,
+    )
+    .unwrap();
+
+    let version = key.rotate().unwrap();
+    assert_eq!(version.object_type, object::Type::HmacKey);
+    assert_eq!(key.valid_versions().unwrap(), [version]);
+
+    let err = key.public_keys().unwrap_err();
+    assert_eq!(*err.kind(), rotation::ErrorKind::AlgorithmInvalid);
+}
+
+/// Names too long to label versions are rejected
+#[test]
+fn rotation_name_test() {
+    let err = rotation::RotatingKey::new(
+        crate::open_mockhsm_client(&MockHsm::new()),
+        "a-name-much-too-long-for-labels",
+        asymmetric::Algorithm::Ed25519,
+        Domain::DOM1,
+        Capability::SIGN_EDDSA,
+    )
+    .err()
+    .unwrap();
+
+    assert_eq!(*err.kind(), rotation::ErrorKind::NameInvalid);
+}
@@ -0,0 +1,954 @@
+//! YubiHSM 2 setup tests: test declarative provisioning of a YubiHSM 2 from scratch
+
+use yubihsm::{
+    authentication::{self, Credentials},
+    object, Capability, Domain,
+};
+
+#[cfg(feature = "setup")]
+use yubihsm::setup::{Profile, Role};
+
+const ROOT_KEY_ID: object::Id = 1;
+const ROOT_KEY_LABEL: &str = "root key";
+
+#[cfg(feature = "setup")]
+#[test]
+fn setup_test() {
+    let root_key = authentication::Key::random();
+    let root_role = Role::new(Credentials::new(ROOT_KEY_ID, root_key))
+        .authentication_key_label(ROOT_KEY_LABEL)
+        .capabilities(Capability::all())
+        .domains(Domain::all());
+
+    // TODO: actually test provisioning the profile
+    let _profile = Profile::default().roles(vec![root_role]);
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn wrap_key_shares_test() {
+    use yubihsm::{opaque, wrap};
+
+    let key = wrap::Key::generate_random(0x100, wrap::Algorithm::Aes256Ccm)
+        .capabilities(Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED)
+        .delegated_capabilities(Capability::all());
+
+    let message = wrap::Plaintext::from_opaque(
+        wrap::Algorithm::Aes256Ccm,
+        0x200,
+        Capability::EXPORTABLE_UNDER_WRAP,
+        Domain::DOM1,
+        "shared secret".into(),
+        opaque::Algorithm::Data,
+        b"wrapped under a split key",
+    )
+    .unwrap()
+    .encrypt(&key)
+    .unwrap();
+
+    let shares: Vec<wrap::Share> = key
+        .split(3, 5)
+        .unwrap()
+        .iter()
+        .map(|share| share.to_string().to_lowercase().parse().unwrap())
+        .collect();
+
+    assert_eq!(shares.len(), 5);
+    assert!(shares.iter().all(|share| share.threshold() == 3));
+
+    // Any 3 shares recover the key
+    for subset in [
+        &shares[..3],
+        &shares[2..],
+        &[1, 4, 3].map(|i| shares[i].clone())[..],
+    ] {
+        let recovered = wrap::Key::combine(subset).unwrap();
+        assert_eq!(recovered.key_len(), 32);
+        message.verify(&recovered).unwrap();
+    }
+
+    // Extra shares are checked against the others
+    message
+        .verify(&wrap::Key::combine(&shares).unwrap())
+        .unwrap();
+
+    let other_key = wrap::Key::generate_random(0x100, wrap::Algorithm::Aes256Ccm)
+        .capabilities(Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED)
+        .delegated_capabilities(Capability::all());
+
+    let mut mixed = shares[..3].to_vec();
+    mixed.push(other_key.split(3, 5).unwrap()[4].clone());
+    assert_eq!(
+        *wrap::Key::combine(&mixed).unwrap_err().kind(),
+        wrap::ErrorKind::ShareInvalid
+    );
+
+    // Too few shares
+    assert_eq!(
+        *wrap::Key::combine(&shares[..2]).unwrap_err().kind(),
+        wrap::ErrorKind::ShareInvalid
+    );
+
+    // Duplicate shares
+    let duplicates = [shares[0].clone(), shares[1].clone(), shares[0].clone()];
+    assert!(wrap::Key::combine(&duplicates).is_err());
+
+    // Transcription errors are caught by the checksum
+    let mut typo = shares[0].to_string().into_bytes();
+    let last = typo.len() - 1;
+    typo[last] = if typo[last] == b'0' { b'1' } else { b'0' };
+    assert_eq!(
+        *String::from_utf8(typo)
+            .unwrap()
+            .parse::<wrap::Share>()
+            .unwrap_err()
+            .kind(),
+        wrap::ErrorKind::ShareInvalid
+    );
+
+    // Invalid thresholds
+    assert!(key.split(0, 5).is_err());
+    assert!(key.split(6, 5).is_err());
+
+    // Shares can be recombined into a profile
+    let _profile = Profile::default().wrap_key_shares(&shares[1..4]).unwrap();
+    assert!(Profile::default().wrap_key_shares(&shares[..1]).is_err());
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn split_wrap_key_provisioning_test() {
+    use yubihsm::{mockhsm::MockHsm, wrap};
+
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let key = wrap::Key::generate_random(0x100, wrap::Algorithm::Aes128Ccm)
+        .label("split key".into())
+        .capabilities(Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED);
+
+    let report = Profile::default()
+        .split_wrap_key(key, 2, 3)
+        .provision(&client)
+        .unwrap();
+
+    let shares = &report.wrap_key_shares[&0x100];
+    assert_eq!(shares.len(), 3);
+    assert_eq!(wrap::Key::combine(&shares[1..]).unwrap().key_len(), 16);
+
+    client
+        .get_object_info(0x100, object::Type::WrapKey)
+        .unwrap();
+
+    // Shares are never serialized
+    let json = report.to_json();
+    assert!(!json.contains(&shares[0].to_string()));
+    assert!(json
+        .parse::<yubihsm::setup::Report>()
+        .unwrap()
+        .wrap_key_shares
+        .is_empty());
+
+    // A key recovered from shares keeps its label
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+    Profile::default()
+        .wrap_key_shares(&shares[..2])
+        .unwrap()
+        .provision(&client)
+        .unwrap();
+    assert_eq!(
+        client
+            .get_object_info(0x100, object::Type::WrapKey)
+            .unwrap()
+            .label,
+        object::Label::from("split key")
+    );
+
+    // An invalid threshold is caught before anything is installed
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+    let key = wrap::Key::generate_random(0x100, wrap::Algorithm::Aes128Ccm);
+    assert!(Profile::default()
+        .split_wrap_key(key, 4, 3)
+        .provision(&client)
+        .is_err());
+    assert!(client
+        .get_object_info(0x100, object::Type::WrapKey)
+        .is_err());
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn profile_config_test() {
+    use std::{env, fs};
+    use yubihsm::setup;
+
+    let key_file = env::temp_dir().join("yubihsm-rs-profile-config-test.key");
+    fs::write(&key_file, format!("{}\n", "42".repeat(32))).unwrap();
+    env::set_var("YUBIHSM_RS_TEST_SIGNER_PASSWORD", "signer password");
+
+    let toml = format!(
+        r#"
+audit = "on"
+
+[[roles]]
+authentication_key_id = 2
+authentication_key_label = "signer"
+authentication_key = {{ env = "YUBIHSM_RS_TEST_SIGNER_PASSWORD" }}
+capabilities = "sign-ecdsa, sign-eddsa"
+domains = "1,2"
+
+[[wrap_keys]]
+id = 0x100
+label = "backup"
+key = {{ file = {:?} }}
+capabilities = "export-wrapped,import-wrapped"
+delegated_capabilities = "all"
+domains = "all"
+
+[[wrap_keys]]
+id = 0x101
+algorithm = "aes128-ccm-wrap"
+split = {{ threshold = 2, shares = 3 }}
+capabilities = "export-wrapped"
+domains = "none"
+"#,
+        key_file
+    );
+
+    let profile = setup::Config::from_toml(&toml).unwrap().resolve().unwrap();
+    let serialized = profile.to_toml().unwrap();
+    assert!(serialized.contains("capabilities = \"sign-ecdsa,sign-eddsa\""));
+    assert!(serialized.contains("YUBIHSM_RS_TEST_SIGNER_PASSWORD"));
+    assert!(!serialized.contains(&"42".repeat(32)));
+    assert_eq!(
+        setup::Config::from_toml(&serialized)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to_toml()
+            .unwrap(),
+        serialized
+    );
+
+    let json = profile.to_json().unwrap();
+    assert_eq!(
+        setup::Config::from_json(&json).unwrap().to_json().unwrap(),
+        json
+    );
+
+    // Schema errors carry line numbers
+    let err = setup::Config::from_toml(&toml.replace("sign-eddsa", "sign-everything")).unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::ProfileInvalid);
+    assert!(err.to_string().contains("line 8"), "{}", err);
+    assert!(err.to_string().contains("sign-everything"), "{}", err);
+
+    let err = setup::Config::from_toml(&toml.replace("audit", "auditing")).unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+
+    // Secrets aren't read until the config is resolved
+    let config = setup::Config::from_toml(&toml.replace("TEST_SIGNER", "TEST_MISSING")).unwrap();
+    let err = config.resolve().unwrap_err();
+    assert!(err.to_string().contains("roles[0]"), "{}", err);
+    assert!(
+        err.to_string().contains("YUBIHSM_RS_TEST_MISSING_PASSWORD"),
+        "{}",
+        err
+    );
+
+    let err =
+        setup::Config::from_toml(&toml.replace("\"backup\"", &format!("{:?}", "x".repeat(41))))
+            .unwrap()
+            .resolve()
+            .unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::LabelInvalid);
+    assert!(err.to_string().contains("wrap_keys[0]"), "{}", err);
+
+    // Secret files are relative to the directory of a profile file
+    let profile_dir = env::temp_dir().join("yubihsm-rs-profile-config-test");
+    fs::create_dir_all(&profile_dir).unwrap();
+    fs::copy(&key_file, profile_dir.join("backup.key")).unwrap();
+
+    let profile_file = profile_dir.join("profile.toml");
+    fs::write(
+        &profile_file,
+        toml.replace(&format!("{:?}", key_file), "\"backup.key\""),
+    )
+    .unwrap();
+
+    setup::Config::load(&profile_file)
+        .unwrap()
+        .resolve()
+        .unwrap();
+    fs::remove_dir_all(profile_dir).unwrap();
+
+    let err = setup::Config::from_json(r#"{ "audit": "sometimes" }"#).unwrap_err();
+    assert!(err.to_string().contains("line 1"), "{}", err);
+
+    // Inline secrets are never serialized
+    let role = Role::new(Credentials::from_password(3, b"password"))
+        .capabilities(Capability::all())
+        .domains(Domain::all());
+    assert!(Profile::default().roles(vec![role]).to_toml().is_err());
+
+    fs::remove_file(key_file).unwrap();
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn objects_provisioning_test() {
+    use yubihsm::{asymmetric, command, hmac, mockhsm::MockHsm, opaque, setup, ssh, AuditOption};
+
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let objects = vec![
+        setup::Object::generate_asymmetric_key(0x200, asymmetric::Algorithm::Ed25519)
+            .label("signer")
+            .capabilities(Capability::SIGN_EDDSA)
+            .domains(Domain::DOM1),
+        setup::Object::put_asymmetric_key(0x201, asymmetric::Algorithm::EcP256, [0x11; 32])
+            .unwrap()
+            .label("imported signer")
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1),
+        setup::Object::generate_hmac_key(0x202, hmac::Algorithm::Sha256)
+            .capabilities(Capability::SIGN_HMAC | Capability::VERIFY_HMAC)
+            .domains(Domain::DOM2),
+        setup::Object::opaque(0x203, opaque::Algorithm::Data, b"opaque data".to_vec())
+            .label("opaque")
+            .capabilities(Capability::empty())
+            .domains(Domain::DOM1),
+        setup::Object::ssh_template(0x204, ssh::Template::from_bytes(vec![0x01; 64]))
+            .domains(Domain::DOM1),
+    ];
+
+    let report = Profile::default()
+        .fips_mode(false)
+        .objects(objects)
+        .command_audit_option(command::Code::SignEddsa, AuditOption::On)
+        .provision(&client)
+        .unwrap();
+
+    assert_eq!(report.objects.len(), 5);
+
+    let signer = &report.objects[0];
+    assert_eq!(
+        signer.handle,
+        object::Handle::new(0x200, object::Type::AsymmetricKey)
+    );
+    assert_eq!(signer.label, "signer");
+    assert!(signer.generated);
+    assert_eq!(
+        signer.public_key.as_ref().unwrap(),
+        &client.get_public_key(0x200).unwrap()
+    );
+
+    assert!(!report.objects[1].generated);
+    assert!(report.objects[1].public_key.is_some());
+    assert!(report.objects[2].public_key.is_none());
+
+    assert_eq!(client.get_opaque(0x203).unwrap(), b"opaque data".to_vec());
+    assert_eq!(
+        client
+            .get_command_audit_option(command::Code::SignEddsa)
+            .unwrap(),
+        AuditOption::On
+    );
+
+    // Created objects (but no key material) are listed in the report
+    let parsed: setup::Report = report.to_json().parse().unwrap();
+    assert_eq!(parsed.objects.len(), 5);
+    assert_eq!(parsed.objects[0].public_key, signer.public_key);
+
+    // Imported keys must have the right length
+    assert!(
+        setup::Object::put_asymmetric_key(0x205, asymmetric::Algorithm::EcP256, [0x11; 31])
+            .is_err()
+    );
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn signed_report_test() {
+    use yubihsm::{asymmetric, command, mockhsm::MockHsm, setup, AuditOption};
+
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let signing_key = setup::Object::generate_asymmetric_key(0x200, asymmetric::Algorithm::EcP256)
+        .label("report signer")
+        .capabilities(Capability::SIGN_ECDSA)
+        .domains(Domain::DOM1);
+
+    let report = Profile::default()
+        .roles(vec![Role::new(Credentials::from_password(2, b"password"))
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1)])
+        .objects(vec![signing_key])
+        .command_audit_option(command::Code::SignEcdsa, AuditOption::On)
+        .report_signing_key(0x200)
+        .provision(&client)
+        .unwrap();
+
+    assert_eq!(report.signature.as_ref().unwrap().key_id, 0x200);
+    assert!(report.objects[1].attestation_certificate.is_some());
+    assert_eq!(report.objects[0].capabilities, Capability::SIGN_ECDSA);
+    assert_eq!(report.audit.as_ref().unwrap().force, AuditOption::Off);
+
+    let stored = setup::Report::load(&client, setup::DEFAULT_REPORT_OBJECT_ID).unwrap();
+    stored.verify(&client, 0x200).unwrap();
+    stored
+        .verify_signature(&client.get_public_key(0x200).unwrap())
+        .unwrap();
+
+    // Reports signed by a key other than the expected one are rejected
+    let err = stored.verify(&client, 0x201).unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::ReportInvalid);
+    assert!(err.to_string().contains("0x0200"), "{}", err);
+
+    // Tampering with the report invalidates the signature
+    let mut forged = stored.clone();
+    forged.objects[0].capabilities = Capability::all();
+    assert_eq!(
+        *forged.verify(&client, 0x200).unwrap_err().kind(),
+        setup::ErrorKind::ReportInvalid
+    );
+
+    // Unsigned reports can't be verified
+    let mut unsigned = stored.clone();
+    unsigned.signature = None;
+    assert!(unsigned.verify(&client, 0x200).is_err());
+
+    // Changes to the device after provisioning are caught
+    client
+        .set_command_audit_option(command::Code::SignEcdsa, AuditOption::Off)
+        .unwrap();
+    let err = stored.verify(&client, 0x200).unwrap_err();
+    assert!(err.to_string().contains("SignEcdsa"), "{}", err);
+
+    client
+        .set_command_audit_option(command::Code::SignEcdsa, AuditOption::On)
+        .unwrap();
+    client
+        .delete_object(2, object::Type::AuthenticationKey)
+        .unwrap();
+    let err = stored.verify(&client, 0x200).unwrap_err();
+    assert!(err.to_string().contains("0x0002"), "{}", err);
+
+    // Only ECDSA keys can sign reports
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+    let ed25519_key = setup::Object::generate_asymmetric_key(0x200, asymmetric::Algorithm::Ed25519)
+        .capabilities(Capability::SIGN_EDDSA)
+        .domains(Domain::DOM1);
+    assert!(Profile::default()
+        .objects(vec![ed25519_key])
+        .report_signing_key(0x200)
+        .provision(&client)
+        .is_err());
+}
+
+/// Devices without the FIPS option can be provisioned and their reports
+/// verified, as long as the profile doesn't set a FIPS mode
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn non_fips_device_provisioning_test() {
+    use yubihsm::{asymmetric, mockhsm::MockHsm, setup};
+
+    let hsm = MockHsm::builder().firmware_version(2, 0, 0).build();
+    let client = crate::open_mockhsm_client(&hsm);
+    assert!(client.get_fips_option().is_err());
+
+    let signing_key = setup::Object::generate_asymmetric_key(0x200, asymmetric::Algorithm::EcP256)
+        .capabilities(Capability::SIGN_ECDSA)
+        .domains(Domain::DOM1);
+
+    let report = Profile::default()
+        .objects(vec![signing_key])
+        .report_signing_key(0x200)
+        .provision(&client)
+        .unwrap();
+
+    assert_eq!(report.audit.as_ref().unwrap().fips, None);
+
+    let stored = setup::Report::load(&client, setup::DEFAULT_REPORT_OBJECT_ID).unwrap();
+    stored.verify(&client, 0x200).unwrap();
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn compliance_audit_test() {
+    use yubihsm::{
+        asymmetric, command, mockhsm::MockHsm, opaque, setup, setup::Violation, AuditOption,
+    };
+
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let profile = Profile::default()
+        .roles(vec![Role::new(Credentials::from_password(2, b"password"))
+            .authentication_key_label("signer")
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1)])
+        .objects(vec![setup::Object::generate_asymmetric_key(
+            0x200,
+            asymmetric::Algorithm::EcP256,
+        )
+        .label("signing key")
+        .capabilities(Capability::SIGN_ECDSA)
+        .domains(Domain::DOM1)])
+        .command_audit_option(command::Code::SignEcdsa, AuditOption::On);
+
+    profile.provision(&client).unwrap();
+
+    // The stored setup report and device attestation objects are allowed,
+    // but the default key is flagged
+    assert_eq!(
+        setup::audit(&client, &profile).unwrap(),
+        vec![Violation::DefaultAuthenticationKeyPresent]
+    );
+
+    // Simulate drift
+    client
+        .put_opaque(
+            0x300,
+            "added later".into(),
+            Domain::DOM1,
+            Capability::empty(),
+            opaque::Algorithm::Data,
+            b"unexpected".to_vec(),
+        )
+        .unwrap();
+    client
+        .delete_object(0x200, object::Type::AsymmetricKey)
+        .unwrap();
+    client
+        .delete_object(2, object::Type::AuthenticationKey)
+        .unwrap();
+    client
+        .put_authentication_key(
+            2,
+            "signer".into(),
+            Domain::DOM1 | Domain::DOM2,
+            Capability::SIGN_ECDSA | Capability::EXPORT_WRAPPED,
+            Capability::empty(),
+            authentication::Algorithm::YubicoAes,
+            authentication::Key::derive_from_password(b"password"),
+        )
+        .unwrap();
+    client
+        .set_command_audit_option(command::Code::SignEcdsa, AuditOption::Off)
+        .unwrap();
+    client.set_force_audit_option(AuditOption::On).unwrap();
+
+    let violations = setup::audit(&client, &profile).unwrap();
+    let signer = object::Handle::new(2, object::Type::AuthenticationKey);
+
+    for violation in [
+        Violation::CapabilitiesMismatch {
+            handle: signer.clone(),
+            expected: Capability::SIGN_ECDSA,
+            actual: Capability::SIGN_ECDSA | Capability::EXPORT_WRAPPED,
+        },
+        Violation::DomainsMismatch {
+            handle: signer,
+            expected: Domain::DOM1,
+            actual: Domain::DOM1 | Domain::DOM2,
+        },
+        Violation::UnexpectedObject {
+            handle: object::Handle::new(0x300, object::Type::Opaque),
+            label: "added later".to_owned(),
+        },
+        Violation::MissingObject {
+            handle: object::Handle::new(0x200, object::Type::AsymmetricKey),
+            label: "signing key".to_owned(),
+        },
+        Violation::ForceAuditMismatch {
+            expected: AuditOption::Off,
+            actual: AuditOption::On,
+        },
+        Violation::CommandAuditMismatch {
+            command: command::Code::SignEcdsa,
+            expected: AuditOption::On,
+            actual: AuditOption::Off,
+        },
+    ] {
+        assert!(violations.contains(&violation), "{:?}", violations);
+    }
+
+    assert_eq!(violations.len(), 7);
+    assert!(violations
+        .iter()
+        .any(|v| v.to_string() == "missing asymmetric-key 0x0200 \"signing key\""));
+
+    // Violations can be serialized for alerting
+    let json = serde_json::to_string(&violations).unwrap();
+    assert!(
+        json.contains("\"violation\":\"unexpected_object\""),
+        "{}",
+        json
+    );
+    assert_eq!(
+        serde_json::from_str::<Vec<Violation>>(&json).unwrap(),
+        violations
+    );
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn profile_objects_config_test() {
+    use std::{env, fs};
+    use yubihsm::{opaque, setup};
+
+    let cert_file = env::temp_dir().join("yubihsm-rs-profile-objects-config-test.der");
+    fs::write(&cert_file, [0x30, 0x82, 0x00, 0x00]).unwrap();
+    env::set_var("YUBIHSM_RS_TEST_HMAC_KEY", "42".repeat(32));
+
+    let toml = format!(
+        r#"
+fips = false
+
+[command_audit]
+sign-ecdsa = "on"
+generate-asymmetric-key = "fix"
+
+[[objects]]
+type = "asymmetric-key"
+id = 0x200
+label = "signer"
+algorithm = "ecp256"
+generate = true
+capabilities = "sign-ecdsa"
+domains = "1"
+
+[[objects]]
+type = "hmac-key"
+id = 0x201
+algorithm = "hmac-sha256"
+key = {{ env = "YUBIHSM_RS_TEST_HMAC_KEY" }}
+capabilities = "sign-hmac"
+domains = "1"
+
+[[objects]]
+type = "opaque"
+id = 0x200
+label = "signer certificate"
+algorithm = "opaque-x509-certificate"
+data = {{ file = {:?} }}
+capabilities = "none"
+domains = "1"
+"#,
+        cert_file
+    );
+
+    let profile = setup::Config::from_toml(&toml).unwrap().resolve().unwrap();
+    let serialized = profile.to_toml().unwrap();
+    assert!(serialized.contains("fips = false"));
+    assert!(serialized.contains("sign-ecdsa = \"on\""));
+    assert!(serialized.contains("generate-asymmetric-key = \"fix\""));
+    assert!(serialized.contains("algorithm = \"opaque-x509-certificate\""));
+    assert!(serialized.contains("YUBIHSM_RS_TEST_HMAC_KEY"));
+    assert!(!serialized.contains(&"42".repeat(32)));
+    assert_eq!(
+        setup::Config::from_toml(&serialized)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to_toml()
+            .unwrap(),
+        serialized
+    );
+
+    // Keys need either `generate = true` or a `key`
+    let err = setup::Config::from_toml(&toml.replace("generate = true", ""))
+        .unwrap()
+        .resolve()
+        .unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::ProfileInvalid);
+    assert!(
+        err.to_string()
+            .contains("asymmetric-key 0x0200 needs either `generate = true` or a `key`"),
+        "{}",
+        err
+    );
+
+    // Algorithms have to match the object type
+    let err = setup::Config::from_toml(&toml.replace("\"hmac-sha256\"", "\"ecp256\""))
+        .unwrap()
+        .resolve()
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("unsupported hmac-key algorithm"),
+        "{}",
+        err
+    );
+
+    assert!(
+        setup::Config::from_toml(&toml.replace("sign-ecdsa = ", "sign-everything = ")).is_err()
+    );
+
+    // Inline contents are never serialized
+    let object = setup::Object::opaque(0x300, opaque::Algorithm::Data, b"inline".to_vec());
+    assert!(Profile::default().objects(vec![object]).to_toml().is_err());
+
+    fs::remove_file(cert_file).unwrap();
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+mod plan {
+    use super::*;
+    use yubihsm::{mockhsm::MockHsm, setup, wrap, AuditOption};
+
+    /// Profile with a role, a wrap key and auditing enabled
+    fn profile(capabilities: Capability) -> Profile {
+        let role = Role::new(Credentials::from_password(2, b"signer password"))
+            .authentication_key_label("signer")
+            .capabilities(capabilities)
+            .domains(Domain::DOM1);
+
+        let wrap_key = wrap::Key::from_bytes(0x100, &[0x42; 16])
+            .unwrap()
+            .label("backup".into())
+            .capabilities(Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED);
+
+        Profile::default()
+            .roles(vec![role])
+            .wrap_keys(vec![wrap_key])
+            .audit_option(AuditOption::On)
+    }
+
+    #[test]
+    fn plan_and_apply_test() {
+        let client = crate::open_mockhsm_client(&MockHsm::new());
+        let profile = profile(Capability::SIGN_ECDSA);
+
+        let plan = setup::plan(&client, &profile).unwrap();
+        assert_eq!(plan.steps.len(), 3);
+        assert!(plan.unchanged.is_empty());
+        assert_eq!(plan.unmanaged.len(), 1);
+        assert_eq!(plan.unmanaged[0].object_id, 1);
+
+        let description = plan.to_string();
+        assert!(description.contains("+ create authentication key 0x0002 \"signer\""));
+        assert!(description.contains("+ create wrap key 0x0100 \"backup\""));
+        assert!(description.contains("~ set force audit: Off -> On"));
+
+        let applied = setup::apply(&client, &plan).unwrap();
+        assert_eq!(applied.steps.len(), 3);
+        assert_eq!(client.get_force_audit_option().unwrap(), AuditOption::On);
+
+        // Applying a plan converges the HSM with the profile
+        let plan = setup::plan(&client, &profile).unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.unchanged.len(), 2);
+
+        // Changed attributes replace the existing key
+        let plan = setup::plan(&client, &profile(Capability::SIGN_EDDSA)).unwrap();
+        assert_eq!(plan.steps.len(), 1);
+        assert!(plan.steps[0]
+            .to_string()
+            .starts_with("~ replace authentication key 0x0002"));
+
+        setup::apply(&client, &plan).unwrap();
+        let info = client
+            .get_object_info(2, object::Type::AuthenticationKey)
+            .unwrap();
+        assert_eq!(info.capabilities, Capability::SIGN_EDDSA);
+    }
+
+    #[test]
+    fn apply_rollback_test() {
+        let client = crate::open_mockhsm_client(&MockHsm::new());
+        let plan = setup::plan(&client, &profile(Capability::SIGN_ECDSA)).unwrap();
+
+        // Create a conflicting wrap key after planning, so the second step fails
+        client
+            .put_wrap_key(
+                0x100,
+                "conflict".into(),
+                Domain::all(),
+                Capability::empty(),
+                Capability::empty(),
+                wrap::Algorithm::Aes128Ccm,
+                vec![0u8; 16],
+            )
+            .unwrap();
+
+        let err = setup::apply(&client, &plan).unwrap_err();
+        assert_eq!(*err.error.kind(), setup::ErrorKind::SetupFailed);
+        assert_eq!((err.step_number, err.step_count), (2, 3));
+        assert_eq!(err.rollback.len(), 1);
+        assert!(err.rollback[0].1.is_ok());
+
+        let message = err.to_string();
+        assert!(message.contains("step 2 of 3"), "{}", message);
+        assert!(
+            message.contains("rolled back: + create authentication key 0x0002"),
+            "{}",
+            message
+        );
+
+        assert!(client
+            .get_object_info(2, object::Type::AuthenticationKey)
+            .is_err());
+        assert_eq!(client.get_force_audit_option().unwrap(), AuditOption::Off);
+    }
+
+    #[test]
+    fn plan_objects_test() {
+        use yubihsm::{asymmetric, command};
+
+        let client = crate::open_mockhsm_client(&MockHsm::new());
+
+        let signer = |label: &str| {
+            setup::Object::generate_asymmetric_key(0x200, asymmetric::Algorithm::EcP256)
+                .label(label)
+                .capabilities(Capability::SIGN_ECDSA)
+                .domains(Domain::DOM1)
+        };
+
+        let profile = Profile::default()
+            .objects(vec![signer("signer")])
+            .command_audit_option(command::Code::SignEcdsa, AuditOption::On);
+
+        let plan = setup::plan(&client, &profile).unwrap();
+        assert_eq!(plan.steps.len(), 2);
+
+        let description = plan.to_string();
+        assert!(description.contains("+ create asymmetric-key 0x0200 \"signer\", generated"));
+        assert!(description.contains("~ set audit for SignEcdsa: Off -> On"));
+
+        let applied = setup::apply(&client, &plan).unwrap();
+        assert_eq!(applied.objects.len(), 1);
+        assert!(applied.objects[0].public_key.is_some());
+        assert!(setup::plan(&client, &profile).unwrap().is_empty());
+
+        // Relabeling an object replaces it
+        let profile = profile.objects(vec![signer("new signer")]);
+        let plan = setup::plan(&client, &profile).unwrap();
+        assert_eq!(plan.steps.len(), 1);
+        assert!(plan.steps[0]
+            .to_string()
+            .starts_with("~ replace asymmetric-key 0x0200 \"new signer\""));
+
+        setup::apply(&client, &plan).unwrap();
+        assert!(setup::plan(&client, &profile).unwrap().is_empty());
+    }
+
+    #[test]
+    fn apply_without_attestation_key_test() {
+        use yubihsm::asymmetric;
+
+        let client = crate::open_mockhsm_client(&MockHsm::new());
+        client
+            .delete_object(0, object::Type::AsymmetricKey)
+            .unwrap();
+
+        // Keys are still created when they can't be attested
+        let profile = Profile::default().objects(vec![setup::Object::generate_asymmetric_key(
+            0x200,
+            asymmetric::Algorithm::EcP256,
+        )
+        .label("signer")
+        .capabilities(Capability::SIGN_ECDSA)
+        .domains(Domain::DOM1)]);
+
+        let applied = setup::apply(&client, &setup::plan(&client, &profile).unwrap()).unwrap();
+        assert!(applied.objects[0].attestation_certificate.is_none());
+        assert!(client.get_public_key(0x200).is_ok());
+    }
+
+    #[test]
+    fn apply_failed_replacement_test() {
+        use yubihsm::asymmetric;
+
+        let client = crate::open_mockhsm_client(&MockHsm::new());
+
+        let signer = |algorithm| {
+            setup::Object::generate_asymmetric_key(0x200, algorithm)
+                .label("signer")
+                .capabilities(Capability::SIGN_ECDSA)
+                .domains(Domain::DOM1)
+        };
+
+        let profile = Profile::default().objects(vec![signer(asymmetric::Algorithm::EcP256)]);
+        setup::apply(&client, &setup::plan(&client, &profile).unwrap()).unwrap();
+
+        // The MockHsm can't generate Brainpool keys, so the replacement fails
+        // after the existing key has been deleted
+        let profile = profile.objects(vec![signer(asymmetric::Algorithm::EcBp256)]);
+        let plan = setup::plan(&client, &profile).unwrap();
+        let err = setup::apply(&client, &plan).unwrap_err();
+
+        let message = err.to_string();
+        assert!(
+            message.contains("NOT restored: asymmetric-key 0x0200 \"signer\""),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn apply_failure_keeps_wrap_key_shares_test() {
+        use yubihsm::asymmetric;
+
+        let client = crate::open_mockhsm_client(&MockHsm::new());
+
+        client
+            .put_wrap_key(
+                0x100,
+                "old backup".into(),
+                Domain::all(),
+                Capability::empty(),
+                Capability::empty(),
+                wrap::Algorithm::Aes128Ccm,
+                vec![0u8; 16],
+            )
+            .unwrap();
+
+        // The wrap key is replaced, then generating the Brainpool key fails
+        // (the MockHsm doesn't support it) and the replacement can't be
+        // rolled back
+        let key = wrap::Key::generate_random(0x100, wrap::Algorithm::Aes128Ccm)
+            .label("backup".into())
+            .capabilities(Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED);
+
+        let profile = Profile::default().split_wrap_key(key, 2, 3).objects(vec![
+            setup::Object::generate_asymmetric_key(0x200, asymmetric::Algorithm::EcBp256),
+        ]);
+
+        let plan = setup::plan(&client, &profile).unwrap();
+        let err = setup::apply(&client, &plan).unwrap_err();
+        assert_eq!(err.rollback.len(), 1);
+        assert!(err.rollback[0].1.is_err());
+
+        let info = client
+            .get_object_info(0x100, object::Type::WrapKey)
+            .unwrap();
+        assert_eq!(info.label, object::Label::from("backup"));
+
+        // The shares of the wrap key left in the HSM are returned
+        let shares = &err.applied.wrap_key_shares[&0x100];
+        assert_eq!(shares.len(), 3);
+        wrap::Key::combine(&shares[..2]).unwrap();
+    }
+
+    #[test]
+    fn apply_session_key_test() {
+        let client = crate::open_mockhsm_client(&MockHsm::new());
+
+        // Changing the default authentication key would replace the key the
+        // client is authenticated with
+        let profile = Profile::default().roles(vec![Role::new(Credentials::default())
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1)]);
+
+        let plan = setup::plan(&client, &profile).unwrap();
+        assert!(plan.steps[0]
+            .to_string()
+            .starts_with("~ replace authentication key 0x0001"));
+
+        let err = setup::apply(&client, &plan).unwrap_err();
+        assert!(err.to_string().contains("current session"), "{}", err);
+
+        let info = client
+            .get_object_info(1, object::Type::AuthenticationKey)
+            .unwrap();
+        assert_eq!(info.capabilities, Capability::all());
+    }
+}
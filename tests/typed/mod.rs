@@ -0,0 +1,131 @@
+//! Typed handle tests: objects in a `MockHsm` accessed through typed handles
+
+#![cfg(feature = "mockhsm")]
+
+use sha2::{Sha256, Sha512};
+use yubihsm::{
+    client,
+    ecdsa::NistP256,
+    mockhsm::MockHsm,
+    object::{
+        self,
+        typed::{AsymmetricKey, Ed25519, HmacKey, OpaqueObject, TypedHandle, WrapKey},
+    },
+    opaque, wrap, Capability, Domain,
+};
+
+/// Asymmetric keys are resolved with their algorithm checked
+#[test]
+fn asymmetric_key_test() {
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let key = AsymmetricKey::<NistP256>::generate(
+        client.clone(),
+        0x100,
+        "ecdsa key".into(),
+        Domain::DOM1,
+        Capability::SIGN_ECDSA,
+    )
+    .unwrap();
+
+    assert_eq!(
+        key.handle(),
+        object::Handle::new(0x100, object::Type::AsymmetricKey)
+    );
+    assert!(!key.sign_prehash_raw([0u8; 32]).unwrap().is_empty());
+
+    let resolved =
+        AsymmetricKey::<NistP256>::resolve_by_label(client.clone(), "ecdsa key").unwrap();
+    assert_eq!(resolved.public_key().unwrap(), key.public_key().unwrap());
+
+    let err = AsymmetricKey::<Ed25519>::resolve(client.clone(), 0x100)
+        .err()
+        .unwrap();
+    assert_eq!(*err.kind(), client::ErrorKind::AlgorithmMismatch);
+
+    let key = AsymmetricKey::<Ed25519>::generate(
+        client,
+        0x101,
+        "eddsa key".into(),
+        Domain::DOM1,
+        Capability::SIGN_EDDSA,
+    )
+    .unwrap();
+
+    let signature = key.sign(b"message".as_ref()).unwrap();
+    let signer = key.signer().unwrap();
+    assert_eq!(
+        signature,
+        yubihsm::asymmetric::signature::Signer::sign(&signer, b"message")
+    );
+}
+
+/// HMAC keys sign and verify tags
+#[test]
+fn hmac_key_test() {
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let key = HmacKey::<Sha256>::generate(
+        client.clone(),
+        0x100,
+        "hmac key".into(),
+        Domain::DOM1,
+        Capability::SIGN_HMAC | Capability::VERIFY_HMAC,
+    )
+    .unwrap();
+
+    let tag = key.sign(b"message".as_ref()).unwrap();
+    key.verify(b"message".as_ref(), tag).unwrap();
+
+    let err = HmacKey::<Sha512>::resolve(client, 0x100).err().unwrap();
+    assert_eq!(*err.kind(), client::ErrorKind::AlgorithmMismatch);
+}
+
+/// Wrap keys export and import objects, and opaque objects are read back
+#[test]
+fn wrap_key_test() {
+    let client = crate::open_mockhsm_client(&MockHsm::new());
+
+    let wrap_key = WrapKey::generate(
+        client.clone(),
+        0x100,
+        "wrap key".into(),
+        Domain::DOM1,
+        Capability::EXPORT_WRAPPED
+            | Capability::IMPORT_WRAPPED
+            | Capability::WRAP_DATA
+            | Capability::UNWRAP_DATA,
+        Capability::all(),
+        wrap::Algorithm::Aes256Ccm,
+    )
+    .unwrap();
+
+    let data = OpaqueObject::put(
+        client.clone(),
+        0x101,
+        "certificate".into(),
+        Domain::DOM1,
+        Capability::EXPORTABLE_UNDER_WRAP,
+        opaque::Algorithm::Data,
+        b"opaque data".as_ref(),
+    )
+    .unwrap();
+
+    let message = wrap_key.export(&data).unwrap();
+    data.delete().unwrap();
+
+    let handle = wrap_key.import(message).unwrap();
+    assert_eq!(handle, object::Handle::new(0x101, object::Type::Opaque));
+
+    let data = OpaqueObject::resolve_by_label(client.clone(), "certificate").unwrap();
+    assert_eq!(data.get().unwrap(), b"opaque data");
+
+    let message = wrap_key.wrap_data(b"plaintext".to_vec()).unwrap();
+    assert_eq!(wrap_key.unwrap_data(message).unwrap(), b"plaintext");
+
+    let err = WrapKey::resolve(client, 0x101).err().unwrap();
+    assert_eq!(
+        err.device_error(),
+        Some(yubihsm::device::ErrorKind::ObjectNotFound)
+    );
+}